@@ -0,0 +1,382 @@
+use crate::error::{Error, Result};
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::Engine;
+use nostr::hashes::{sha256::Hash as Sha256Hash, Hash};
+use nostr::{EventBuilder, JsonUtil, Keys, Kind, Tag, TagKind, Tags, Timestamp};
+use serde::Deserialize;
+use std::io::Read;
+use std::path::Path;
+
+/// Largest blob we'll upload or download.
+pub const MAX_ATTACHMENT_SIZE: u64 = 100 * 1024 * 1024;
+
+/// Urls handed out by [`LocalBlobServer`].
+#[cfg(test)]
+const LOCAL_URL_PREFIX: &str = "local:";
+
+/// Kind of the authorization event Blossom servers expect.
+const BLOSSOM_AUTH_KIND: u16 = 24242;
+
+/// A file attached to a mail, referenced from the rumor through an `imeta` tag.
+///
+/// The blob on the server is encrypted with a key only found in the rumor, so the server never
+/// sees the file itself.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attachment {
+    pub url: String,
+    pub name: String,
+    pub mime: String,
+    /// Size of the decrypted file.
+    pub size: u64,
+    /// Hex sha256 of the encrypted blob, which is what the server addresses it by.
+    pub sha256: String,
+    /// Hex sha256 of the decrypted file.
+    pub original_sha256: String,
+    pub key: String,
+    pub nonce: String,
+}
+
+impl Attachment {
+    pub fn to_tag(&self) -> Tag {
+        Tag::custom(
+            TagKind::custom("imeta"),
+            vec![
+                format!("url {}", self.url),
+                format!("name {}", self.name),
+                format!("m {}", self.mime),
+                format!("size {}", self.size),
+                format!("x {}", self.sha256),
+                format!("ox {}", self.original_sha256),
+                "encryption-algorithm aes-gcm".to_string(),
+                format!("decryption-key {}", self.key),
+                format!("decryption-nonce {}", self.nonce),
+            ],
+        )
+    }
+
+    fn from_tag(tag: &Tag) -> Option<Self> {
+        let values = tag.as_slice();
+        if values.first().map(String::as_str) != Some("imeta") {
+            return None;
+        }
+
+        let field = |name: &str| {
+            values[1..].iter().find_map(|v| {
+                v.strip_prefix(name)
+                    .and_then(|rest| rest.strip_prefix(' '))
+                    .map(str::to_string)
+            })
+        };
+
+        // only encrypted attachments are ours to open
+        if field("encryption-algorithm")? != "aes-gcm" {
+            return None;
+        }
+
+        Some(Self {
+            url: field("url")?,
+            name: field("name").unwrap_or_else(|| "attachment".to_string()),
+            mime: field("m").unwrap_or_else(|| "application/octet-stream".to_string()),
+            size: field("size").and_then(|s| s.parse().ok()).unwrap_or(0),
+            sha256: field("x")?,
+            original_sha256: field("ox").unwrap_or_default(),
+            key: field("decryption-key")?,
+            nonce: field("decryption-nonce")?,
+        })
+    }
+
+    /// Every attachment referenced by a rumor's tags.
+    pub fn from_tags(tags: &Tags) -> Vec<Self> {
+        tags.iter().filter_map(Self::from_tag).collect()
+    }
+}
+
+/// Somewhere encrypted attachment blobs can be uploaded to.
+pub trait BlobServer: Send {
+    /// Stores the blob and returns the url it can be fetched from.
+    fn upload(&self, keys: &Keys, blob: &[u8], sha256: &str) -> Result<String>;
+}
+
+#[derive(Deserialize)]
+struct BlobDescriptor {
+    url: String,
+    sha256: String,
+}
+
+/// A server speaking the Blossom protocol.
+pub struct BlossomServer {
+    base_url: String,
+}
+
+impl BlossomServer {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into(),
+        }
+    }
+
+    fn authorization(keys: &Keys, sha256: &str) -> Result<String> {
+        let expiration = Timestamp::from(Timestamp::now().as_u64() + 300);
+        let auth_event = EventBuilder::new(Kind::Custom(BLOSSOM_AUTH_KIND), "Upload attachment")
+            .tags(vec![
+                Tag::hashtag("upload"),
+                Tag::custom(TagKind::custom("x"), vec![sha256.to_string()]),
+                Tag::expiration(expiration),
+            ])
+            .sign_with_keys(keys)
+            .map_err(|e| Error::Generic(e.to_string()))?;
+
+        let encoded = base64::engine::general_purpose::STANDARD.encode(auth_event.as_json());
+        Ok(format!("Nostr {}", encoded))
+    }
+}
+
+impl BlobServer for BlossomServer {
+    fn upload(&self, keys: &Keys, blob: &[u8], sha256: &str) -> Result<String> {
+        let url = format!("{}/upload", self.base_url.trim_end_matches('/'));
+        let descriptor: BlobDescriptor = ureq::put(&url)
+            .set("Authorization", &Self::authorization(keys, sha256)?)
+            .set("Content-Type", "application/octet-stream")
+            .send_bytes(blob)
+            .map_err(|e| Error::Http(e.to_string()))?
+            .into_json()?;
+
+        if descriptor.sha256 != sha256 {
+            return Err(Error::Generic(format!(
+                "blob server stored {} instead of {}",
+                descriptor.sha256, sha256
+            )));
+        }
+
+        Ok(descriptor.url)
+    }
+}
+
+/// Keeps blobs in a directory on this machine instead of a real server, for tests.
+#[cfg(test)]
+pub struct LocalBlobServer {
+    dir: std::path::PathBuf,
+}
+
+#[cfg(test)]
+impl LocalBlobServer {
+    pub fn new(dir: impl Into<std::path::PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn get(&self, url: &str) -> Result<Vec<u8>> {
+        let sha256 = url
+            .strip_prefix(LOCAL_URL_PREFIX)
+            .filter(|s| is_sha256(s))
+            .ok_or(Error::DecodeFailed)?;
+
+        Ok(std::fs::read(self.dir.join(sha256))?)
+    }
+}
+
+#[cfg(test)]
+impl BlobServer for LocalBlobServer {
+    fn upload(&self, _keys: &Keys, blob: &[u8], sha256: &str) -> Result<String> {
+        std::fs::create_dir_all(&self.dir)?;
+        std::fs::write(self.dir.join(sha256), blob)?;
+
+        Ok(format!("{}{}", LOCAL_URL_PREFIX, sha256))
+    }
+}
+
+/// The Blossom server configured in preferences. Attachments can't be sent without one, since
+/// recipients need somewhere to fetch them from.
+pub fn blob_server(configured: &str) -> Result<Box<dyn BlobServer>> {
+    if configured.trim().is_empty() {
+        return Err(Error::Generic(
+            "Set an attachment server in settings to send attachments".to_string(),
+        ));
+    }
+
+    Ok(Box::new(BlossomServer::new(configured.trim())))
+}
+
+/// Whether `s` looks like a hex sha256, and so is safe to use as a file name.
+#[cfg(test)]
+fn is_sha256(s: &str) -> bool {
+    s.len() == 64 && s.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(s: &str) -> Result<Vec<u8>> {
+    if s.len() % 2 != 0 || !s.is_ascii() {
+        return Err(Error::DecodeFailed);
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).map_err(|_| Error::DecodeFailed))
+        .collect()
+}
+
+/// Best guess at a file's mime type from its extension.
+pub fn guess_mime(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+
+    match extension.as_deref() {
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("svg") => "image/svg+xml",
+        Some("pdf") => "application/pdf",
+        Some("txt") => "text/plain",
+        Some("md") => "text/markdown",
+        Some("zip") => "application/zip",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Encrypts the file at `path` with a fresh key and uploads it.
+pub fn upload_file(server: &dyn BlobServer, keys: &Keys, path: &Path) -> Result<Attachment> {
+    if std::fs::metadata(path)?.len() > MAX_ATTACHMENT_SIZE {
+        return Err(Error::Generic(format!("{} is too large", path.display())));
+    }
+    let plaintext = std::fs::read(path)?;
+
+    let key: [u8; 32] = rand::random();
+    let nonce: [u8; 12] = rand::random();
+    let blob = Aes256Gcm::new_from_slice(&key)
+        .expect("32 bytes is a valid key")
+        .encrypt(Nonce::from_slice(&nonce), plaintext.as_slice())
+        .map_err(|_| Error::Encryption)?;
+
+    let sha256 = Sha256Hash::hash(&blob).to_string();
+    let url = server.upload(keys, &blob, &sha256)?;
+
+    Ok(Attachment {
+        url,
+        name: path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "attachment".to_string()),
+        mime: guess_mime(path).to_string(),
+        size: plaintext.len() as u64,
+        sha256,
+        original_sha256: Sha256Hash::hash(&plaintext).to_string(),
+        key: to_hex(&key),
+        nonce: to_hex(&nonce),
+    })
+}
+
+/// Fetches an attachment, checks it against the hashes in the rumor and decrypts it.
+pub fn download(attachment: &Attachment) -> Result<Vec<u8>> {
+    let mut blob = Vec::new();
+    ureq::get(&attachment.url)
+        .call()
+        .map_err(|e| Error::Http(e.to_string()))?
+        .into_reader()
+        .take(MAX_ATTACHMENT_SIZE)
+        .read_to_end(&mut blob)?;
+
+    open(attachment, &blob)
+}
+
+/// Checks a fetched blob against the hashes in the rumor and decrypts it.
+fn open(attachment: &Attachment, blob: &[u8]) -> Result<Vec<u8>> {
+    if Sha256Hash::hash(&blob).to_string() != attachment.sha256 {
+        return Err(Error::Generic(format!(
+            "{} does not match its hash",
+            attachment.name
+        )));
+    }
+
+    let key = from_hex(&attachment.key)?;
+    let nonce = from_hex(&attachment.nonce)?;
+    if nonce.len() != 12 {
+        return Err(Error::DecodeFailed);
+    }
+    let plaintext = Aes256Gcm::new_from_slice(&key)
+        .map_err(|_| Error::Encryption)?
+        .decrypt(Nonce::from_slice(&nonce), blob)
+        .map_err(|_| Error::Encryption)?;
+
+    if !attachment.original_sha256.is_empty()
+        && Sha256Hash::hash(&plaintext).to_string() != attachment.original_sha256
+    {
+        return Err(Error::Generic(format!(
+            "decrypted {} does not match its hash",
+            attachment.name
+        )));
+    }
+
+    Ok(plaintext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A fresh directory for one test's blobs.
+    fn blob_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hoot-{}-{}", name, rand::random::<u64>()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn upload(dir: &Path, contents: &[u8]) -> (LocalBlobServer, Attachment) {
+        let path = dir.join("notes.txt");
+        std::fs::write(&path, contents).unwrap();
+        let server = LocalBlobServer::new(dir.join("blobs"));
+        let attachment = upload_file(&server, &Keys::generate(), &path).unwrap();
+
+        (server, attachment)
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = blob_dir("round-trip");
+        let (server, attachment) = upload(&dir, b"meeting at noon");
+
+        assert_eq!(attachment.name, "notes.txt");
+        assert_eq!(attachment.mime, "text/plain");
+        assert_eq!(attachment.size, 15);
+
+        let blob = server.get(&attachment.url).unwrap();
+        assert_ne!(blob, b"meeting at noon", "the server only sees ciphertext");
+        assert_eq!(open(&attachment, &blob).unwrap(), b"meeting at noon");
+
+        // the tag carries everything needed to open it again
+        let tags = Tags::new(vec![attachment.to_tag()]);
+        assert_eq!(Attachment::from_tags(&tags), vec![attachment]);
+    }
+
+    #[test]
+    fn tampered_blob_is_refused() {
+        let dir = blob_dir("tampered");
+        let (server, attachment) = upload(&dir, b"meeting at noon");
+
+        let mut blob = server.get(&attachment.url).unwrap();
+        blob[0] ^= 1;
+        assert!(open(&attachment, &blob).is_err());
+    }
+
+    #[test]
+    fn wrong_hash_is_refused() {
+        let dir = blob_dir("wrong-hash");
+        let (server, mut attachment) = upload(&dir, b"meeting at noon");
+        let blob = server.get(&attachment.url).unwrap();
+
+        attachment.original_sha256 = Sha256Hash::hash(b"something else").to_string();
+        assert!(open(&attachment, &blob).is_err());
+    }
+
+    #[test]
+    fn local_urls_must_be_hashes() {
+        let server = LocalBlobServer::new(blob_dir("traversal"));
+        assert!(server.get("local:../../etc/passwd").is_err());
+        assert!(server.get("local:abc").is_err());
+    }
+}
@@ -0,0 +1,172 @@
+use crate::error::Result;
+use crate::local_store::LocalStore;
+use crate::profile::Profile;
+use nostr::{Event, Keys, Kind, PublicKey, TagKind, ToBech32};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::error;
+
+const CONTACTS_NAMESPACE: &str = "contacts";
+
+/// Someone in an account's address book.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Contact {
+    pub pubkey: PublicKey,
+    pub name: String,
+    #[serde(default)]
+    pub nip05: String,
+    #[serde(default)]
+    pub notes: String,
+    /// Relays this person would like to be reached on.
+    #[serde(default)]
+    pub relays: Vec<String>,
+}
+
+impl Contact {
+    pub fn new(pubkey: PublicKey) -> Self {
+        Self {
+            pubkey,
+            name: String::new(),
+            nip05: String::new(),
+            notes: String::new(),
+            relays: Vec::new(),
+        }
+    }
+
+    /// Whether `query` matches this contact's name, NIP-05 or npub, ignoring case.
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        self.name.to_lowercase().contains(&query)
+            || self.nip05.to_lowercase().contains(&query)
+            || self.pubkey.to_bech32().unwrap().starts_with(&query)
+    }
+}
+
+/// Address books for every loaded account, mirrored to the encrypted [`LocalStore`].
+#[derive(Default)]
+pub struct ContactStore {
+    contacts: HashMap<PublicKey, Vec<Contact>>,
+}
+
+impl ContactStore {
+    pub fn load(&mut self, store: &LocalStore, accounts: &[Keys]) {
+        self.contacts.clear();
+        for keys in accounts {
+            let names = match store.list(&keys.public_key(), CONTACTS_NAMESPACE) {
+                Ok(n) => n,
+                Err(e) => {
+                    error!("could not list contacts: {}", e);
+                    continue;
+                }
+            };
+
+            let mut contacts = Vec::new();
+            for name in names {
+                match store.read::<Contact>(keys, CONTACTS_NAMESPACE, &name) {
+                    Ok(Some(contact)) => contacts.push(contact),
+                    Ok(None) => {}
+                    Err(e) => error!("could not read contact {}: {}", name, e),
+                }
+            }
+            Self::sort(&mut contacts);
+            self.contacts.insert(keys.public_key(), contacts);
+        }
+    }
+
+    fn sort(contacts: &mut [Contact]) {
+        contacts.sort_by_key(|c| c.name.to_lowercase());
+    }
+
+    /// The address book of one account, sorted by name.
+    pub fn for_account(&self, account: &PublicKey) -> &[Contact] {
+        self.contacts.get(account).map_or(&[], Vec::as_slice)
+    }
+
+    /// Whether any of our accounts has `pubkey` in its address book.
+    pub fn contains(&self, pubkey: &PublicKey) -> bool {
+        self.contacts
+            .values()
+            .flatten()
+            .any(|contact| contact.pubkey == *pubkey)
+    }
+
+    /// Every contact across all accounts, each person listed once.
+    pub fn all(&self) -> Vec<Contact> {
+        let mut all: Vec<Contact> = Vec::new();
+        for contact in self.contacts.values().flatten() {
+            if !all.iter().any(|c| c.pubkey == contact.pubkey) {
+                all.push(contact.clone());
+            }
+        }
+        Self::sort(&mut all);
+
+        all
+    }
+
+    /// Adds the contact to `keys`' address book, replacing any entry for the same person.
+    pub fn save(&mut self, store: &LocalStore, keys: &Keys, contact: Contact) -> Result<()> {
+        store.write(keys, CONTACTS_NAMESPACE, &contact.pubkey.to_hex(), &contact)?;
+
+        let contacts = self.contacts.entry(keys.public_key()).or_default();
+        contacts.retain(|c| c.pubkey != contact.pubkey);
+        contacts.push(contact);
+        Self::sort(contacts);
+
+        Ok(())
+    }
+
+    pub fn remove(&mut self, store: &LocalStore, keys: &Keys, pubkey: &PublicKey) -> Result<()> {
+        store.remove(&keys.public_key(), CONTACTS_NAMESPACE, &pubkey.to_hex())?;
+        if let Some(contacts) = self.contacts.get_mut(&keys.public_key()) {
+            contacts.retain(|c| c.pubkey != *pubkey);
+        }
+
+        Ok(())
+    }
+
+    /// Adds everyone in a kind 3 follow list published by `keys` who isn't a contact yet.
+    /// Names and NIP-05s come from petnames or whatever profiles nostrdb already has.
+    /// Returns how many contacts were added.
+    pub fn import_follows(
+        &mut self,
+        store: &LocalStore,
+        ndb: &nostrdb::Ndb,
+        keys: &Keys,
+        follows: &Event,
+    ) -> Result<usize> {
+        if follows.kind != Kind::ContactList || follows.pubkey != keys.public_key() {
+            return Ok(0);
+        }
+
+        let mut added = 0;
+        for tag in follows.tags.iter().filter(|t| t.kind() == TagKind::p()) {
+            let values = tag.as_slice();
+            let Some(pubkey) = values.get(1).and_then(|v| PublicKey::from_hex(v).ok()) else {
+                continue;
+            };
+            let known = self
+                .for_account(&keys.public_key())
+                .iter()
+                .any(|c| c.pubkey == pubkey);
+            if known {
+                continue;
+            }
+
+            let profile = Profile::load(ndb, &pubkey).unwrap_or_default();
+            let petname = values.get(3).filter(|v| !v.is_empty()).cloned();
+            let mut contact = Contact::new(pubkey);
+            contact.name = petname
+                .or_else(|| profile.best_name().map(str::to_string))
+                .unwrap_or_default();
+            contact.nip05 = profile.nip05.unwrap_or_default();
+            if let Some(relay) = values.get(2).filter(|v| !v.is_empty()) {
+                contact.relays.push(relay.clone());
+            }
+
+            self.save(store, keys, contact)?;
+            added += 1;
+        }
+
+        Ok(added)
+    }
+}
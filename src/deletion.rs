@@ -0,0 +1,223 @@
+use crate::error::{Error, Result};
+use crate::local_store::LocalStore;
+use crate::mail_event::MAIL_EVENT_KIND;
+use crate::nip11::Nip11Cache;
+use crate::relay::RelayPool;
+use nostr::{Event, EventBuilder, EventId, Keys, Kind, PublicKey, Tag, TagKind};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tracing::error;
+
+const DELETION_NAMESPACE: &str = "deletion";
+const PENDING_FILE: &str = "pending";
+
+/// How long to wait before asking a relay again after it couldn't be asked.
+const RETRY_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// What became of a deletion request at one relay.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RelayDeletion {
+    /// The request went out; the relay's answer arrives as an OK for `event`. `auth_error`
+    /// says why authenticating first failed, if it did.
+    Sent {
+        event: EventId,
+        auth_error: Option<String>,
+    },
+    /// Not sent, and why.
+    Skipped(String),
+}
+
+/// The deletion requests sent for one account's messages, relay by relay.
+#[derive(Debug, Clone)]
+pub struct DeletionReport {
+    pub account: PublicKey,
+    pub messages: usize,
+    pub relays: Vec<(String, RelayDeletion)>,
+}
+
+/// A NIP-09 deletion request for gift wraps addressed to `keys`.
+///
+/// The wraps are signed by throwaway keys, so relays can't match this against the author the
+/// way NIP-09 normally works. The ones that honor it check that we've authenticated as the
+/// wrap's `p` tag instead.
+fn deletion_event(keys: &Keys, wrap_ids: &[EventId]) -> Result<Event> {
+    let mut tags: Vec<Tag> = wrap_ids.iter().map(|id| Tag::event(*id)).collect();
+    tags.push(Tag::custom(TagKind::k(), vec![MAIL_EVENT_KIND.to_string()]));
+
+    EventBuilder::new(Kind::EventDeletion, "deleted by recipient")
+        .tags(tags)
+        .sign_with_keys(keys)
+        .map_err(|e| Error::Generic(e.to_string()))
+}
+
+/// Asks the relay at `relay_url` to delete these gift wraps if it advertises NIP-09 and NIP-42,
+/// authenticating as `keys` first. Also returns whether the relay is done with them: either it
+/// got the request or it can't take one at all. Otherwise it's worth asking again later.
+fn request(
+    relays: &mut RelayPool,
+    nip11: &Nip11Cache,
+    keys: &Keys,
+    relay_url: &str,
+    wrap_ids: &[EventId],
+) -> (RelayDeletion, bool) {
+    match nip11.cached(relay_url) {
+        None => {
+            let reason = "Relay information isn't available".to_string();
+            return (RelayDeletion::Skipped(reason), false);
+        }
+        Some(info) if !info.supports(9) => {
+            let reason = "Doesn't support deletion (NIP-09)".to_string();
+            return (RelayDeletion::Skipped(reason), true);
+        }
+        Some(info) if !info.supports(42) => {
+            let reason = "Doesn't support authentication (NIP-42)".to_string();
+            return (RelayDeletion::Skipped(reason), true);
+        }
+        Some(_) => {}
+    }
+
+    let event = match deletion_event(keys, wrap_ids) {
+        Ok(event) => event,
+        Err(e) => {
+            error!("could not build deletion request: {}", e);
+            return (RelayDeletion::Skipped(e.to_string()), false);
+        }
+    };
+    // relays usually send a challenge as soon as we connect; without one we try anyway and
+    // let the relay say whether it wanted auth
+    let auth_error = relays.authenticate(relay_url, keys).err().map(|e| {
+        error!("could not authenticate to {}: {}", relay_url, e);
+        e.to_string()
+    });
+    match relays.publish_to(relay_url, &event) {
+        Ok(()) => {
+            let sent = RelayDeletion::Sent {
+                event: event.id,
+                auth_error,
+            };
+            (sent, true)
+        }
+        Err(e) => (RelayDeletion::Skipped(e.to_string()), false),
+    }
+}
+
+/// A purged gift wrap whose deletion still has to be asked of some relay.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PendingDeletion {
+    wrap_id: EventId,
+    /// Relays that got the request, or can't take one.
+    settled: Vec<String>,
+}
+
+/// Purged gift wraps whose deletion some relay hasn't been asked for yet, per account. Kept in
+/// the [`LocalStore`] so that mail purged before a relay connected still gets its request.
+#[derive(Default)]
+pub struct DeletionQueue {
+    pending: HashMap<PublicKey, Vec<PendingDeletion>>,
+    // relays that couldn't be asked for now, and when that was
+    retry_after: HashMap<String, Instant>,
+}
+
+impl DeletionQueue {
+    pub fn load(&mut self, store: &LocalStore, accounts: &[Keys]) {
+        self.pending.clear();
+        for keys in accounts {
+            match store.read(keys, DELETION_NAMESPACE, PENDING_FILE) {
+                Ok(pending) => {
+                    self.pending
+                        .insert(keys.public_key(), pending.unwrap_or_default());
+                }
+                Err(e) => error!("could not read pending deletions: {}", e),
+            }
+        }
+    }
+
+    /// Queues deletion requests for gift wraps addressed to `keys`.
+    pub fn push(&mut self, store: &LocalStore, keys: &Keys, wrap_ids: &[EventId]) {
+        let pending = self.pending.entry(keys.public_key()).or_default();
+        for id in wrap_ids {
+            if !pending.iter().any(|p| p.wrap_id == *id) {
+                pending.push(PendingDeletion {
+                    wrap_id: *id,
+                    settled: Vec::new(),
+                });
+            }
+        }
+        if let Err(e) = store.write(keys, DELETION_NAMESPACE, PENDING_FILE, pending) {
+            error!("could not save pending deletions: {}", e);
+        }
+    }
+
+    /// Sends what's queued to every connected relay we know the capabilities of, returning a
+    /// report per account that something went out for. A wrap stays queued until every relay
+    /// in the pool got its request or can't take one; relays that couldn't be asked are tried
+    /// again after [`RETRY_INTERVAL`].
+    pub fn flush(
+        &mut self,
+        store: &LocalStore,
+        relays: &mut RelayPool,
+        nip11: &Nip11Cache,
+        accounts: &[Keys],
+    ) -> Vec<DeletionReport> {
+        if self.pending.values().all(Vec::is_empty) {
+            return Vec::new();
+        }
+        let mut urls: Vec<String> = relays
+            .connected_urls()
+            .into_iter()
+            .filter(|url| nip11.checked(url))
+            .filter(|url| {
+                self.retry_after
+                    .get(url)
+                    .is_none_or(|tried| tried.elapsed() >= RETRY_INTERVAL)
+            })
+            .collect();
+        if urls.is_empty() {
+            return Vec::new();
+        }
+        urls.sort();
+
+        let mut reports = Vec::new();
+        for keys in accounts {
+            let Some(pending) = self.pending.get_mut(&keys.public_key()) else {
+                continue;
+            };
+            let mut report = DeletionReport {
+                account: keys.public_key(),
+                messages: pending.len(),
+                relays: Vec::new(),
+            };
+            for url in &urls {
+                let wrap_ids: Vec<EventId> = pending
+                    .iter()
+                    .filter(|p| !p.settled.contains(url))
+                    .map(|p| p.wrap_id)
+                    .collect();
+                if wrap_ids.is_empty() {
+                    continue;
+                }
+                let (deletion, settled) = request(relays, nip11, keys, url, &wrap_ids);
+                if settled {
+                    for p in pending.iter_mut().filter(|p| wrap_ids.contains(&p.wrap_id)) {
+                        p.settled.push(url.clone());
+                    }
+                } else {
+                    self.retry_after.insert(url.clone(), Instant::now());
+                }
+                report.relays.push((url.clone(), deletion));
+            }
+            if report.relays.is_empty() {
+                continue;
+            }
+
+            pending.retain(|p| !relays.relays.keys().all(|url| p.settled.contains(url)));
+            if let Err(e) = store.write(keys, DELETION_NAMESPACE, PENDING_FILE, pending) {
+                error!("could not save pending deletions: {}", e);
+            }
+            reports.push(report);
+        }
+
+        reports
+    }
+}
@@ -0,0 +1,325 @@
+use crate::error::{Error, Result};
+use crate::local_store::LocalStore;
+use crate::message_state::APP_DATA_KIND;
+use crate::relay::RelayPool;
+use nostr::nips::nip44;
+use nostr::{Event, EventBuilder, Keys, Kind, PublicKey, Tag, TagKind, Timestamp};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::error;
+
+const DRAFTS_NAMESPACE: &str = "drafts";
+
+/// Drafts are synced as app data (kind 30078), one event per draft under a `d` tag of this
+/// prefix followed by the draft's id.
+const DRAFT_IDENTIFIER_PREFIX: &str = "hoot/draft/";
+
+/// A half-written mail, saved from a compose window.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Draft {
+    pub id: String,
+    pub subject: String,
+    pub to_field: String,
+    #[serde(default)]
+    pub cc_field: String,
+    pub content: String,
+    pub updated_at: u64,
+    /// Tombstone left behind when a draft is deleted, so stale copies on relays can't bring it
+    /// back.
+    #[serde(default)]
+    pub deleted: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DraftPayload {
+    subject: String,
+    to_field: String,
+    #[serde(default)]
+    cc_field: String,
+    content: String,
+}
+
+impl Draft {
+    pub fn new_id() -> String {
+        format!("{:016x}", rand::random::<u64>())
+    }
+
+    fn identifier(&self) -> String {
+        format!("{}{}", DRAFT_IDENTIFIER_PREFIX, self.id)
+    }
+
+    fn coordinate(&self, author: &PublicKey) -> String {
+        format!(
+            "{}:{}:{}",
+            APP_DATA_KIND,
+            author.to_hex(),
+            self.identifier()
+        )
+    }
+
+    /// Events that sync this draft to our relays. The content is NIP-44 encrypted to ourselves;
+    /// deleted drafts are published with empty content plus a NIP-09 deletion request.
+    pub fn to_events(&self, keys: &Keys) -> Result<Vec<Event>> {
+        let content = if self.deleted {
+            String::new()
+        } else {
+            let payload = serde_json::to_string(&DraftPayload {
+                subject: self.subject.clone(),
+                to_field: self.to_field.clone(),
+                cc_field: self.cc_field.clone(),
+                content: self.content.clone(),
+            })?;
+            nip44::encrypt(
+                keys.secret_key(),
+                &keys.public_key(),
+                payload,
+                nip44::Version::V2,
+            )
+            .map_err(|_| Error::Encryption)?
+        };
+
+        let created_at = Timestamp::from(self.updated_at);
+        let draft_event = EventBuilder::new(Kind::Custom(APP_DATA_KIND), content)
+            .tags(vec![Tag::identifier(self.identifier())])
+            .custom_created_at(created_at)
+            .sign_with_keys(keys)
+            .map_err(|e| Error::Generic(e.to_string()))?;
+
+        let mut events = vec![draft_event];
+        if self.deleted {
+            let deletion = EventBuilder::new(Kind::EventDeletion, "draft deleted")
+                .tags(vec![
+                    Tag::event(events[0].id),
+                    Tag::custom(TagKind::a(), vec![self.coordinate(&keys.public_key())]),
+                ])
+                .custom_created_at(created_at)
+                .sign_with_keys(keys)
+                .map_err(|e| Error::Generic(e.to_string()))?;
+            events.push(deletion);
+        }
+
+        Ok(events)
+    }
+
+    fn from_event(keys: &Keys, event: &Event) -> Result<Self> {
+        let id = event
+            .tags
+            .identifier()
+            .and_then(|d| d.strip_prefix(DRAFT_IDENTIFIER_PREFIX))
+            .ok_or(Error::DecodeFailed)?
+            .to_string();
+        let updated_at = event.created_at.as_u64();
+
+        if event.content.is_empty() {
+            return Ok(Self::tombstone(id, updated_at));
+        }
+
+        let payload = nip44::decrypt(keys.secret_key(), &keys.public_key(), &event.content)
+            .map_err(|_| Error::Encryption)?;
+        let payload: DraftPayload = serde_json::from_str(&payload)?;
+
+        Ok(Self {
+            id,
+            subject: payload.subject,
+            to_field: payload.to_field,
+            cc_field: payload.cc_field,
+            content: payload.content,
+            updated_at,
+            deleted: false,
+        })
+    }
+
+    fn tombstone(id: String, updated_at: u64) -> Self {
+        Self {
+            id,
+            subject: String::new(),
+            to_field: String::new(),
+            cc_field: String::new(),
+            content: String::new(),
+            updated_at,
+            deleted: true,
+        }
+    }
+}
+
+/// Sends a draft's sync events to every connected relay, logging anything that goes wrong.
+pub fn publish(relays: &mut RelayPool, keys: &Keys, draft: &Draft) {
+    let events = match draft.to_events(keys) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("could not build draft events: {}", e);
+            return;
+        }
+    };
+
+    for event in events {
+        if let Err(e) = relays.publish(&event) {
+            error!("could not publish draft: {}", e);
+        }
+    }
+}
+
+/// Drafts for every loaded account, mirrored to the encrypted [`LocalStore`].
+#[derive(Default)]
+pub struct DraftStore {
+    drafts: HashMap<PublicKey, Vec<Draft>>,
+}
+
+impl DraftStore {
+    pub fn load(&mut self, store: &LocalStore, accounts: &[Keys]) {
+        self.drafts.clear();
+        for keys in accounts {
+            let names = match store.list(&keys.public_key(), DRAFTS_NAMESPACE) {
+                Ok(n) => n,
+                Err(e) => {
+                    error!("could not list drafts: {}", e);
+                    continue;
+                }
+            };
+
+            let mut drafts = Vec::new();
+            for name in names {
+                match store.read::<Draft>(keys, DRAFTS_NAMESPACE, &name) {
+                    Ok(Some(draft)) => drafts.push(draft),
+                    Ok(None) => {}
+                    Err(e) => error!("could not read draft {}: {}", name, e),
+                }
+            }
+            drafts.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
+            self.drafts.insert(keys.public_key(), drafts);
+        }
+    }
+
+    /// Every live draft along with the account it belongs to, newest first.
+    pub fn all(&self) -> Vec<(PublicKey, Draft)> {
+        let mut all: Vec<(PublicKey, Draft)> = self
+            .drafts
+            .iter()
+            .flat_map(|(pubkey, drafts)| drafts.iter().map(|d| (*pubkey, d.clone())))
+            .filter(|(_, d)| !d.deleted)
+            .collect();
+        all.sort_by(|a, b| b.1.updated_at.cmp(&a.1.updated_at));
+
+        all
+    }
+
+    /// Whether `account` has a live draft with this id, saved here or synced from elsewhere.
+    pub fn contains(&self, account: &PublicKey, id: &str) -> bool {
+        self.drafts
+            .get(account)
+            .is_some_and(|drafts| drafts.iter().any(|d| d.id == id && !d.deleted))
+    }
+
+    /// Stores the draft under `keys`' account and returns it with its new timestamp. If the
+    /// sending account was switched since the last save, the draft is deleted from the
+    /// accounts out of `accounts` it was under before, and their tombstones are returned too so
+    /// they can be published.
+    pub fn save(
+        &mut self,
+        store: &LocalStore,
+        accounts: &[Keys],
+        keys: &Keys,
+        mut draft: Draft,
+    ) -> Result<(Draft, Vec<(Keys, Draft)>)> {
+        draft.updated_at = Timestamp::now().as_u64();
+
+        let mut tombstones = Vec::new();
+        for previous in accounts
+            .iter()
+            .filter(|k| k.public_key() != keys.public_key())
+        {
+            let saved_there = self
+                .drafts
+                .get(&previous.public_key())
+                .is_some_and(|drafts| drafts.iter().any(|d| d.id == draft.id && !d.deleted));
+            if saved_there {
+                let tombstone = Draft::tombstone(draft.id.clone(), draft.updated_at);
+                self.put(store, previous, tombstone.clone())?;
+                tombstones.push((previous.clone(), tombstone));
+            }
+        }
+
+        self.put(store, keys, draft.clone())?;
+
+        Ok((draft, tombstones))
+    }
+
+    /// Replaces the draft with a tombstone and returns it, ready to be published.
+    pub fn delete(&mut self, store: &LocalStore, keys: &Keys, id: &str) -> Result<Draft> {
+        let tombstone = Draft::tombstone(id.to_string(), Timestamp::now().as_u64());
+        self.put(store, keys, tombstone.clone())?;
+
+        Ok(tombstone)
+    }
+
+    /// Merges a draft event from one of our relays, keeping whichever side was written last.
+    /// Returns the draft if it replaced what we had. App data that isn't a draft is left be.
+    pub fn process_event(
+        &mut self,
+        store: &LocalStore,
+        accounts: &[Keys],
+        event: &Event,
+    ) -> Option<Draft> {
+        let keys = accounts.iter().find(|k| k.public_key() == event.pubkey)?;
+
+        let incoming = if event.kind == Kind::Custom(APP_DATA_KIND) {
+            let is_draft = event
+                .tags
+                .identifier()
+                .is_some_and(|d| d.starts_with(DRAFT_IDENTIFIER_PREFIX));
+            if !is_draft {
+                return None;
+            }
+            match Draft::from_event(keys, event) {
+                Ok(d) => d,
+                Err(e) => {
+                    error!("could not read synced draft {}: {}", event.id, e);
+                    return None;
+                }
+            }
+        } else if event.kind == Kind::EventDeletion {
+            let prefix = format!(
+                "{}:{}:{}",
+                APP_DATA_KIND,
+                keys.public_key().to_hex(),
+                DRAFT_IDENTIFIER_PREFIX
+            );
+            let id = event
+                .tags
+                .iter()
+                .filter(|t| t.kind() == TagKind::a())
+                .filter_map(|t| t.content())
+                .find_map(|c| c.strip_prefix(&prefix))?;
+            Draft::tombstone(id.to_string(), event.created_at.as_u64())
+        } else {
+            return None;
+        };
+
+        let newer = self
+            .drafts
+            .get(&keys.public_key())
+            .and_then(|drafts| drafts.iter().find(|d| d.id == incoming.id))
+            .is_none_or(|local| incoming.updated_at > local.updated_at);
+        if !newer {
+            return None;
+        }
+
+        match self.put(store, keys, incoming.clone()) {
+            Ok(()) => Some(incoming),
+            Err(e) => {
+                error!("could not store synced draft: {}", e);
+                None
+            }
+        }
+    }
+
+    fn put(&mut self, store: &LocalStore, keys: &Keys, draft: Draft) -> Result<()> {
+        store.write(keys, DRAFTS_NAMESPACE, &draft.id, &draft)?;
+
+        let drafts = self.drafts.entry(keys.public_key()).or_default();
+        drafts.retain(|d| d.id != draft.id);
+        drafts.insert(0, draft);
+
+        Ok(())
+    }
+}
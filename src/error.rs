@@ -5,6 +5,9 @@ pub enum Error {
     Generic(String),
     Empty,
     DecodeFailed,
+    Io(std::io::Error),
+    Encryption,
+    Http(String),
 }
 
 impl From<serde_json::Error> for Error {
@@ -13,6 +16,12 @@ impl From<serde_json::Error> for Error {
     }
 }
 
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -21,6 +30,9 @@ impl std::fmt::Display for Error {
             Error::Generic(s) => write!(f, "{}", s),
             Error::Empty => write!(f, "Data was empty"),
             Error::DecodeFailed => write!(f, "Could not decode JSON data."),
+            Error::Io(err) => write!(f, "IO error: {}", err),
+            Error::Encryption => write!(f, "Could not encrypt or decrypt data."),
+            Error::Http(err) => write!(f, "HTTP error: {}", err),
         }
     }
 }
@@ -0,0 +1,145 @@
+use crate::error::{Error, Result};
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Largest response body we'll read from a plain GET.
+const MAX_RESPONSE_SIZE: u64 = 1024 * 1024;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Redirects followed by a [`media_client`].
+const MAX_REDIRECTS: u32 = 5;
+
+/// Fetches documents over HTTP. Lookups go through this so they can be pointed at something
+/// other than the network.
+pub trait HttpClient: Send + Sync {
+    fn get(&self, url: &str) -> Result<Vec<u8>>;
+
+    /// A GET asking for a particular content type, for servers that answer the same URL
+    /// differently depending on it.
+    fn get_accepting(&self, url: &str, _accept: &str) -> Result<Vec<u8>> {
+        self.get(url)
+    }
+}
+
+/// Talks to the real network.
+pub struct UreqClient {
+    agent: ureq::Agent,
+}
+
+impl UreqClient {
+    /// A client that follows at most `redirects` redirects, and fails on any beyond that.
+    pub fn new(redirects: u32) -> Self {
+        let agent = ureq::AgentBuilder::new()
+            .redirects(redirects)
+            .timeout_connect(CONNECT_TIMEOUT)
+            .timeout(REQUEST_TIMEOUT)
+            .build();
+
+        Self { agent }
+    }
+
+    fn fetch(request: ureq::Request) -> Result<Vec<u8>> {
+        let url = request.url().to_string();
+        let response = request.call().map_err(|e| Error::Http(e.to_string()))?;
+        // with redirects turned off, ureq hands 3xx responses back as they are
+        if response.status() >= 300 {
+            return Err(Error::Http(format!(
+                "{}: {} {}",
+                url,
+                response.status(),
+                response.status_text()
+            )));
+        }
+
+        let mut body = Vec::new();
+        response
+            .into_reader()
+            .take(MAX_RESPONSE_SIZE)
+            .read_to_end(&mut body)?;
+
+        Ok(body)
+    }
+}
+
+impl HttpClient for UreqClient {
+    fn get(&self, url: &str) -> Result<Vec<u8>> {
+        Self::fetch(self.agent.get(url))
+    }
+
+    fn get_accepting(&self, url: &str, accept: &str) -> Result<Vec<u8>> {
+        Self::fetch(self.agent.get(url).set("Accept", accept))
+    }
+}
+
+/// Serves files from a directory laid out as `<host>/<path>` instead of going to the network,
+/// so lookups can be tried against made-up servers. Query strings are ignored, and a directory
+/// is served by its `index.json`. A `<path>.redirect` file holding a url stands for a redirect
+/// there.
+pub struct LocalClient {
+    dir: PathBuf,
+    redirects: u32,
+}
+
+impl LocalClient {
+    pub fn new(dir: impl Into<PathBuf>, redirects: u32) -> Self {
+        Self {
+            dir: dir.into(),
+            redirects,
+        }
+    }
+
+    fn fetch(&self, url: &str, redirects: u32) -> Result<Vec<u8>> {
+        let rest = url
+            .strip_prefix("https://")
+            .or_else(|| url.strip_prefix("http://"))
+            .ok_or_else(|| Error::Http(format!("{}: unsupported scheme", url)))?;
+        let path = rest.split(['?', '#']).next().unwrap_or_default();
+        if path.split('/').any(|part| part == "..") {
+            return Err(Error::Http(format!("{}: 404 Not Found", url)));
+        }
+
+        let mut file = self.dir.join(path);
+        if file.is_dir() {
+            file.push("index.json");
+        }
+
+        let mut redirect = file.clone().into_os_string();
+        redirect.push(".redirect");
+        if let Ok(location) = std::fs::read_to_string(redirect) {
+            if redirects == 0 {
+                return Err(Error::Http(format!("{}: 301 Moved Permanently", url)));
+            }
+            return self.fetch(location.trim(), redirects - 1);
+        }
+
+        std::fs::read(file).map_err(|_| Error::Http(format!("{}: 404 Not Found", url)))
+    }
+}
+
+impl HttpClient for LocalClient {
+    fn get(&self, url: &str) -> Result<Vec<u8>> {
+        self.fetch(url, self.redirects)
+    }
+}
+
+fn with_redirects(redirects: u32) -> Arc<dyn HttpClient> {
+    match std::env::var_os("HOOT_HTTP_DIR") {
+        Some(dir) => Arc::new(LocalClient::new(dir, redirects)),
+        None => Arc::new(UreqClient::new(redirects)),
+    }
+}
+
+/// The client lookups should use. It never follows redirects, since NIP-05 and NIP-11
+/// documents must come from the host that was asked. Setting `HOOT_HTTP_DIR` swaps the
+/// network for a [`LocalClient`] serving that directory.
+pub fn client() -> Arc<dyn HttpClient> {
+    with_redirects(0)
+}
+
+/// Like [`client`], but follows a few redirects, for things like profile pictures that are
+/// often served from somewhere else.
+pub fn media_client() -> Arc<dyn HttpClient> {
+    with_redirects(MAX_REDIRECTS)
+}
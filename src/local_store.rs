@@ -0,0 +1,110 @@
+use crate::error::{Error, Result};
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use nostr::hashes::{sha256::Hash as Sha256Hash, Hash};
+use nostr::{Keys, PublicKey};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs;
+use std::path::PathBuf;
+
+const NONCE_LEN: usize = 12;
+
+/// Per-account files kept under the app's storage directory.
+///
+/// Everything written through here is encrypted with a key derived from the account's secret
+/// key, so nothing readable ever touches the disk.
+pub struct LocalStore {
+    root: PathBuf,
+}
+
+impl LocalStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn dir(&self, pubkey: &PublicKey, namespace: &str) -> PathBuf {
+        self.root.join(pubkey.to_hex()).join(namespace)
+    }
+
+    fn cipher(keys: &Keys) -> Aes256Gcm {
+        let mut material = b"hoot-local-store".to_vec();
+        material.extend_from_slice(keys.secret_key().as_secret_bytes());
+        let key = Sha256Hash::hash(&material);
+
+        Aes256Gcm::new_from_slice(&key.to_byte_array()).expect("sha256 output is a valid key")
+    }
+
+    pub fn write<T: Serialize>(
+        &self,
+        keys: &Keys,
+        namespace: &str,
+        name: &str,
+        value: &T,
+    ) -> Result<()> {
+        let plaintext = serde_json::to_vec(value)?;
+        let nonce: [u8; NONCE_LEN] = rand::random();
+        let ciphertext = Self::cipher(keys)
+            .encrypt(Nonce::from_slice(&nonce), plaintext.as_slice())
+            .map_err(|_| Error::Encryption)?;
+
+        let mut data = nonce.to_vec();
+        data.extend(ciphertext);
+
+        let dir = self.dir(&keys.public_key(), namespace);
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join(name), data)?;
+
+        Ok(())
+    }
+
+    pub fn read<T: DeserializeOwned>(
+        &self,
+        keys: &Keys,
+        namespace: &str,
+        name: &str,
+    ) -> Result<Option<T>> {
+        let path = self.dir(&keys.public_key(), namespace).join(name);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let data = fs::read(path)?;
+        if data.len() < NONCE_LEN {
+            return Err(Error::DecodeFailed);
+        }
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        let plaintext = Self::cipher(keys)
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| Error::Encryption)?;
+
+        Ok(Some(serde_json::from_slice(&plaintext)?))
+    }
+
+    /// Names of every file stored for this account under `namespace`.
+    pub fn list(&self, pubkey: &PublicKey, namespace: &str) -> Result<Vec<String>> {
+        let dir = self.dir(pubkey, namespace);
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut names = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            if entry.path().is_file() {
+                names.push(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+
+        Ok(names)
+    }
+
+    pub fn remove(&self, pubkey: &PublicKey, namespace: &str, name: &str) -> Result<()> {
+        let path = self.dir(pubkey, namespace).join(name);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+
+        Ok(())
+    }
+}
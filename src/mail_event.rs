@@ -1,8 +1,97 @@
-use nostr::{Event, EventBuilder, Keys, Kind, PublicKey, Tag, TagKind, TagStandard};
+use crate::attachments::Attachment;
+use crate::error::{Error, Result};
+use nostr::nips::nip13;
+use nostr::nips::nip44;
+use nostr::{
+    Event, EventBuilder, EventId, JsonUtil, Keys, Kind, PublicKey, Tag, TagKind, TagStandard,
+    Timestamp,
+};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use pollster::FutureExt as _;
 
 pub const MAIL_EVENT_KIND: u16 = 1059;
+/// Tag on mail sent by a rule rather than a person, so rules on the other end leave it be.
+pub const AUTO_SUBMITTED: &str = "auto-submitted";
+
+/// Seals and gift wraps are dated up to two days back, as NIP-59 asks, so relays can't
+/// line them up with when the mail was written.
+const TWEAK_SECONDS: u64 = 2 * 24 * 60 * 60;
+
+/// NIP-13 proof of work to do on outgoing mail, in leading zero bits. 0 skips a layer.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Difficulty {
+    pub wrap: u8,
+    pub seal: u8,
+}
+
+impl Difficulty {
+    /// Hashes it takes on average to send to `recipients` people.
+    pub fn expected_attempts(&self, recipients: usize) -> u64 {
+        let layer = |bits: u8| if bits == 0 { 0 } else { 1u64 << bits.min(63) };
+        (layer(self.wrap).saturating_add(layer(self.seal))).saturating_mul(recipients as u64)
+    }
+}
+
+/// Progress of mining on a background thread, shared with whoever is waiting on it.
+#[derive(Debug, Default)]
+pub struct Mining {
+    attempts: AtomicU64,
+    cancelled: AtomicBool,
+}
+
+impl Mining {
+    pub fn attempts(&self) -> u64 {
+        self.attempts.load(Ordering::Relaxed)
+    }
+
+    /// Makes the mining give up at its next attempt.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    fn cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// A nonce tag that gives an event by `pubkey` with these contents `difficulty` leading zero
+/// bits, or no tag at all when `difficulty` is 0. Fails if `mining` gets cancelled first.
+fn mine(
+    pubkey: &PublicKey,
+    created_at: &Timestamp,
+    kind: &Kind,
+    tags: &[Tag],
+    content: &str,
+    difficulty: u8,
+    mining: &Mining,
+) -> Result<Vec<Tag>> {
+    let mut tags = tags.to_vec();
+    if difficulty == 0 {
+        return Ok(tags);
+    }
+
+    tags.push(Tag::pow(0, difficulty));
+    let mut nonce: u128 = 0;
+    loop {
+        if mining.cancelled() {
+            return Err(Error::Generic("proof of work was cancelled".to_string()));
+        }
+        *tags.last_mut().unwrap() = Tag::pow(nonce, difficulty);
+        let id = EventId::new(pubkey, created_at, kind, &tags, content);
+        mining.attempts.fetch_add(1, Ordering::Relaxed);
+        if nip13::get_leading_zero_bits(id.as_bytes()) >= difficulty {
+            return Ok(tags);
+        }
+        nonce += 1;
+    }
+}
+
+/// Somewhere in the last [`TWEAK_SECONDS`].
+fn tweaked_now() -> Timestamp {
+    let now = Timestamp::now().as_u64();
+    Timestamp::from(now - rand::random::<u64>() % TWEAK_SECONDS)
+}
 
 pub struct MailMessage {
     pub to: Vec<PublicKey>,
@@ -10,30 +99,47 @@ pub struct MailMessage {
     pub bcc: Vec<PublicKey>,
     pub subject: String,
     pub content: String,
+    pub attachments: Vec<Attachment>,
+    /// Sent by a rule, see [`AUTO_SUBMITTED`].
+    pub auto_submitted: bool,
 }
 
 impl MailMessage {
-    pub fn to_events(&mut self, sending_keys: &Keys) -> HashMap<PublicKey, Event> {
-        let mut pubkeys_to_send_to: Vec<PublicKey> = Vec::new();
+    /// Everyone the message gets wrapped for.
+    pub fn recipients(&self) -> Vec<PublicKey> {
+        self.to.iter().chain(&self.cc).copied().collect()
+    }
+
+    /// The unsigned mail event that goes inside each gift wrap.
+    fn rumor(&self) -> EventBuilder {
         let mut tags: Vec<Tag> = Vec::new();
 
         for pubkey in &self.to {
             tags.push(Tag::public_key(*pubkey));
-            pubkeys_to_send_to.push(*pubkey);
         }
 
         for pubkey in &self.cc {
             tags.push(Tag::custom(TagKind::p(), vec![pubkey.to_hex().as_str(), "cc"]));
-            pubkeys_to_send_to.push(*pubkey);
         }
 
         tags.push(Tag::from_standardized(TagStandard::Subject(self.subject.clone())));
 
-        let base_event = EventBuilder::new(Kind::Custom(MAIL_EVENT_KIND), &self.content)
-            .tags(tags);
+        for attachment in &self.attachments {
+            tags.push(attachment.to_tag());
+        }
+
+        if self.auto_submitted {
+            tags.push(Tag::custom(TagKind::custom(AUTO_SUBMITTED), vec!["auto-generated"]));
+        }
+
+        EventBuilder::new(Kind::Custom(MAIL_EVENT_KIND), &self.content).tags(tags)
+    }
+
+    pub fn to_events(&mut self, sending_keys: &Keys) -> HashMap<PublicKey, Event> {
+        let base_event = self.rumor();
 
         let mut event_list: HashMap<PublicKey, Event> = HashMap::new();
-        for pubkey in pubkeys_to_send_to {
+        for pubkey in self.recipients() {
             let wrapped_event =
                 EventBuilder::gift_wrap(sending_keys, &pubkey, base_event.clone(), None).block_on().unwrap();
             event_list.insert(pubkey, wrapped_event);
@@ -41,4 +147,76 @@ impl MailMessage {
 
         event_list
     }
+
+    /// Like [`Self::to_events`], but with `difficulty` worth of proof of work on each gift
+    /// wrap and seal, which can take a while. `mining` reports progress and can cancel it.
+    pub fn to_events_with_pow(
+        &self,
+        sending_keys: &Keys,
+        difficulty: Difficulty,
+        mining: &Mining,
+    ) -> Result<HashMap<PublicKey, Event>> {
+        let rumor = self.rumor().build(sending_keys.public_key());
+
+        let mut event_list: HashMap<PublicKey, Event> = HashMap::new();
+        for pubkey in self.recipients() {
+            let seal = EventBuilder::seal(sending_keys, &pubkey, rumor.clone())
+                .block_on()
+                .map_err(|e| Error::Generic(e.to_string()))?;
+            let seal = if difficulty.seal == 0 {
+                seal.sign_with_keys(sending_keys)
+            } else {
+                // re-sign the sealed rumor with a nonce that gives it enough leading zeros
+                let unsigned = seal.build(sending_keys.public_key());
+                let tags = mine(
+                    &unsigned.pubkey,
+                    &unsigned.created_at,
+                    &unsigned.kind,
+                    unsigned.tags.as_slice(),
+                    &unsigned.content,
+                    difficulty.seal,
+                    mining,
+                )?;
+                EventBuilder::new(Kind::Seal, unsigned.content)
+                    .tags(tags)
+                    .custom_created_at(unsigned.created_at)
+                    .sign_with_keys(sending_keys)
+            }
+            .map_err(|e| Error::Generic(e.to_string()))?;
+
+            let wrap = if difficulty.wrap == 0 {
+                EventBuilder::gift_wrap_from_seal(&pubkey, &seal, None)
+                    .map_err(|e| Error::Generic(e.to_string()))?
+            } else {
+                // nostr signs its gift wraps with a throwaway key it keeps to itself, so to
+                // mine one we have to hold that key ourselves
+                let wrap_keys = Keys::generate();
+                let content = nip44::encrypt(
+                    wrap_keys.secret_key(),
+                    &pubkey,
+                    seal.as_json(),
+                    nip44::Version::V2,
+                )
+                .map_err(|_| Error::Encryption)?;
+                let created_at = tweaked_now();
+                let tags = mine(
+                    &wrap_keys.public_key(),
+                    &created_at,
+                    &Kind::GiftWrap,
+                    &[Tag::public_key(pubkey)],
+                    &content,
+                    difficulty.wrap,
+                    mining,
+                )?;
+                EventBuilder::new(Kind::GiftWrap, content)
+                    .tags(tags)
+                    .custom_created_at(created_at)
+                    .sign_with_keys(&wrap_keys)
+                    .map_err(|e| Error::Generic(e.to_string()))?
+            };
+            event_list.insert(pubkey, wrap);
+        }
+
+        Ok(event_list)
+    }
 }
@@ -1,16 +1,34 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // for windows release
 
-use eframe::egui::{self, FontDefinitions, Sense, Vec2b};
+use eframe::egui::{self, FontDefinitions};
 use egui::FontFamily::Proportional;
-use egui_extras::{Column, TableBuilder};
 use std::collections::HashMap;
 use tracing::{debug, error, info, Level};
 
 mod account_manager;
+mod attachments;
+mod contacts;
+mod deletion;
+mod drafts;
 mod error;
+mod http;
 mod keystorage;
+mod local_store;
 mod mail_event;
+mod message_state;
+mod mute;
+mod nip05;
+mod nip11;
+mod preferences;
+mod profile;
 mod relay;
+mod rules;
+mod search;
+mod signature;
+mod spam;
+mod task;
+mod templates;
+mod time_format;
 mod ui;
 
 fn main() -> Result<(), eframe::Error> {
@@ -33,6 +51,7 @@ fn main() -> Result<(), eframe::Error> {
         options,
         Box::new(|cc| {
             let _ = &cc.egui_ctx.set_visuals(egui::Visuals::light());
+            egui_extras::install_image_loaders(&cc.egui_ctx);
             let mut fonts = FontDefinitions::default();
             fonts.font_data.insert(
                 "Inter".to_owned(),
@@ -56,6 +75,7 @@ fn main() -> Result<(), eframe::Error> {
 pub enum Page {
     Inbox,
     Drafts,
+    Contacts,
     Settings,
     // TODO: fix this mess
     Onboarding,
@@ -71,6 +91,13 @@ pub struct HootState {
     pub compose_window: HashMap<egui::Id, ui::compose_window::ComposeWindowState>,
     pub onboarding: ui::onboarding::OnboardingState,
     pub settings: ui::settings::SettingsState,
+    pub attachments: ui::attachments::AttachmentsState,
+    pub markdown: ui::markdown::MarkdownState,
+    pub contacts: ui::contacts::ContactsState,
+    pub inbox: ui::inbox::InboxState,
+    pub mailboxes: ui::mailboxes::MailboxTreeState,
+    pub search: ui::search::SearchBarState,
+    pub notifications: ui::notifications::NotificationsState,
 }
 
 pub struct Hoot {
@@ -81,7 +108,32 @@ pub struct Hoot {
     relays: relay::RelayPool,
     ndb: nostrdb::Ndb,
     events: Vec<nostr::Event>,
+    // what each gift wrap in `events` unwrapped to
+    unwrapped: HashMap<nostr::EventId, nostr::nips::nip59::UnwrappedGift>,
+    profiles: profile::ProfileCache,
     account_manager: account_manager::AccountManager,
+    local_store: local_store::LocalStore,
+    drafts: drafts::DraftStore,
+    contacts: contacts::ContactStore,
+    message_states: message_state::MessageStateStore,
+    mutes: mute::MuteStore,
+    deletions: deletion::DeletionQueue,
+    search: search::SearchIndex,
+    saved_searches: search::SavedSearchHits,
+    rule_log: rules::RuleLog,
+    wot: spam::WebOfTrust,
+    spam: spam::SpamFilter,
+    preferences: preferences::Preferences,
+    // the preferences that are kept encrypted
+    private: preferences::PrivatePreferences,
+    storage_dir: std::path::PathBuf,
+    spell_checker: Box<dyn ui::composer::SpellChecker>,
+    nip05: nip05::Nip05Cache,
+    avatars: ui::profile::AvatarCache,
+    nip11: nip11::Nip11Cache,
+    locale: time_format::Locale,
+    // last time the trash was checked for messages past their retention
+    trash_checked: Option<std::time::Instant>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -90,12 +142,14 @@ enum HootStatus {
     Ready,
 }
 
+const TRASH_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
 fn update_app(app: &mut Hoot, ctx: &egui::Context) {
     #[cfg(feature = "profiling")]
     puffin::profile_function!();
-    let ctx = ctx.clone();
+    let repaint_ctx = ctx.clone();
     let wake_up = move || {
-        ctx.request_repaint();
+        repaint_ctx.request_repaint();
     };
 
     if app.status == HootStatus::Initalizing {
@@ -104,6 +158,24 @@ fn update_app(app: &mut Hoot, ctx: &egui::Context) {
             Ok(..) => {}
             Err(v) => error!("something went wrong trying to load keys: {}", v),
         }
+        app.drafts.load(&app.local_store, &app.account_manager.loaded_keys);
+        app.contacts.load(&app.local_store, &app.account_manager.loaded_keys);
+        app.message_states
+            .load(&app.local_store, &app.account_manager.loaded_keys);
+        app.mutes.load(&app.local_store, &app.account_manager.loaded_keys);
+        app.search.load(&app.local_store, &app.account_manager.loaded_keys);
+        app.private = preferences::PrivatePreferences::load(
+            &app.local_store,
+            &app.account_manager.loaded_keys,
+        );
+        app.saved_searches
+            .refresh(&app.search, &app.private.saved_searches);
+        app.rule_log
+            .load(&app.local_store, &app.account_manager.loaded_keys);
+        app.spam.load(&app.local_store, &app.account_manager.loaded_keys);
+        app.wot.load(&app.ndb, &app.account_manager.loaded_keys);
+        app.deletions
+            .load(&app.local_store, &app.account_manager.loaded_keys);
         let _ = app
             .relays
             .add_url("wss://relay.chakany.systems".to_string(), wake_up.clone());
@@ -116,6 +188,56 @@ fn update_app(app: &mut Hoot, ctx: &egui::Context) {
 
             // TODO: fix error handling
             let _ = app.relays.add_subscription(gw_sub);
+
+            let mut drafts_sub = relay::Subscription::default();
+            let own_pubkeys: Vec<nostr::PublicKey> = app
+                .account_manager
+                .loaded_keys
+                .iter()
+                .map(|keys| keys.public_key())
+                .collect();
+            drafts_sub.filter(
+                nostr::Filter::new()
+                    .kinds([
+                        nostr::Kind::Custom(message_state::APP_DATA_KIND),
+                        nostr::Kind::EventDeletion,
+                    ])
+                    .authors(own_pubkeys),
+            );
+            let _ = app.relays.add_subscription(drafts_sub);
+
+            // our own profiles, so they can be edited without losing fields
+            let mut profile_sub = relay::Subscription::default();
+            profile_sub.filter(
+                nostr::Filter::new()
+                    .kind(nostr::Kind::Metadata)
+                    .authors(app.account_manager.loaded_keys.iter().map(|k| k.public_key())),
+            );
+            let _ = app.relays.add_subscription(profile_sub);
+
+            // read state and folders written by our other devices
+            let mut app_data_sub = relay::Subscription::default();
+            app_data_sub.filter(
+                nostr::Filter::new()
+                    .kind(nostr::Kind::Custom(message_state::APP_DATA_KIND))
+                    .authors(app.account_manager.loaded_keys.iter().map(|k| k.public_key()))
+                    .identifiers(message_state::sync_identifiers()),
+            );
+            let _ = app.relays.add_subscription(app_data_sub);
+
+            // who we've blocked, as set from our other clients
+            app.mutes
+                .subscribe(&mut app.relays, &app.account_manager.loaded_keys);
+
+            // who we follow, and who they follow, decides what counts as spam
+            let mut follows_sub = relay::Subscription::default();
+            follows_sub.filter(
+                nostr::Filter::new()
+                    .kind(nostr::Kind::ContactList)
+                    .authors(app.account_manager.loaded_keys.iter().map(|k| k.public_key())),
+            );
+            let _ = app.relays.add_subscription(follows_sub);
+            app.wot.request(&mut app.relays);
         }
 
         app.status = HootStatus::Ready;
@@ -124,21 +246,54 @@ fn update_app(app: &mut Hoot, ctx: &egui::Context) {
 
     app.relays.keepalive(wake_up);
 
+    // relay information decides things like whether we can ask a relay to delete mail
+    let relay_urls: Vec<String> = app.relays.relays.keys().cloned().collect();
+    for url in relay_urls {
+        app.nip11.get(ctx, &url);
+    }
+    let reports = app.deletions.flush(
+        &app.local_store,
+        &mut app.relays,
+        &app.nip11,
+        &app.account_manager.loaded_keys,
+    );
+    app.state.inbox.deletions.extend(reports);
+
+    // first checked once setup is done, then every so often
+    if app
+        .trash_checked
+        .is_none_or(|checked| checked.elapsed() >= TRASH_CHECK_INTERVAL)
+    {
+        app.empty_trash();
+    }
+
     let new_val = app.relays.try_recv();
-    if new_val.is_some() {
-        info!("{:?}", new_val.clone());
+    if let Some((relay_url, message)) = new_val {
+        info!("{}: {:?}", relay_url, message);
 
-        match relay::RelayMessage::from_json(&new_val.unwrap()) {
-            Ok(v) => process_message(app, &v),
+        match relay::RelayMessage::from_json(&message) {
+            Ok(v) => process_message(app, &relay_url, &v),
             Err(e) => error!("could not decode message sent from relay: {}", e),
         };
     }
 }
 
-fn process_message(app: &mut Hoot, msg: &relay::RelayMessage) {
+fn process_message(app: &mut Hoot, relay_url: &str, msg: &relay::RelayMessage) {
     use relay::RelayMessage::*;
     match msg {
         Event(sub_id, event) => process_event(app, &sub_id, &event),
+        OK(result) => app.relays.handle_command_result(relay_url, result),
+        Auth(challenge) => app.relays.handle_auth(relay_url, challenge),
+        Eose(sub_id) => {
+            app.relays.handle_eose(relay_url, sub_id);
+            app.mutes.handle_eose(
+                &app.local_store,
+                &mut app.relays,
+                &app.account_manager.loaded_keys,
+                sub_id,
+            );
+        }
+        Closed(sub_id, _) => app.relays.handle_closed(relay_url, sub_id),
         _ => {
             // we don't care rn.
         },
@@ -153,6 +308,135 @@ fn process_event(app: &mut Hoot, _sub_id: &str, event: &str) {
     if let Err(err) = app.ndb.process_event(event) {
         error!("error processing event: {}", err);
     }
+
+    // relay sends ["EVENT", <subscription id>, <event JSON>]
+    let event = match serde_json::from_str::<(String, String, nostr::Event)>(event) {
+        Ok((_, _, ev)) => ev,
+        Err(e) => {
+            error!("could not parse event: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = event.verify() {
+        error!("dropping event {} with bad signature: {}", event.id, e);
+        return;
+    }
+
+    // purged mail stays purged, even though relays keep sending it
+    if event.kind == nostr::Kind::Custom(mail_event::MAIL_EVENT_KIND)
+        && !app.unwrapped.contains_key(&event.id)
+        && !app.message_states.get(&event.id).deleted
+    {
+        match app.account_manager.unwrap_gift_wrap(&event) {
+            Ok(gift) => {
+                let keys = message_state::recipient(&event, &app.account_manager.loaded_keys)
+                    .cloned();
+                // blocked senders don't even get their profile looked up
+                let muted = keys
+                    .as_ref()
+                    .is_some_and(|k| app.mutes.is_muted(&k.public_key(), &gift.sender));
+                if muted {
+                    debug!("dropping {} from a muted sender", event.id);
+                    return;
+                }
+                app.profiles
+                    .fetch(&mut app.relays, &app.ndb, [gift.sender]);
+                if let Some(keys) = &keys {
+                    app.search.insert(keys.public_key(), event.id, &gift, |pubkey| {
+                        app.profiles.display_name(&app.ndb, pubkey)
+                    });
+                    app.saved_searches
+                        .ingest(&app.search, &app.private.saved_searches, event.id);
+                }
+                app.unwrapped.insert(event.id, gift);
+                app.events.push(event.clone());
+                if let Some(keys) = &keys {
+                    app.check_spam(keys, &event);
+                    if !app.message_states.get(&event.id).spam {
+                        app.run_rules(keys, event.id);
+                    }
+                }
+            }
+            Err(e) => error!("could not unwrap {}: {}", event.id, e),
+        }
+    }
+
+    app.profiles.process_event(&event);
+    if event.kind == nostr::Kind::Metadata {
+        let name = app.profiles.display_name(&app.ndb, &event.pubkey);
+        if app.search.rename(&event.pubkey, &name) {
+            app.saved_searches
+                .refresh(&app.search, &app.private.saved_searches);
+        }
+    }
+
+    let behind = app.message_states.process_event(
+        &app.local_store,
+        &app.account_manager.loaded_keys,
+        &event,
+    );
+    if let Some((keys, part)) = behind {
+        app.message_states.publish_part(&mut app.relays, keys, part);
+    }
+
+    if event.kind == nostr::Kind::Custom(message_state::APP_DATA_KIND)
+        || event.kind == nostr::Kind::EventDeletion
+    {
+        let merged = app.drafts.process_event(
+            &app.local_store,
+            &app.account_manager.loaded_keys,
+            &event,
+        );
+        if let Some(draft) = merged {
+            // open windows only follow the synced copy while they hold no local edits
+            for window in app.state.compose_window.values_mut() {
+                if window.draft_id == draft.id && !window.has_unsaved_changes() {
+                    window.apply_draft(&draft);
+                }
+            }
+        }
+    }
+
+    let (mutes_changed, behind) =
+        app.mutes
+            .process_event(&app.local_store, &app.account_manager.loaded_keys, &event);
+    if let Some(keys) = behind {
+        app.mutes.publish(&app.local_store, &mut app.relays, keys);
+    }
+    if mutes_changed {
+        app.hide_muted();
+    }
+
+    if app.wot.process_event(&event) {
+        app.wot.request(&mut app.relays);
+        app.recheck_spam();
+    }
+
+    if event.kind == nostr::Kind::ContactList
+        && app.state.contacts.pending_import.remove(&event.pubkey)
+    {
+        let keys = app
+            .account_manager
+            .loaded_keys
+            .iter()
+            .find(|k| k.public_key() == event.pubkey);
+        if let Some(keys) = keys {
+            match app.contacts.import_follows(&app.local_store, &app.ndb, keys, &event) {
+                Ok(added) => {
+                    app.state.contacts.status =
+                        Some(format!("Imported {} contacts from your follow list.", added));
+                    let pubkeys: Vec<nostr::PublicKey> = app
+                        .contacts
+                        .for_account(&event.pubkey)
+                        .iter()
+                        .map(|c| c.pubkey)
+                        .collect();
+                    app.profiles.fetch(&mut app.relays, &app.ndb, pubkeys);
+                }
+                Err(e) => error!("could not import follow list: {}", e),
+            }
+        }
+    }
 }
 
 fn render_app(app: &mut Hoot, ctx: &egui::Context) {
@@ -170,12 +454,13 @@ fn render_app(app: &mut Hoot, ctx: &egui::Context) {
     } else {
         egui::SidePanel::left("Side Navbar").show(ctx, |ui| {
             ui.heading("Hoot");
-            if ui.button("Inbox").clicked() {
-                app.page = Page::Inbox;
-            }
+            ui::mailboxes::MailboxTree::ui(app, ui);
             if ui.button("Drafts").clicked() {
                 app.page = Page::Drafts;
             }
+            if ui.button("Contacts").clicked() {
+                app.page = Page::Contacts;
+            }
             if ui.button("Settings").clicked() {
                 app.page = Page::Settings;
             }
@@ -185,7 +470,7 @@ fn render_app(app: &mut Hoot, ctx: &egui::Context) {
         });
 
         egui::TopBottomPanel::top("Search").show(ctx, |ui| {
-            ui.heading("Search");
+            ui::search::SearchBar::ui(app, ui);
         });
 
         egui::CentralPanel::default().show(ctx, |ui| {
@@ -195,14 +480,20 @@ fn render_app(app: &mut Hoot, ctx: &egui::Context) {
             }
 
             if app.page == Page::Inbox {
-                ui.label("hello there!");
+                ui.horizontal(|ui| {
+                    ui.heading(app.state.inbox.mailbox.title());
+                    if let Some(label) = app.state.inbox.label.clone() {
+                        if ui
+                            .button(format!("🏷 {} ✕", label))
+                            .on_hover_text("Stop filtering by this label")
+                            .clicked()
+                        {
+                            app.state.inbox.label = None;
+                        }
+                    }
+                });
                 if ui.button("Compose").clicked() {
-                    let state = ui::compose_window::ComposeWindowState {
-                        subject: String::new(),
-                        to_field: String::new(),
-                        content: String::new(),
-                        selected_account: None,
-                    };
+                    let state = ui::compose_window::ComposeWindowState::new();
                     app.state
                         .compose_window
                         .insert(egui::Id::new(rand::random::<u32>()), state);
@@ -238,46 +529,21 @@ fn render_app(app: &mut Hoot, ctx: &egui::Context) {
                         .unwrap();
                 }
 
-                ui.label(format!("total events rendered: {}", app.events.len()));
-
-                TableBuilder::new(ui)
-                    .column(Column::auto())
-                    .column(Column::auto())
-                    .column(Column::remainder())
-                    .column(Column::remainder())
-                    .column(Column::remainder())
-                    .striped(true)
-                    .sense(Sense::click())
-                    .auto_shrink(Vec2b { x: false, y: false })
-                    .header(20.0, |_header| {})
-                    .body(|mut body| {
-                        let row_height = 30.0;
-                        let events = app.events.clone();
-                        body.rows(row_height, events.len(), |mut row| {
-                            let event = &events[row.index()];
-                            row.col(|ui| {
-                                ui.checkbox(&mut false, "");
-                            });
-                            row.col(|ui| {
-                                ui.checkbox(&mut false, "");
-                            });
-                            row.col(|ui| {
-                                ui.label(event.pubkey.to_string());
-                            });
-                            row.col(|ui| {
-                                ui.label(event.content.clone());
-                            });
-                            row.col(|ui| {
-                                ui.label("2 minutes ago");
-                            });
-
-                            if row.response().clicked() {
-                                println!("clicked: {}", event.content.clone());
-                                app.focused_post = event.id.to_string();
-                                app.page = Page::Post;
-                            }
-                        });
-                    });
+                ui.horizontal(|ui| {
+                    ui.label(format!("total events rendered: {}", app.events.len()));
+                    ui::inbox::InboxTable::column_menu(app, ui);
+                });
+                ui::inbox::InboxTable::actions(app, ui);
+
+                let results = ui::inbox::InboxTable::ids(app).len();
+                if !app.state.inbox.search.trim().is_empty() {
+                    ui.label(format!("{} results", results));
+                }
+                ui::inbox::InboxTable::ui(app, ui);
+            } else if app.page == Page::Drafts {
+                ui::drafts::DraftsScreen::ui(app, ui);
+            } else if app.page == Page::Contacts {
+                ui::contacts::ContactsScreen::ui(app, ui);
             } else if app.page == Page::Settings {
                 ui.heading("Settings");
                 ui::settings::SettingsScreen::ui(app, ui);
@@ -293,10 +559,61 @@ fn render_app(app: &mut Hoot, ctx: &egui::Context) {
                     .find(|&x| x.id.to_string() == app.focused_post)
                     .expect("event id should be present inside event list");
 
-                let event_to_display = app.account_manager.unwrap_gift_wrap(gift_wrapped_event).expect("we should be able to unwrap an event we recieved");
+                let event_to_display = app
+                    .unwrapped
+                    .get(&gift_wrapped_event.id)
+                    .cloned()
+                    .expect("every event in the list has been unwrapped");
 
                 ui.heading("View Message");
-                ui.label(format!("Content: {}", event_to_display.rumor.content));
+                let wrap_id = gift_wrapped_event.id;
+                let recipient_keys =
+                    message_state::recipient(gift_wrapped_event, &app.account_manager.loaded_keys)
+                        .cloned();
+                let mut not_spam = false;
+                let mut block = false;
+                if app.message_states.get(&wrap_id).spam {
+                    ui.horizontal(|ui| {
+                        ui.colored_label(egui::Color32::YELLOW, "⚠ This message is in Spam.");
+                        not_spam = ui.button("Not Spam").clicked();
+                    });
+                }
+                if ui.button("Reply").clicked() {
+                    // answer from whichever of our accounts the message was wrapped for
+                    let account = message_state::recipient(
+                        gift_wrapped_event,
+                        &app.account_manager.loaded_keys,
+                    )
+                    .cloned();
+                    if let Some(keys) = account {
+                        let signature = app.preferences.signature(&keys.public_key());
+                        let state = ui::compose_window::ComposeWindowState::reply(
+                            keys.clone(),
+                            &event_to_display,
+                            signature,
+                        );
+                        app.state
+                            .compose_window
+                            .insert(egui::Id::new(rand::random::<u32>()), state);
+                    }
+                }
+                block = ui
+                    .button("🚫 Block Sender")
+                    .on_hover_text("Hides their mail, now and from now on, on all your devices")
+                    .clicked();
+                ui.checkbox(&mut app.state.markdown.show_source, "View source");
+                if app.state.markdown.show_source {
+                    ui.add(egui::Label::new(
+                        egui::RichText::new(&event_to_display.rumor.content).monospace(),
+                    ));
+                } else {
+                    ui::markdown::MarkdownView::ui(
+                        ui,
+                        &mut app.state.markdown,
+                        gift_wrapped_event.id,
+                        &event_to_display.rumor.content,
+                    );
+                }
                 ui.label(match &event_to_display.rumor.tags.find(nostr::TagKind::Subject) {
                     Some(s) => match s.content() {
                         Some(c) => format!("Subject: {}", c.to_string()),
@@ -310,10 +627,46 @@ fn render_app(app: &mut Hoot, ctx: &egui::Context) {
                     None => "ID: None".to_string(),
                 });
 
-                ui.label(format!("Author: {}", event_to_display.sender.to_string()));
+                let created_at = event_to_display.rumor.created_at.as_u64();
+                let now = nostr::Timestamp::now().as_u64();
+                ui.label(format!("Date: {}", app.locale.absolute(created_at)))
+                    .on_hover_text(app.locale.relative(created_at, now));
+
+                ui.horizontal(|ui| {
+                    ui.label("From:");
+                    let known = app.knows(&event_to_display.sender);
+                    ui::profile::ProfileView::ui(
+                        ui,
+                        &app.ndb,
+                        &mut app.profiles,
+                        &mut app.nip05,
+                        &mut app.avatars,
+                        &event_to_display.sender,
+                        known,
+                    );
+                    ui.small(profile::short_npub(&event_to_display.sender));
+                });
+
+                let attachments = attachments::Attachment::from_tags(&event_to_display.rumor.tags);
+                ui::attachments::AttachmentsView::ui(app, ui, &attachments);
+
+                if not_spam {
+                    ui::inbox::InboxTable::apply(
+                        app,
+                        &std::collections::HashSet::from([wrap_id]),
+                        message_state::Action::Spam(false),
+                    );
+                }
+                if let Some(keys) = recipient_keys.filter(|_| block) {
+                    app.set_muted(&keys, &event_to_display.sender, true);
+                }
             }
         });
     }
+
+    ui::markdown::MarkdownView::link_confirmation(ctx, &mut app.state.markdown);
+    ui::inbox::InboxTable::deletion_report(app, ctx);
+    ui::notifications::Notifications::ui(app, ctx);
 }
 
 impl Hoot {
@@ -324,6 +677,10 @@ impl Hoot {
 
         let ndb = nostrdb::Ndb::new(storage_dir.to_str().unwrap(), &ndb_config)
             .expect("could not load nostrdb");
+        let preferences = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, preferences::STORAGE_KEY))
+            .unwrap_or_default();
         Self {
             page: Page::Inbox,
             focused_post: "".into(),
@@ -332,9 +689,305 @@ impl Hoot {
             relays: relay::RelayPool::new(),
             ndb,
             events: Vec::new(),
+            unwrapped: HashMap::new(),
+            profiles: profile::ProfileCache::default(),
             account_manager: account_manager::AccountManager::new(),
+            local_store: local_store::LocalStore::new(storage_dir.join("accounts")),
+            drafts: drafts::DraftStore::default(),
+            contacts: contacts::ContactStore::default(),
+            message_states: message_state::MessageStateStore::default(),
+            mutes: mute::MuteStore::default(),
+            deletions: deletion::DeletionQueue::default(),
+            search: search::SearchIndex::default(),
+            saved_searches: search::SavedSearchHits::default(),
+            rule_log: rules::RuleLog::default(),
+            wot: spam::WebOfTrust::default(),
+            spam: spam::SpamFilter::default(),
+            preferences,
+            private: preferences::PrivatePreferences::default(),
+            storage_dir,
+            spell_checker: Box::new(ui::composer::NoSpellCheck),
+            nip05: nip05::Nip05Cache::new(http::client()),
+            avatars: ui::profile::AvatarCache::default(),
+            nip11: nip11::Nip11Cache::new(http::client()),
+            locale: time_format::Locale::from_env(),
+            trash_checked: None,
+        }
+    }
+
+    /// Whether `pubkey` is one of us, a contact or someone we follow, which is who we'll fetch
+    /// profile pictures for without being asked.
+    fn knows(&self, pubkey: &nostr::PublicKey) -> bool {
+        self.contacts.contains(pubkey) || self.wot.distance(pubkey).is_some_and(|d| d <= 1)
+    }
+
+    /// Drops everything we decrypted for these messages.
+    fn forget(&mut self, ids: &[nostr::EventId]) {
+        for id in ids {
+            self.unwrapped.remove(id);
+        }
+        self.events.retain(|e| !ids.contains(&e.id));
+        self.search.remove(ids);
+        self.saved_searches.remove(ids);
+        self.state.inbox.selected.retain(|id| !ids.contains(id));
+        self.state
+            .notifications
+            .pending
+            .retain(|n| !ids.contains(&n.id));
+        if self.page == Page::Post && ids.iter().any(|id| id.to_string() == self.focused_post) {
+            self.page = Page::Inbox;
+        }
+    }
+
+    /// Blocks or unblocks `sender` for `keys`' account and syncs the mute list.
+    fn set_muted(&mut self, keys: &nostr::Keys, sender: &nostr::PublicKey, muted: bool) {
+        if let Err(e) = self.mutes.set(&self.local_store, keys, sender, muted) {
+            error!("could not save mute list: {}", e);
+            return;
+        }
+        self.mutes.publish(&self.local_store, &mut self.relays, keys);
+        if muted {
+            self.hide_muted();
+        }
+    }
+
+    /// Drops mail we already have from senders its account has muted since. Unmuting
+    /// brings it back the next time relays send it.
+    fn hide_muted(&mut self) {
+        let muted: Vec<nostr::EventId> = self
+            .events
+            .iter()
+            .filter(|event| {
+                let keys = message_state::recipient(event, &self.account_manager.loaded_keys);
+                let gift = self.unwrapped.get(&event.id);
+                keys.zip(gift)
+                    .is_some_and(|(k, g)| self.mutes.is_muted(&k.public_key(), &g.sender))
+            })
+            .map(|event| event.id)
+            .collect();
+        if !muted.is_empty() {
+            self.forget(&muted);
+        }
+    }
+
+    /// Queues requests for relays to delete their copies of these messages, one per account
+    /// they were sent to. They go out once relays are connected. Must run before
+    /// [`Self::forget`], which drops the events.
+    fn delete_from_relays(&mut self, ids: &[nostr::EventId]) {
+        let groups = message_state::by_account(
+            &self.events,
+            &self.account_manager.loaded_keys,
+            |id| ids.contains(id),
+        );
+        for (keys, ids) in groups {
+            self.deletions.push(&self.local_store, &keys, &ids);
+        }
+    }
+
+    /// What rules get to see of the message `id`, which came in for `account`.
+    fn incoming(
+        &mut self,
+        account: nostr::PublicKey,
+        id: nostr::EventId,
+    ) -> Option<rules::Incoming> {
+        let gift = self.unwrapped.get(&id)?;
+        let sender_name = self.profiles.display_name(&self.ndb, &gift.sender);
+        let in_contacts = self
+            .contacts
+            .for_account(&account)
+            .iter()
+            .any(|c| c.pubkey == gift.sender);
+
+        Some(rules::Incoming::new(id, account, gift, sender_name, in_contacts))
+    }
+
+    /// The mail we already have that `rule` would match, as "sender: subject" lines.
+    fn preview_rule(&mut self, rule: &rules::Rule) -> Vec<String> {
+        let messages: Vec<(nostr::PublicKey, nostr::EventId)> = self
+            .events
+            .iter()
+            .filter_map(|event| {
+                message_state::recipient(event, &self.account_manager.loaded_keys)
+                    .map(|keys| (keys.public_key(), event.id))
+            })
+            .collect();
+
+        messages
+            .into_iter()
+            .filter_map(|(account, id)| self.incoming(account, id))
+            .filter(|incoming| rule.matches(incoming))
+            .map(|incoming| format!("{}: {}", incoming.sender_name, incoming.subject))
+            .collect()
+    }
+
+    /// Runs the rules in settings on a message that just came in for `keys`, once per message.
+    fn run_rules(&mut self, keys: &nostr::Keys, id: nostr::EventId) {
+        // recorded even without rules, so a rule made later doesn't run on old mail
+        if !self.rule_log.record(keys, id) || self.private.rules.is_empty() {
+            return;
+        }
+        let Some(incoming) = self.incoming(keys.public_key(), id) else {
+            return;
+        };
+        let Some(gift) = self.unwrapped.get(&id).cloned() else {
+            return;
+        };
+        let recent = incoming.recent(nostr::Timestamp::now().as_u64());
+
+        let mut state_actions = Vec::new();
+        let mut outgoing = Vec::new();
+        for rule in self.private.rules.iter().filter(|r| r.matches(&incoming)) {
+            debug!("rule {:?} matched {}", rule.name, id);
+            for action in &rule.actions {
+                match action {
+                    rules::RuleAction::Label(label) => {
+                        state_actions.push(message_state::Action::Label(label.clone()))
+                    }
+                    rules::RuleAction::MoveTo(folder) => {
+                        state_actions.push(message_state::Action::MoveTo(Some(folder.clone())))
+                    }
+                    rules::RuleAction::MarkRead => {
+                        state_actions.push(message_state::Action::MarkRead(true))
+                    }
+                    rules::RuleAction::Star => {
+                        state_actions.push(message_state::Action::Star(true))
+                    }
+                    rules::RuleAction::Notify if recent => {
+                        self.state
+                            .notifications
+                            .pending
+                            .push(ui::notifications::Notification {
+                                id,
+                                sender_name: incoming.sender_name.clone(),
+                                subject: incoming.subject.clone(),
+                            })
+                    }
+                    // never answer a machine, or two clients' rules could reply forever
+                    rules::RuleAction::AutoReply(text) if recent && !incoming.automatic => {
+                        outgoing.push(rules::auto_reply(&gift, text))
+                    }
+                    rules::RuleAction::Forward(to) if recent => {
+                        if let Some(to) = rules::parse_pubkey(to) {
+                            outgoing.push(rules::forward(&gift, &incoming.sender_name, to));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut syncs = Vec::new();
+        for action in state_actions {
+            if let Err(e) = self
+                .message_states
+                .apply(&self.local_store, keys, &[id], &action)
+            {
+                error!("could not apply rule to {}: {}", id, e);
+                continue;
+            }
+            if let Some(sync) = action.sync().filter(|s| !syncs.contains(s)) {
+                syncs.push(sync);
+            }
+        }
+        for sync in syncs {
+            self.message_states.publish(&mut self.relays, keys, sync, &[id]);
+        }
+
+        // better to skip than to risk replying again on every restart
+        if !outgoing.is_empty() {
+            if let Err(e) = self.rule_log.write(&self.local_store, keys) {
+                error!("could not record that rules ran on {}: {}", id, e);
+                return;
+            }
+        }
+        for mut message in outgoing {
+            for event in message.to_events(keys).values() {
+                if let Err(e) = self.relays.publish(event) {
+                    error!("could not send mail from a rule: {}", e);
+                }
+            }
         }
     }
+
+    /// Sends new mail from people we don't trust to Spam. Each message is only checked once,
+    /// so moving it back out sticks.
+    fn check_spam(&mut self, keys: &nostr::Keys, wrap: &nostr::Event) {
+        if !self.preferences.spam_filter {
+            return;
+        }
+        if !self.spam.first_check(keys, wrap.id) {
+            return;
+        }
+        let Some(gift) = self.unwrapped.get(&wrap.id) else {
+            return;
+        };
+
+        let account = keys.public_key();
+        let words = spam::words(gift);
+        let (reputation, spam_probability) = self.spam.score(&account, &gift.sender, &words);
+        let signals = spam::Signals {
+            distance: self.wot.distance(&gift.sender),
+            in_contacts: self
+                .contacts
+                .for_account(&account)
+                .iter()
+                .any(|c| c.pubkey == gift.sender),
+            pow: spam::pow(wrap, gift),
+            reputation,
+            spam_probability,
+        };
+        let (is_spam, reason) = signals.verdict(self.preferences.spam_min_pow);
+        debug!("spam check of {}: {} ({})", wrap.id, is_spam, reason);
+
+        if is_spam {
+            let action = message_state::Action::Spam(true);
+            if let Err(e) = self
+                .message_states
+                .apply(&self.local_store, keys, &[wrap.id], &action)
+            {
+                error!("could not move {} to spam: {}", wrap.id, e);
+            }
+        }
+    }
+
+    /// Takes mail out of Spam that the filter put there before it knew we follow the sender,
+    /// which happens when mail arrives ahead of our contact lists.
+    fn recheck_spam(&mut self) {
+        let trusted: Vec<(nostr::Keys, nostr::EventId)> = self
+            .events
+            .iter()
+            .filter(|event| self.message_states.get(&event.id).spam)
+            .filter_map(|event| {
+                let keys = message_state::recipient(event, &self.account_manager.loaded_keys)?;
+                let gift = self.unwrapped.get(&event.id)?;
+                let followed = self.wot.distance(&gift.sender).is_some_and(|d| d <= 1);
+                (followed && !self.spam.has_feedback(&keys.public_key(), &event.id))
+                    .then(|| (keys.clone(), event.id))
+            })
+            .collect();
+
+        for (keys, id) in trusted {
+            let action = message_state::Action::Spam(false);
+            if let Err(e) = self.message_states.apply(&self.local_store, &keys, &[id], &action) {
+                error!("could not move {} out of spam: {}", id, e);
+            }
+        }
+    }
+
+    /// Purges whatever has been in the trash longer than the retention setting allows.
+    fn empty_trash(&mut self) {
+        let purged = self.message_states.purge_expired(
+            &self.local_store,
+            &self.account_manager.loaded_keys,
+            self.preferences.trash_retention_days,
+        );
+        if !purged.is_empty() {
+            info!("purged {} messages from the trash", purged.len());
+            self.delete_from_relays(&purged);
+            self.forget(&purged);
+        }
+        self.trash_checked = Some(std::time::Instant::now());
+    }
 }
 
 impl eframe::App for Hoot {
@@ -342,6 +995,18 @@ impl eframe::App for Hoot {
         update_app(self, ctx);
         render_app(self, ctx);
     }
+
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        self.private
+            .save(&self.local_store, &self.account_manager.loaded_keys);
+        eframe::set_value(storage, preferences::STORAGE_KEY, &self.preferences);
+        self.search
+            .save(&self.local_store, &self.account_manager.loaded_keys);
+        self.spam
+            .save(&self.local_store, &self.account_manager.loaded_keys);
+        self.rule_log
+            .save(&self.local_store, &self.account_manager.loaded_keys);
+    }
 }
 
 #[cfg(feature = "profiling")]
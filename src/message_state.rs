@@ -0,0 +1,870 @@
+use crate::error::{Error, Result};
+use crate::local_store::LocalStore;
+use crate::relay::RelayPool;
+use nostr::nips::nip44;
+use nostr::{Event, EventBuilder, EventId, Keys, Kind, PublicKey, Tag, Timestamp};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use tracing::error;
+
+const STATE_NAMESPACE: &str = "messages";
+const STATE_FILE: &str = "state";
+const MAILBOXES_FILE: &str = "mailboxes";
+
+/// NIP-78 application data kind used to sync state between our own devices.
+pub const APP_DATA_KIND: u16 = 30078;
+/// Per-message state is spread over this many events, by the first hex digit of message ids.
+const SHARDS: u8 = 16;
+/// Most a NIP-44 payload can hold.
+const MAX_PAYLOAD: usize = 65535;
+
+/// What the user has done with a received message.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MessageState {
+    pub read: bool,
+    /// When `read` last changed, so copies from other devices merge by whichever came last.
+    pub read_at: u64,
+    pub starred: bool,
+    pub archived: bool,
+    /// When the message was moved to the trash, 0 if it isn't there.
+    pub trashed_at: u64,
+    /// Purged for good. The gift wrap is still on relays, so this is what keeps it from coming
+    /// back the next time they send it.
+    pub deleted: bool,
+    /// Folder the message was moved to, `None` for the inbox.
+    pub folder: Option<String>,
+    pub labels: BTreeSet<String>,
+    /// When `folder` or `labels` last changed.
+    pub filed_at: u64,
+    /// In the Spam folder, whether the filter put it there or the user did.
+    pub spam: bool,
+}
+
+impl MessageState {
+    /// Whether the message is still around and not in the trash.
+    pub fn live(&self) -> bool {
+        !self.deleted && self.trashed_at == 0
+    }
+
+    /// Whether the message belongs in the inbox view.
+    pub fn in_inbox(&self) -> bool {
+        self.live() && !self.spam && !self.archived && self.folder.is_none()
+    }
+
+    /// What's kept of a purged message: just enough to know not to show it again.
+    fn tombstone() -> Self {
+        Self {
+            deleted: true,
+            ..Default::default()
+        }
+    }
+}
+
+/// Folders and labels an account has made, whether or not anything is filed under them yet.
+///
+/// Folders nest by name, so `Work/Clients` shows up under `Work`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Mailboxes {
+    pub folders: BTreeSet<String>,
+    pub labels: BTreeSet<String>,
+    updated_at: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MailboxKind {
+    Folder,
+    Label,
+}
+
+/// Which of our app data events a change travels in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sync {
+    ReadState,
+    Folders,
+}
+
+impl Sync {
+    pub const ALL: [Self; 2] = [Self::ReadState, Self::Folders];
+
+    fn identifier(self) -> &'static str {
+        match self {
+            Self::ReadState => "hoot/read-state",
+            Self::Folders => "hoot/folders",
+        }
+    }
+}
+
+/// One of the events a [`Sync`] travels in. Per-message state is split by message id so no
+/// event outgrows what NIP-44 can encrypt, and the folder list goes in an event of its own.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SyncPart {
+    sync: Sync,
+    // `None` for the folder list
+    shard: Option<u8>,
+}
+
+impl SyncPart {
+    fn all() -> Vec<Self> {
+        let mut parts = vec![Self {
+            sync: Sync::Folders,
+            shard: None,
+        }];
+        for sync in Sync::ALL {
+            parts.extend((0..SHARDS).map(|shard| Self {
+                sync,
+                shard: Some(shard),
+            }));
+        }
+
+        parts
+    }
+
+    /// The parts `ids` are kept in, and for [`Sync::Folders`] the folder list too.
+    fn holding(sync: Sync, ids: &[EventId]) -> Vec<Self> {
+        let mut parts = Vec::new();
+        if sync == Sync::Folders {
+            parts.push(Self { sync, shard: None });
+        }
+        for id in ids {
+            let part = Self {
+                sync,
+                shard: Some(shard_of(id)),
+            };
+            if !parts.contains(&part) {
+                parts.push(part);
+            }
+        }
+
+        parts
+    }
+
+    /// The `d` tag of the event.
+    fn identifier(self) -> String {
+        match self.shard {
+            Some(shard) => format!("{}/{:x}", self.sync.identifier(), shard),
+            None => self.sync.identifier().to_string(),
+        }
+    }
+}
+
+/// The `d` tags of every event our state is synced in, for asking relays for them.
+pub fn sync_identifiers() -> Vec<String> {
+    SyncPart::all()
+        .into_iter()
+        .map(SyncPart::identifier)
+        .collect()
+}
+
+fn shard_of(id: &EventId) -> u8 {
+    id.as_bytes()[0] >> 4
+}
+
+/// The newest of `entries` that fit in one event, going by `changed_at`. Past that, older
+/// messages just don't sync.
+fn fit<T: Serialize>(
+    mut entries: Vec<(EventId, T)>,
+    changed_at: impl Fn(&T) -> u64,
+) -> Result<HashMap<EventId, T>> {
+    entries.sort_by_key(|(_, value)| std::cmp::Reverse(changed_at(value)));
+
+    // `["id",value]` is as long as `"id":value,` in the map
+    let mut size = 2;
+    let mut kept = HashMap::new();
+    for (id, value) in entries {
+        size += serde_json::to_string(&(&id, &value))?.len();
+        if size > MAX_PAYLOAD {
+            break;
+        }
+        kept.insert(id, value);
+    }
+
+    Ok(kept)
+}
+
+// where one message is filed, as carried by the folders events
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Filing {
+    folder: Option<String>,
+    labels: BTreeSet<String>,
+    filed_at: u64,
+}
+
+/// Something done to a batch of messages at once.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    MarkRead(bool),
+    Star(bool),
+    Archive,
+    MoveTo(Option<String>),
+    Label(String),
+    Unlabel(String),
+    Trash,
+    /// Takes messages back out of the archive or trash.
+    Restore,
+    /// Deletes trashed messages from this device for good. Their gift wraps stay in nostrdb,
+    /// which can't remove a note; only the decrypted copies go, and a tombstone keeps the
+    /// wraps hidden.
+    Purge,
+    /// Moves messages into or out of Spam.
+    Spam(bool),
+}
+
+impl Action {
+    fn apply(&self, state: &mut MessageState, now: u64) {
+        match self {
+            Self::MarkRead(read) => {
+                state.read = *read;
+                state.read_at = now;
+            }
+            Self::Star(starred) => state.starred = *starred,
+            Self::Archive => state.archived = true,
+            Self::MoveTo(folder) => {
+                state.folder = folder.clone();
+                state.archived = false;
+                state.trashed_at = 0;
+                state.spam = false;
+                state.filed_at = now;
+            }
+            Self::Label(label) => {
+                state.labels.insert(label.clone());
+                state.filed_at = now;
+            }
+            Self::Unlabel(label) => {
+                state.labels.remove(label);
+                state.filed_at = now;
+            }
+            Self::Trash => state.trashed_at = now,
+            Self::Restore => {
+                state.trashed_at = 0;
+                state.archived = false;
+            }
+            Self::Purge => *state = MessageState::tombstone(),
+            Self::Spam(spam) => state.spam = *spam,
+        }
+    }
+
+    /// The event that has to be republished for our other devices to see this.
+    pub fn sync(&self) -> Option<Sync> {
+        match self {
+            Self::MarkRead(_) => Some(Sync::ReadState),
+            Self::MoveTo(_) | Self::Label(_) | Self::Unlabel(_) => Some(Sync::Folders),
+            _ => None,
+        }
+    }
+}
+
+/// The account out of `accounts` a gift wrap was addressed to.
+pub fn recipient<'a>(event: &Event, accounts: &'a [Keys]) -> Option<&'a Keys> {
+    event
+        .tags
+        .public_keys()
+        .find_map(|pk| accounts.iter().find(|k| k.public_key() == *pk))
+}
+
+/// The gift wraps in `events` that `wanted` picks, grouped by the account they were sent to.
+pub fn by_account(
+    events: &[Event],
+    accounts: &[Keys],
+    wanted: impl Fn(&EventId) -> bool,
+) -> Vec<(Keys, Vec<EventId>)> {
+    let mut groups: Vec<(Keys, Vec<EventId>)> = Vec::new();
+    for event in events.iter().filter(|e| wanted(&e.id)) {
+        let Some(keys) = recipient(event, accounts) else {
+            continue;
+        };
+        match groups
+            .iter_mut()
+            .find(|(k, _)| k.public_key() == keys.public_key())
+        {
+            Some((_, ids)) => ids.push(event.id),
+            None => groups.push((keys.clone(), vec![event.id])),
+        }
+    }
+
+    groups
+}
+
+/// Per-message state for every loaded account, mirrored to the encrypted [`LocalStore`].
+#[derive(Default)]
+pub struct MessageStateStore {
+    states: HashMap<PublicKey, HashMap<EventId, MessageState>>,
+    mailboxes: HashMap<PublicKey, Mailboxes>,
+    generation: u64,
+}
+
+impl MessageStateStore {
+    pub fn load(&mut self, store: &LocalStore, accounts: &[Keys]) {
+        self.states.clear();
+        self.mailboxes.clear();
+        self.generation += 1;
+        for keys in accounts {
+            let states = match store.read(keys, STATE_NAMESPACE, STATE_FILE) {
+                Ok(s) => s.unwrap_or_default(),
+                Err(e) => {
+                    error!("could not read message state: {}", e);
+                    HashMap::new()
+                }
+            };
+            self.states.insert(keys.public_key(), states);
+
+            let mailboxes = match store.read(keys, STATE_NAMESPACE, MAILBOXES_FILE) {
+                Ok(m) => m.unwrap_or_default(),
+                Err(e) => {
+                    error!("could not read folders: {}", e);
+                    Mailboxes::default()
+                }
+            };
+            self.mailboxes.insert(keys.public_key(), mailboxes);
+        }
+    }
+
+    // every change is saved right away, so this is also where it's counted
+    fn save(&mut self, store: &LocalStore, keys: &Keys) -> Result<()> {
+        self.generation += 1;
+        let pubkey = keys.public_key();
+        if let Some(states) = self.states.get(&pubkey) {
+            store.write(keys, STATE_NAMESPACE, STATE_FILE, states)?;
+        }
+        if let Some(mailboxes) = self.mailboxes.get(&pubkey) {
+            store.write(keys, STATE_NAMESPACE, MAILBOXES_FILE, mailboxes)?;
+        }
+
+        Ok(())
+    }
+
+    /// Goes up with every change, so views built from message state know when to rebuild.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// State of a message, whichever account it belongs to. Untouched messages get the default.
+    pub fn get(&self, id: &EventId) -> MessageState {
+        self.states
+            .values()
+            .find_map(|states| states.get(id))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Every folder and label across all accounts, including ones only used by messages.
+    pub fn mailboxes(&self) -> Mailboxes {
+        let mut all = Mailboxes::default();
+        for mailboxes in self.mailboxes.values() {
+            all.folders.extend(mailboxes.folders.iter().cloned());
+            all.labels.extend(mailboxes.labels.iter().cloned());
+        }
+        for state in self.states.values().flat_map(|states| states.values()) {
+            all.folders.extend(state.folder.clone());
+            all.labels.extend(state.labels.iter().cloned());
+        }
+
+        all
+    }
+
+    /// Adds a folder or label to `keys`' account.
+    pub fn add_mailbox(
+        &mut self,
+        store: &LocalStore,
+        keys: &Keys,
+        kind: MailboxKind,
+        name: &str,
+    ) -> Result<()> {
+        let mailboxes = self.mailboxes.entry(keys.public_key()).or_default();
+        let names = match kind {
+            MailboxKind::Folder => &mut mailboxes.folders,
+            MailboxKind::Label => &mut mailboxes.labels,
+        };
+        if names.insert(name.to_string()) {
+            mailboxes.updated_at = Timestamp::now().as_u64();
+        }
+
+        self.save(store, keys)
+    }
+
+    /// Removes a folder or label from `keys`' account. Messages in a removed folder go back to
+    /// the inbox, and its subfolders go with it. Returns the messages that were refiled.
+    pub fn remove_mailbox(
+        &mut self,
+        store: &LocalStore,
+        keys: &Keys,
+        kind: MailboxKind,
+        name: &str,
+    ) -> Result<Vec<EventId>> {
+        let now = Timestamp::now().as_u64();
+        let prefix = format!("{}/", name);
+        let removed = |folder: &String| folder == name || folder.starts_with(&prefix);
+
+        let mailboxes = self.mailboxes.entry(keys.public_key()).or_default();
+        match kind {
+            MailboxKind::Folder => mailboxes.folders.retain(|f| !removed(f)),
+            MailboxKind::Label => {
+                mailboxes.labels.remove(name);
+            }
+        }
+        mailboxes.updated_at = now;
+
+        let mut refiled = Vec::new();
+        let states = self.states.entry(keys.public_key()).or_default();
+        for (id, state) in states.iter_mut() {
+            let filed = match kind {
+                MailboxKind::Folder => state.folder.as_ref().is_some_and(removed),
+                MailboxKind::Label => state.labels.remove(name),
+            };
+            if filed {
+                if kind == MailboxKind::Folder {
+                    state.folder = None;
+                }
+                state.filed_at = now;
+                refiled.push(*id);
+            }
+        }
+
+        self.save(store, keys)?;
+        Ok(refiled)
+    }
+
+    /// Applies `action` to the messages in `ids`, all of which belong to `keys`' account, and
+    /// saves the result.
+    pub fn apply(
+        &mut self,
+        store: &LocalStore,
+        keys: &Keys,
+        ids: &[EventId],
+        action: &Action,
+    ) -> Result<()> {
+        let now = Timestamp::now().as_u64();
+        let states = self.states.entry(keys.public_key()).or_default();
+        for id in ids {
+            action.apply(states.entry(*id).or_default(), now);
+        }
+
+        // filing something somewhere new makes that folder or label part of the account
+        let mailboxes = self.mailboxes.entry(keys.public_key()).or_default();
+        let added = match action {
+            Action::MoveTo(Some(folder)) => mailboxes.folders.insert(folder.clone()),
+            Action::Label(label) => mailboxes.labels.insert(label.clone()),
+            _ => false,
+        };
+        if added {
+            mailboxes.updated_at = now;
+        }
+
+        self.save(store, keys)
+    }
+
+    /// Purges everything that has been in the trash for more than `days` days and returns what
+    /// went, so the decrypted copies can be dropped too. Zero days keeps the trash forever.
+    pub fn purge_expired(
+        &mut self,
+        store: &LocalStore,
+        accounts: &[Keys],
+        days: u32,
+    ) -> Vec<EventId> {
+        if days == 0 {
+            return Vec::new();
+        }
+        let cutoff = Timestamp::now()
+            .as_u64()
+            .saturating_sub(u64::from(days) * 24 * 60 * 60);
+
+        let mut purged = Vec::new();
+        for keys in accounts {
+            let Some(states) = self.states.get_mut(&keys.public_key()) else {
+                continue;
+            };
+            let expired: Vec<EventId> = states
+                .iter()
+                .filter(|(_, s)| !s.deleted && s.trashed_at > 0 && s.trashed_at < cutoff)
+                .map(|(id, _)| *id)
+                .collect();
+            if expired.is_empty() {
+                continue;
+            }
+            for id in &expired {
+                states.insert(*id, MessageState::tombstone());
+            }
+            if let Err(e) = self.save(store, keys) {
+                error!("could not save message state: {}", e);
+            }
+            purged.extend(expired);
+        }
+
+        purged
+    }
+
+    /// Number of unread messages out of `ids` that are still in the inbox.
+    pub fn unread(&self, ids: impl IntoIterator<Item = EventId>) -> usize {
+        self.unread_where(ids, MessageState::in_inbox)
+    }
+
+    /// Number of unread messages out of `ids` whose state matches `filter`.
+    pub fn unread_where(
+        &self,
+        ids: impl IntoIterator<Item = EventId>,
+        filter: impl Fn(&MessageState) -> bool,
+    ) -> usize {
+        ids.into_iter()
+            .map(|id| self.get(&id))
+            .filter(|state| !state.read && state.live() && filter(state))
+            .count()
+    }
+
+    // what of `keys`' messages in `shard` there is to sync, and that's worth syncing
+    fn synced(&self, keys: &Keys, shard: u8) -> impl Iterator<Item = (&EventId, &MessageState)> {
+        self.states
+            .get(&keys.public_key())
+            .into_iter()
+            .flatten()
+            .filter(move |(id, state)| !state.deleted && shard_of(id) == shard)
+    }
+
+    fn read_entries(&self, keys: &Keys, shard: u8) -> Result<HashMap<EventId, (bool, u64)>> {
+        let entries = self
+            .synced(keys, shard)
+            .filter(|(_, state)| state.read_at > 0)
+            .map(|(id, state)| (*id, (state.read, state.read_at)))
+            .collect();
+
+        fit(entries, |(_, read_at)| *read_at)
+    }
+
+    fn filings(&self, keys: &Keys, shard: u8) -> Result<HashMap<EventId, Filing>> {
+        let entries = self
+            .synced(keys, shard)
+            .filter(|(_, state)| state.filed_at > 0)
+            .map(|(id, state)| {
+                let filing = Filing {
+                    folder: state.folder.clone(),
+                    labels: state.labels.clone(),
+                    filed_at: state.filed_at,
+                };
+                (*id, filing)
+            })
+            .collect();
+
+        fit(entries, |filing| filing.filed_at)
+    }
+
+    /// `keys`' side of `part` as a NIP-78 event, encrypted to ourselves.
+    fn event(&self, keys: &Keys, part: SyncPart) -> Result<Event> {
+        let json = match (part.sync, part.shard) {
+            (Sync::ReadState, Some(shard)) => {
+                serde_json::to_string(&self.read_entries(keys, shard)?)?
+            }
+            (Sync::Folders, Some(shard)) => serde_json::to_string(&self.filings(keys, shard)?)?,
+            (Sync::Folders, None) => {
+                let mailboxes = self
+                    .mailboxes
+                    .get(&keys.public_key())
+                    .cloned()
+                    .unwrap_or_default();
+                serde_json::to_string(&mailboxes)?
+            }
+            (Sync::ReadState, None) => {
+                return Err(Error::Generic(
+                    "read state is only synced in shards".to_string(),
+                ))
+            }
+        };
+        let content = nip44::encrypt(
+            keys.secret_key(),
+            &keys.public_key(),
+            json,
+            nip44::Version::V2,
+        )
+        .map_err(|_| Error::Encryption)?;
+
+        EventBuilder::new(Kind::Custom(APP_DATA_KIND), content)
+            .tags(vec![Tag::identifier(part.identifier())])
+            .sign_with_keys(keys)
+            .map_err(|e| Error::Generic(e.to_string()))
+    }
+
+    /// Sends the parts of `keys`' `sync` that hold `ids` to every connected relay.
+    pub fn publish(&self, relays: &mut RelayPool, keys: &Keys, sync: Sync, ids: &[EventId]) {
+        for part in SyncPart::holding(sync, ids) {
+            self.publish_part(relays, keys, part);
+        }
+    }
+
+    /// Sends one part of `keys`' synced state to every connected relay.
+    pub fn publish_part(&self, relays: &mut RelayPool, keys: &Keys, part: SyncPart) {
+        let result = self
+            .event(keys, part)
+            .and_then(|event| relays.publish(&event));
+        if let Err(e) = result {
+            error!("could not publish {}: {}", part.identifier(), e);
+        }
+    }
+
+    fn decrypt<T: DeserializeOwned>(keys: &Keys, event: &Event) -> Result<T> {
+        let json = nip44::decrypt(keys.secret_key(), &keys.public_key(), &event.content)
+            .map_err(|_| Error::Encryption)?;
+
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    /// Merges state synced from another of our devices. Each message keeps whichever side
+    /// changed last, so every device ends up agreeing no matter what order the events arrive
+    /// in.
+    ///
+    /// Returns the account and event to republish if we know something the event doesn't, so
+    /// the relay copy catches up.
+    pub fn process_event<'a>(
+        &mut self,
+        store: &LocalStore,
+        accounts: &'a [Keys],
+        event: &Event,
+    ) -> Option<(&'a Keys, SyncPart)> {
+        if event.kind != Kind::Custom(APP_DATA_KIND) {
+            return None;
+        }
+        let identifier = event.tags.identifier()?;
+        let part = SyncPart::all()
+            .into_iter()
+            .find(|p| p.identifier() == identifier)?;
+        let keys = accounts.iter().find(|k| k.public_key() == event.pubkey)?;
+
+        let merged = match (part.sync, part.shard) {
+            (Sync::ReadState, Some(shard)) => {
+                Self::decrypt(keys, event).and_then(|read| self.merge_read(keys, shard, read))
+            }
+            (Sync::Folders, Some(shard)) => Self::decrypt(keys, event)
+                .and_then(|filings| self.merge_filings(keys, shard, filings)),
+            (Sync::Folders, None) => {
+                Self::decrypt(keys, event).map(|mailboxes| self.merge_mailboxes(keys, mailboxes))
+            }
+            (Sync::ReadState, None) => return None,
+        };
+        let (changed, ahead) = match merged {
+            Ok(v) => v,
+            Err(e) => {
+                error!("could not read synced {} {}: {}", identifier, event.id, e);
+                return None;
+            }
+        };
+
+        if changed {
+            if let Err(e) = self.save(store, keys) {
+                error!("could not save message state: {}", e);
+            }
+        }
+
+        ahead.then_some((keys, part))
+    }
+
+    // read wins ties, so a message opened on two devices in the same second stays read
+    fn merge_read(
+        &mut self,
+        keys: &Keys,
+        shard: u8,
+        incoming: HashMap<EventId, (bool, u64)>,
+    ) -> Result<(bool, bool)> {
+        let states = self.states.entry(keys.public_key()).or_default();
+        let mut changed = false;
+        for (id, (read, read_at)) in &incoming {
+            let state = states.entry(*id).or_default();
+            if (*read_at, *read) > (state.read_at, state.read) {
+                state.read = *read;
+                state.read_at = *read_at;
+                changed = true;
+            }
+        }
+
+        // compared with what we'd publish, so what doesn't fit isn't sent back and forth
+        let ahead = self.read_entries(keys, shard)?.iter().any(|(id, ours)| {
+            incoming
+                .get(id)
+                .is_none_or(|theirs| (ours.1, ours.0) > (theirs.1, theirs.0))
+        });
+
+        Ok((changed, ahead))
+    }
+
+    fn merge_filings(
+        &mut self,
+        keys: &Keys,
+        shard: u8,
+        incoming: HashMap<EventId, Filing>,
+    ) -> Result<(bool, bool)> {
+        let states = self.states.entry(keys.public_key()).or_default();
+        let mut changed = false;
+        for (id, filing) in &incoming {
+            let state = states.entry(*id).or_default();
+            if filing.filed_at > state.filed_at {
+                state.folder = filing.folder.clone();
+                state.labels = filing.labels.clone();
+                state.filed_at = filing.filed_at;
+                changed = true;
+            }
+        }
+
+        let ahead = self.filings(keys, shard)?.iter().any(|(id, ours)| {
+            incoming
+                .get(id)
+                .is_none_or(|theirs| ours.filed_at > theirs.filed_at)
+        });
+
+        Ok((changed, ahead))
+    }
+
+    fn merge_mailboxes(&mut self, keys: &Keys, incoming: Mailboxes) -> (bool, bool) {
+        let mailboxes = self.mailboxes.entry(keys.public_key()).or_default();
+        if incoming.updated_at > mailboxes.updated_at {
+            *mailboxes = incoming;
+            return (true, false);
+        }
+
+        (false, mailboxes.updated_at > incoming.updated_at)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // message ids in shard 0
+    fn id(n: u8) -> EventId {
+        EventId::from_byte_array([n; 32])
+    }
+
+    fn read(entries: &[(EventId, bool, u64)]) -> HashMap<EventId, (bool, u64)> {
+        entries
+            .iter()
+            .map(|(id, read, read_at)| (*id, (*read, *read_at)))
+            .collect()
+    }
+
+    #[test]
+    fn read_state_keeps_the_latest_change() {
+        let keys = Keys::generate();
+        let mut store = MessageStateStore::default();
+        let (changed, ahead) = store
+            .merge_read(&keys, 0, read(&[(id(1), true, 10), (id(2), true, 10)]))
+            .unwrap();
+        assert!(changed);
+        assert!(!ahead);
+
+        // an older change is ignored, and we say we know better
+        let (changed, ahead) = store
+            .merge_read(&keys, 0, read(&[(id(1), false, 5)]))
+            .unwrap();
+        assert!(!changed);
+        assert!(ahead);
+        assert!(store.get(&id(1)).read);
+
+        // a newer one wins
+        store
+            .merge_read(&keys, 0, read(&[(id(1), false, 20)]))
+            .unwrap();
+        assert!(!store.get(&id(1)).read);
+        assert_eq!(store.get(&id(1)).read_at, 20);
+    }
+
+    #[test]
+    fn read_wins_ties() {
+        let keys = Keys::generate();
+        let mut store = MessageStateStore::default();
+        store
+            .merge_read(&keys, 0, read(&[(id(1), false, 10)]))
+            .unwrap();
+        store
+            .merge_read(&keys, 0, read(&[(id(1), true, 10)]))
+            .unwrap();
+        assert!(store.get(&id(1)).read);
+
+        let (changed, _) = store
+            .merge_read(&keys, 0, read(&[(id(1), false, 10)]))
+            .unwrap();
+        assert!(!changed);
+        assert!(store.get(&id(1)).read);
+    }
+
+    #[test]
+    fn filings_keep_the_latest_change() {
+        let keys = Keys::generate();
+        let mut store = MessageStateStore::default();
+        let filing = |folder: &str, filed_at| Filing {
+            folder: Some(folder.to_string()),
+            labels: BTreeSet::from(["urgent".to_string()]),
+            filed_at,
+        };
+
+        store
+            .merge_filings(&keys, 0, HashMap::from([(id(1), filing("Work", 10))]))
+            .unwrap();
+        let (changed, ahead) = store
+            .merge_filings(&keys, 0, HashMap::from([(id(1), filing("Home", 5))]))
+            .unwrap();
+        assert!(!changed);
+        assert!(ahead);
+        assert_eq!(store.get(&id(1)).folder.as_deref(), Some("Work"));
+
+        store
+            .merge_filings(&keys, 0, HashMap::from([(id(1), filing("Home", 20))]))
+            .unwrap();
+        let state = store.get(&id(1));
+        assert_eq!(state.folder.as_deref(), Some("Home"));
+        assert!(state.labels.contains("urgent"));
+    }
+
+    #[test]
+    fn mailboxes_keep_the_latest_list() {
+        let keys = Keys::generate();
+        let mut store = MessageStateStore::default();
+        let mailboxes = |folder: &str, updated_at| Mailboxes {
+            folders: BTreeSet::from([folder.to_string()]),
+            labels: BTreeSet::new(),
+            updated_at,
+        };
+
+        assert_eq!(
+            store.merge_mailboxes(&keys, mailboxes("Work", 10)),
+            (true, false)
+        );
+        assert_eq!(
+            store.merge_mailboxes(&keys, mailboxes("Home", 5)),
+            (false, true)
+        );
+        assert_eq!(
+            store.merge_mailboxes(&keys, mailboxes("Home", 10)),
+            (false, false)
+        );
+        assert!(store.mailboxes().folders.contains("Work"));
+        assert!(!store.mailboxes().folders.contains("Home"));
+    }
+
+    #[test]
+    fn purging_takes_only_what_has_been_in_the_trash_long_enough() {
+        let dir = std::env::temp_dir().join(format!("hoot-state-{}", rand::random::<u64>()));
+        let local = LocalStore::new(&dir);
+        let keys = Keys::generate();
+        let now = Timestamp::now().as_u64();
+        let day = 24 * 60 * 60;
+
+        let mut store = MessageStateStore::default();
+        let states = store.states.entry(keys.public_key()).or_default();
+        let trashed = |trashed_at| MessageState {
+            trashed_at,
+            ..Default::default()
+        };
+        states.insert(id(1), trashed(now - 40 * day));
+        states.insert(id(2), trashed(now - day));
+        states.insert(id(3), MessageState::default());
+
+        let accounts = [keys];
+        assert!(store.purge_expired(&local, &accounts, 0).is_empty());
+        assert_eq!(store.purge_expired(&local, &accounts, 30), vec![id(1)]);
+        assert!(store.get(&id(1)).deleted);
+        assert!(!store.get(&id(2)).deleted);
+        assert!(store.get(&id(3)).live());
+
+        // tombstones stay put rather than being purged again
+        assert!(store.purge_expired(&local, &accounts, 30).is_empty());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,361 @@
+use crate::error::{Error, Result};
+use crate::local_store::LocalStore;
+use crate::relay::{RelayPool, Subscription};
+use nostr::nips::{nip04, nip44};
+use nostr::{Event, EventBuilder, Filter, Keys, Kind, PublicKey, Tag, Timestamp};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use tracing::error;
+
+const MUTE_NAMESPACE: &str = "mute";
+const LIST_FILE: &str = "list";
+
+/// One account's NIP-51 mute list (kind 10000). Entries other clients put there, public or
+/// private, are kept as they are so publishing ours doesn't lose them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct MuteList {
+    /// Tags out in the open on the event.
+    public: Vec<Vec<String>>,
+    /// Tags encrypted to ourselves in the content, which is where our own entries go.
+    private: Vec<Vec<String>>,
+    updated_at: u64,
+    /// Every entry of the last list we and the relays agreed on. Merging against it tells an
+    /// entry one side removed apart from one the other side added.
+    synced: Vec<Vec<String>>,
+}
+
+impl MuteList {
+    fn entries(&self) -> impl Iterator<Item = &Vec<String>> + '_ {
+        self.public.iter().chain(&self.private)
+    }
+
+    fn same_entries(&self, other: &MuteList) -> bool {
+        let mut ours: Vec<&Vec<String>> = self.entries().collect();
+        let mut theirs: Vec<&Vec<String>> = other.entries().collect();
+        ours.sort();
+        ours.dedup();
+        theirs.sort();
+        theirs.dedup();
+        ours == theirs
+    }
+
+    /// Combines ours with `incoming`: what either side added is kept, and what either side
+    /// removed since they last agreed is dropped.
+    fn merge(&self, incoming: &MuteList) -> MuteList {
+        let ours: HashSet<&Vec<String>> = self.entries().collect();
+        let theirs: HashSet<&Vec<String>> = incoming.entries().collect();
+        let keep = |tag: &&Vec<String>| {
+            !self.synced.contains(*tag) || (ours.contains(tag) && theirs.contains(tag))
+        };
+
+        // theirs first, so an entry both have stays where they put it
+        let mut merged = MuteList::default();
+        let mut seen: HashSet<&Vec<String>> = HashSet::new();
+        for (tags, public) in [
+            (&incoming.public, true),
+            (&incoming.private, false),
+            (&self.public, true),
+            (&self.private, false),
+        ] {
+            for tag in tags.iter().filter(keep) {
+                if !seen.insert(tag) {
+                    continue;
+                }
+                if public {
+                    merged.public.push(tag.clone());
+                } else {
+                    merged.private.push(tag.clone());
+                }
+            }
+        }
+
+        merged
+    }
+
+    fn pubkeys(&self) -> impl Iterator<Item = PublicKey> + '_ {
+        self.entries()
+            .filter(|tag| tag.first().is_some_and(|kind| kind == "p"))
+            .filter_map(|tag| tag.get(1))
+            .filter_map(|hex| PublicKey::from_hex(hex).ok())
+    }
+
+    fn from_event(keys: &Keys, event: &Event) -> Result<Self> {
+        let public = event
+            .tags
+            .iter()
+            .map(|tag| tag.as_slice().to_vec())
+            .collect();
+        let private = if event.content.is_empty() {
+            Vec::new()
+        } else {
+            // older clients still encrypt it with NIP-04
+            let json = nip44::decrypt(keys.secret_key(), &keys.public_key(), &event.content)
+                .ok()
+                .or_else(|| {
+                    nip04::decrypt(keys.secret_key(), &keys.public_key(), &event.content).ok()
+                })
+                .ok_or(Error::Encryption)?;
+            serde_json::from_str(&json)?
+        };
+
+        Ok(Self {
+            public,
+            private,
+            updated_at: event.created_at.as_u64(),
+            synced: Vec::new(),
+        })
+    }
+
+    fn to_event(&self, keys: &Keys) -> Result<Event> {
+        let content = nip44::encrypt(
+            keys.secret_key(),
+            &keys.public_key(),
+            serde_json::to_string(&self.private)?,
+            nip44::Version::V2,
+        )
+        .map_err(|_| Error::Encryption)?;
+        let tags: Vec<Tag> = self
+            .public
+            .iter()
+            .filter_map(|tag| Tag::parse(tag).ok())
+            .collect();
+
+        EventBuilder::new(Kind::MuteList, content)
+            .tags(tags)
+            .custom_created_at(Timestamp::from(self.updated_at))
+            .sign_with_keys(keys)
+            .map_err(|e| Error::Generic(e.to_string()))
+    }
+}
+
+/// Mute lists for every loaded account, mirrored to the encrypted [`LocalStore`].
+#[derive(Default)]
+pub struct MuteStore {
+    lists: HashMap<PublicKey, MuteList>,
+    subscription: Option<String>,
+    /// Whether a relay has sent us the lists it holds. Until then publishing waits, so a
+    /// list we haven't seen yet isn't replaced with ours.
+    fetched: bool,
+    // accounts whose list is waiting on that to be published
+    pending: HashSet<PublicKey>,
+}
+
+impl MuteStore {
+    pub fn load(&mut self, store: &LocalStore, accounts: &[Keys]) {
+        self.lists.clear();
+        for keys in accounts {
+            let list = match store.read(keys, MUTE_NAMESPACE, LIST_FILE) {
+                Ok(l) => l.unwrap_or_default(),
+                Err(e) => {
+                    error!("could not read mute list: {}", e);
+                    MuteList::default()
+                }
+            };
+            self.lists.insert(keys.public_key(), list);
+        }
+    }
+
+    /// Asks relays for the mute lists of `accounts`, and to keep sending them as they change.
+    pub fn subscribe(&mut self, relays: &mut RelayPool, accounts: &[Keys]) {
+        let mut sub = Subscription::default();
+        sub.filter(
+            Filter::new()
+                .kind(Kind::MuteList)
+                .authors(accounts.iter().map(|k| k.public_key())),
+        );
+        self.subscription = Some(sub.id.clone());
+        if let Err(e) = relays.add_subscription(sub) {
+            error!("could not subscribe to mute lists: {}", e);
+        }
+    }
+
+    /// Handles a relay's EOSE. Once the first relay has sent its lists, whatever was waiting
+    /// on them gets published.
+    pub fn handle_eose(
+        &mut self,
+        store: &LocalStore,
+        relays: &mut RelayPool,
+        accounts: &[Keys],
+        subscription_id: &str,
+    ) {
+        if self.fetched || self.subscription.as_deref() != Some(subscription_id) {
+            return;
+        }
+        self.fetched = true;
+
+        for keys in accounts {
+            if self.pending.remove(&keys.public_key()) {
+                self.publish(store, relays, keys);
+            }
+        }
+    }
+
+    /// Whether `account` has muted `sender`.
+    pub fn is_muted(&self, account: &PublicKey, sender: &PublicKey) -> bool {
+        self.lists
+            .get(account)
+            .is_some_and(|list| list.pubkeys().any(|pk| pk == *sender))
+    }
+
+    /// Everyone `account` has muted.
+    pub fn muted(&self, account: &PublicKey) -> Vec<PublicKey> {
+        let mut muted: Vec<PublicKey> = self
+            .lists
+            .get(account)
+            .map(|list| list.pubkeys().collect())
+            .unwrap_or_default();
+        muted.sort_by_key(|pk| pk.to_hex());
+        muted.dedup();
+
+        muted
+    }
+
+    /// Mutes or unmutes `pubkey` for `keys`' account. New entries are private, so relays
+    /// can't see who we've blocked.
+    pub fn set(
+        &mut self,
+        store: &LocalStore,
+        keys: &Keys,
+        pubkey: &PublicKey,
+        muted: bool,
+    ) -> Result<()> {
+        let list = self.lists.entry(keys.public_key()).or_default();
+        let hex = pubkey.to_hex();
+        let other = |tag: &Vec<String>| {
+            !(tag.first().is_some_and(|kind| kind == "p") && tag.get(1) == Some(&hex))
+        };
+        list.public.retain(other);
+        list.private.retain(other);
+        if muted {
+            list.private.push(vec!["p".to_string(), hex]);
+        }
+        // replaceable events only replace older ones, so never go backwards
+        list.updated_at = Timestamp::now().as_u64().max(list.updated_at + 1);
+
+        store.write(keys, MUTE_NAMESPACE, LIST_FILE, list)
+    }
+
+    /// Sends `keys`' mute list to every connected relay, or once the relays have sent theirs
+    /// if they haven't yet.
+    pub fn publish(&mut self, store: &LocalStore, relays: &mut RelayPool, keys: &Keys) {
+        if !self.fetched {
+            self.pending.insert(keys.public_key());
+            return;
+        }
+        let Some(list) = self.lists.get_mut(&keys.public_key()) else {
+            return;
+        };
+        let result = list.to_event(keys).and_then(|event| relays.publish(&event));
+        if let Err(e) = result {
+            error!("could not publish mute list: {}", e);
+            return;
+        }
+
+        list.synced = list.entries().cloned().collect();
+        if let Err(e) = store.write(keys, MUTE_NAMESPACE, LIST_FILE, list) {
+            error!("could not save mute list: {}", e);
+        }
+    }
+
+    /// Takes in a mute list one of our accounts published, from another client or device, and
+    /// merges it into ours. Returns whether ours changed, and the account to republish for
+    /// if relays are missing some of ours.
+    pub fn process_event<'a>(
+        &mut self,
+        store: &LocalStore,
+        accounts: &'a [Keys],
+        event: &Event,
+    ) -> (bool, Option<&'a Keys>) {
+        if event.kind != Kind::MuteList {
+            return (false, None);
+        }
+        let Some(keys) = accounts.iter().find(|k| k.public_key() == event.pubkey) else {
+            return (false, None);
+        };
+
+        let list = self.lists.entry(keys.public_key()).or_default();
+        let created_at = event.created_at.as_u64();
+        if created_at < list.updated_at {
+            return (false, Some(keys));
+        }
+        if created_at == list.updated_at {
+            return (false, None);
+        }
+
+        let incoming = match MuteList::from_event(keys, event) {
+            Ok(incoming) => incoming,
+            Err(e) => {
+                error!("could not read mute list {}: {}", event.id, e);
+                return (false, None);
+            }
+        };
+        let mut merged = list.merge(&incoming);
+        let changed = !merged.same_entries(list);
+        let ahead = !merged.same_entries(&incoming);
+        merged.synced = incoming.entries().cloned().collect();
+        merged.updated_at = if ahead {
+            // replaceable events only replace older ones
+            Timestamp::now().as_u64().max(created_at + 1)
+        } else {
+            created_at
+        };
+        *list = merged;
+        if let Err(e) = store.write(keys, MUTE_NAMESPACE, LIST_FILE, list) {
+            error!("could not save mute list: {}", e);
+        }
+
+        (changed, ahead.then_some(keys))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn p(name: &str) -> Vec<String> {
+        vec!["p".to_string(), name.to_string()]
+    }
+
+    fn list(public: &[&str], private: &[&str], synced: &[&str]) -> MuteList {
+        MuteList {
+            public: public.iter().map(|n| p(n)).collect(),
+            private: private.iter().map(|n| p(n)).collect(),
+            updated_at: 0,
+            synced: synced.iter().map(|n| p(n)).collect(),
+        }
+    }
+
+    #[test]
+    fn first_merge_keeps_everything() {
+        let ours = list(&[], &["alice"], &[]);
+        let theirs = list(&["bob"], &["alice"], &[]);
+        let merged = ours.merge(&theirs);
+
+        assert_eq!(merged.public, vec![p("bob")]);
+        assert_eq!(merged.private, vec![p("alice")]);
+    }
+
+    #[test]
+    fn removals_on_either_side_stick() {
+        // both agreed on alice, bob and carol; we unmuted bob and muted dave, another
+        // client unmuted alice and muted erin
+        let ours = list(&["alice"], &["carol", "dave"], &["alice", "bob", "carol"]);
+        let theirs = list(&["bob", "erin"], &["carol"], &[]);
+        let merged = ours.merge(&theirs);
+
+        assert_eq!(merged.public, vec![p("erin")]);
+        assert_eq!(merged.private, vec![p("carol"), p("dave")]);
+    }
+
+    #[test]
+    fn their_placement_wins() {
+        let ours = list(&[], &["alice"], &["alice"]);
+        let theirs = list(&["alice"], &[], &[]);
+        let merged = ours.merge(&theirs);
+
+        assert_eq!(merged.public, vec![p("alice")]);
+        assert!(merged.private.is_empty());
+        assert!(merged.same_entries(&ours));
+    }
+}
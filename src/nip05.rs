@@ -0,0 +1,268 @@
+use crate::error::{Error, Result};
+use crate::http::HttpClient;
+use crate::task::Task;
+use eframe::egui;
+use nostr::PublicKey;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How long a successful lookup is trusted before it's checked again.
+const CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+/// How long to wait before retrying a lookup that failed.
+const RETRY_AFTER: Duration = Duration::from_secs(60);
+/// How long an identifier has to stay put before it's looked up, so typing one out doesn't
+/// send a request for every keystroke.
+const DEBOUNCE: Duration = Duration::from_millis(600);
+/// Most identifiers kept at once. The ones asked about least recently go first.
+const MAX_ENTRIES: usize = 256;
+
+#[derive(Deserialize)]
+struct NostrJson {
+    names: HashMap<String, String>,
+}
+
+fn valid_tld(tld: &str) -> bool {
+    tld.len() >= 2 && tld.chars().all(|c| c.is_ascii_alphabetic())
+}
+
+/// Splits `name@domain.tld` into its parts. A bare `_@domain.tld` is the domain's own
+/// identifier.
+pub fn parse(identifier: &str) -> Option<(String, String)> {
+    let (name, domain) = identifier.trim().split_once('@')?;
+    let name = name.to_lowercase();
+    let domain = domain.to_lowercase();
+
+    let valid_name = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    let host = domain.split(':').next().unwrap_or_default();
+    let valid_domain = domain
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | ':'))
+        && host.split('.').all(|label| !label.is_empty())
+        && host.rsplit_once('.').is_some_and(|(_, tld)| valid_tld(tld));
+    if !valid_name || !valid_domain {
+        return None;
+    }
+
+    Some((name, domain))
+}
+
+/// Whether a token from an address field looks like a NIP-05 identifier rather than a key.
+pub fn is_identifier(token: &str) -> bool {
+    token.contains('@')
+}
+
+/// Fetches the public key `identifier` points to from its domain's `nostr.json`.
+pub fn lookup(client: &dyn HttpClient, identifier: &str) -> Result<PublicKey> {
+    let (name, domain) = parse(identifier)
+        .ok_or_else(|| Error::Generic(format!("{} is not a valid identifier", identifier)))?;
+
+    let url = format!("https://{}/.well-known/nostr.json?name={}", domain, name);
+    let document: NostrJson = serde_json::from_slice(&client.get(&url)?)?;
+    let hex = document
+        .names
+        .iter()
+        .find(|(n, _)| n.to_lowercase() == name)
+        .map(|(_, hex)| hex)
+        .ok_or_else(|| Error::Generic(format!("{} doesn't know {}", domain, name)))?;
+
+    PublicKey::from_hex(hex).map_err(|_| Error::DecodeFailed)
+}
+
+/// Where a lookup stands.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verification {
+    Pending,
+    Verified(PublicKey),
+    Failed(String),
+}
+
+struct Entry {
+    verification: Verification,
+    checked: Instant,
+    task: Option<Task<Result<PublicKey>>>,
+    // when the identifier was first and last asked about
+    first_asked: Instant,
+    last_asked: Instant,
+}
+
+/// Lookups made this session, shared by everything that shows identifiers.
+pub struct Nip05Cache {
+    client: Arc<dyn HttpClient>,
+    entries: HashMap<String, Entry>,
+}
+
+impl Nip05Cache {
+    pub fn new(client: Arc<dyn HttpClient>) -> Self {
+        Self {
+            client,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// The current state of `identifier`, kicking off a lookup in the background if it hasn't
+    /// been looked up yet or the last answer is too old. Only complete `name@domain.tld`
+    /// identifiers are looked up, once they've stopped changing for a moment.
+    pub fn resolve(&mut self, ctx: &egui::Context, identifier: &str) -> Verification {
+        if parse(identifier).is_none() {
+            return Verification::Failed("Not a name@domain identifier".to_string());
+        }
+
+        let key = identifier.trim().to_lowercase();
+        if !self.entries.contains_key(&key) && self.entries.len() >= MAX_ENTRIES {
+            self.evict();
+        }
+        let now = Instant::now();
+        let entry = self.entries.entry(key.clone()).or_insert_with(|| Entry {
+            verification: Verification::Pending,
+            checked: now,
+            task: None,
+            first_asked: now,
+            last_asked: now,
+        });
+        // asked about again after a gap, say after being typed out a second time
+        if now.duration_since(entry.last_asked) > DEBOUNCE {
+            entry.first_asked = now;
+        }
+        entry.last_asked = now;
+
+        if let Some(result) = entry.task.as_ref().and_then(|t| t.take()) {
+            entry.task = None;
+            entry.checked = Instant::now();
+            entry.verification = match result {
+                Ok(pubkey) => Verification::Verified(pubkey),
+                Err(e) => Verification::Failed(e.to_string()),
+            };
+        }
+
+        let stale = match entry.verification {
+            Verification::Pending => true,
+            Verification::Verified(_) => entry.checked.elapsed() >= CACHE_TTL,
+            Verification::Failed(_) => entry.checked.elapsed() >= RETRY_AFTER,
+        };
+        let settled = entry.first_asked.elapsed();
+        if stale && entry.task.is_none() && settled < DEBOUNCE {
+            ctx.request_repaint_after(DEBOUNCE - settled);
+        } else if stale && entry.task.is_none() {
+            let client = self.client.clone();
+            entry.task = Some(Task::spawn(ctx.clone(), move || {
+                lookup(client.as_ref(), &key)
+            }));
+        }
+
+        entry.verification.clone()
+    }
+
+    /// Makes room by dropping the identifier asked about least recently.
+    fn evict(&mut self) {
+        let oldest = self
+            .entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.last_asked)
+            .map(|(key, _)| key.clone());
+        if let Some(key) = oldest {
+            self.entries.remove(&key);
+        }
+    }
+
+    /// Whether `identifier` has been verified to belong to `pubkey`.
+    pub fn verifies(&mut self, ctx: &egui::Context, identifier: &str, pubkey: &PublicKey) -> bool {
+        self.resolve(ctx, identifier) == Verification::Verified(*pubkey)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::LocalClient;
+    use nostr::Keys;
+
+    /// A local stand-in for `example.com` serving `nostr_json` as its NIP-05 document.
+    fn serve(nostr_json: &str) -> LocalClient {
+        let dir = std::env::temp_dir().join(format!("hoot-nip05-{}", rand::random::<u64>()));
+        let well_known = dir.join("example.com").join(".well-known");
+        std::fs::create_dir_all(&well_known).unwrap();
+        std::fs::write(well_known.join("nostr.json"), nostr_json).unwrap();
+
+        LocalClient::new(dir, 0)
+    }
+
+    fn names(name: &str, hex: &str) -> String {
+        format!(r#"{{"names": {{"{}": "{}"}}}}"#, name, hex)
+    }
+
+    #[test]
+    fn resolves_a_known_name() {
+        let pubkey = Keys::generate().public_key();
+        let client = serve(&names("alice", &pubkey.to_hex()));
+
+        assert_eq!(lookup(&client, "Alice@Example.com").unwrap(), pubkey);
+    }
+
+    #[test]
+    fn missing_name_fails() {
+        let pubkey = Keys::generate().public_key();
+        let client = serve(&names("alice", &pubkey.to_hex()));
+
+        assert!(lookup(&client, "bob@example.com").is_err());
+    }
+
+    #[test]
+    fn malformed_json_fails() {
+        let client = serve(r#"{"names": ["alice"]"#);
+
+        assert!(lookup(&client, "alice@example.com").is_err());
+    }
+
+    #[test]
+    fn wrong_pubkey_does_not_verify() {
+        let claimed = Keys::generate().public_key();
+        let other = Keys::generate().public_key();
+        let client = serve(&names("alice", &other.to_hex()));
+        assert_ne!(lookup(&client, "alice@example.com").unwrap(), claimed);
+
+        let client = serve(r#"{"names": {"alice": "not a pubkey"}}"#);
+        assert!(lookup(&client, "alice@example.com").is_err());
+    }
+
+    #[test]
+    fn redirects_are_not_followed() {
+        let pubkey = Keys::generate().public_key();
+        let dir = std::env::temp_dir().join(format!("hoot-nip05-{}", rand::random::<u64>()));
+        let well_known = dir.join("example.com").join(".well-known");
+        let elsewhere = dir.join("elsewhere.com").join(".well-known");
+        std::fs::create_dir_all(&well_known).unwrap();
+        std::fs::create_dir_all(&elsewhere).unwrap();
+        std::fs::write(
+            well_known.join("nostr.json.redirect"),
+            "https://elsewhere.com/.well-known/nostr.json",
+        )
+        .unwrap();
+        std::fs::write(
+            elsewhere.join("nostr.json"),
+            names("alice", &pubkey.to_hex()),
+        )
+        .unwrap();
+
+        assert!(lookup(&LocalClient::new(&dir, 0), "alice@example.com").is_err());
+        // the stand-in does follow it when asked to, so the redirect itself is what fails
+        assert_eq!(
+            lookup(&LocalClient::new(&dir, 1), "alice@example.com").unwrap(),
+            pubkey
+        );
+    }
+
+    #[test]
+    fn only_complete_identifiers_parse() {
+        assert!(parse("alice@example.com").is_some());
+        assert!(parse("_@example.com").is_some());
+        assert!(parse("alice@e").is_none());
+        assert!(parse("alice@example.").is_none());
+        assert!(parse("alice@example.c").is_none());
+        assert!(parse("@example.com").is_none());
+    }
+}
@@ -0,0 +1,135 @@
+use crate::error::{Error, Result};
+use crate::http::HttpClient;
+use crate::task::Task;
+use eframe::egui;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::error;
+
+/// How long a relay's information document is trusted before it's fetched again.
+const CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+/// How long to wait before retrying a fetch that failed.
+const RETRY_AFTER: Duration = Duration::from_secs(5 * 60);
+
+/// Limits a relay puts on what it accepts.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Limitation {
+    pub auth_required: bool,
+    pub min_pow_difficulty: u8,
+}
+
+/// What a relay says about itself (NIP-11).
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct RelayInformation {
+    pub name: String,
+    pub supported_nips: Vec<u16>,
+    pub limitation: Limitation,
+}
+
+impl RelayInformation {
+    pub fn supports(&self, nip: u16) -> bool {
+        self.supported_nips.contains(&nip)
+    }
+}
+
+/// Fetches the information document of the relay at `relay_url`, a `ws://` or `wss://` url.
+pub fn fetch(client: &dyn HttpClient, relay_url: &str) -> Result<RelayInformation> {
+    let url = if let Some(rest) = relay_url.strip_prefix("wss://") {
+        format!("https://{}", rest)
+    } else if let Some(rest) = relay_url.strip_prefix("ws://") {
+        format!("http://{}", rest)
+    } else {
+        return Err(Error::Generic(format!("{} is not a relay url", relay_url)));
+    };
+
+    let body = client.get_accepting(&url, "application/nostr+json")?;
+    Ok(serde_json::from_slice(&body)?)
+}
+
+struct Entry {
+    information: Option<RelayInformation>,
+    // when the last fetch finished, `None` before the first one has
+    checked: Option<Instant>,
+    task: Option<Task<Result<RelayInformation>>>,
+}
+
+/// Information documents of the relays we've talked to this session.
+pub struct Nip11Cache {
+    client: Arc<dyn HttpClient>,
+    entries: HashMap<String, Entry>,
+}
+
+impl Nip11Cache {
+    pub fn new(client: Arc<dyn HttpClient>) -> Self {
+        Self {
+            client,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// What we know about the relay at `relay_url`, fetching its document in the background if
+    /// we don't have it yet or it's gone stale. `None` until the first fetch succeeds.
+    pub fn get(&mut self, ctx: &egui::Context, relay_url: &str) -> Option<&RelayInformation> {
+        let entry = self
+            .entries
+            .entry(relay_url.to_string())
+            .or_insert_with(|| Entry {
+                information: None,
+                checked: None,
+                task: None,
+            });
+
+        if let Some(result) = entry.task.as_ref().and_then(|t| t.take()) {
+            entry.task = None;
+            entry.checked = Some(Instant::now());
+            match result {
+                Ok(information) => entry.information = Some(information),
+                Err(e) => error!("could not fetch relay information of {}: {}", relay_url, e),
+            }
+        }
+
+        let stale = match (&entry.information, entry.checked) {
+            (_, None) => true,
+            (None, Some(checked)) => checked.elapsed() >= RETRY_AFTER,
+            (Some(_), Some(checked)) => checked.elapsed() >= CACHE_TTL,
+        };
+        if stale && entry.task.is_none() {
+            let client = self.client.clone();
+            let url = relay_url.to_string();
+            entry.task = Some(Task::spawn(ctx.clone(), move || {
+                fetch(client.as_ref(), &url)
+            }));
+        }
+
+        entry.information.as_ref()
+    }
+
+    /// Whether we've heard back from the relay at `relay_url` at all, even if only to learn
+    /// that it has no information to give.
+    pub fn checked(&self, relay_url: &str) -> bool {
+        self.entries
+            .get(relay_url)
+            .is_some_and(|entry| entry.checked.is_some())
+    }
+
+    /// What we already know about the relay at `relay_url`, without fetching anything.
+    pub fn cached(&self, relay_url: &str) -> Option<&RelayInformation> {
+        self.entries
+            .get(relay_url)
+            .and_then(|entry| entry.information.as_ref())
+    }
+
+    /// The most proof of work any of `relay_urls` asks events to have.
+    pub fn min_pow<'a>(&self, relay_urls: impl IntoIterator<Item = &'a String>) -> u8 {
+        relay_urls
+            .into_iter()
+            .filter_map(|url| self.cached(url))
+            .map(|info| info.limitation.min_pow_difficulty)
+            .max()
+            .unwrap_or(0)
+    }
+}
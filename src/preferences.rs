@@ -0,0 +1,118 @@
+use crate::local_store::LocalStore;
+use crate::rules::Rule;
+use crate::search::SavedSearch;
+use crate::templates::Template;
+use crate::ui::inbox::InboxLayout;
+use nostr::{Keys, PublicKey, Timestamp};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::error;
+
+/// Key the preferences are stored under in eframe's storage.
+pub const STORAGE_KEY: &str = "preferences";
+
+const PRIVATE_NAMESPACE: &str = "preferences";
+const PRIVATE_FILE: &str = "private";
+
+/// Settings that survive restarts. Saved through eframe's persistence, so nothing secret goes
+/// in here.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preferences {
+    /// Blossom server attachments get uploaded to. When empty, attachments can't be sent.
+    pub blob_server: String,
+    /// Signature appended to new mail, keyed by the hex pubkey of the sending account.
+    pub signatures: HashMap<String, String>,
+    /// Columns, widths and sort order of the inbox table.
+    pub inbox: InboxLayout,
+    /// Days a message stays in the trash before it's purged. Zero keeps the trash forever.
+    pub trash_retention_days: u32,
+    /// Whether new mail from people we don't trust is checked for spam.
+    pub spam_filter: bool,
+    /// Proof of work (leading zero bits) that vouches for a sender we don't know.
+    pub spam_min_pow: u8,
+    /// Proof of work put on the gift wraps of outgoing mail, in leading zero bits. Relays
+    /// that ask for more get more.
+    pub send_pow: u8,
+    /// Whether the seal inside each gift wrap gets the same proof of work.
+    pub send_pow_seal: bool,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Self {
+            blob_server: String::new(),
+            signatures: HashMap::new(),
+            inbox: InboxLayout::default(),
+            trash_retention_days: 30,
+            spam_filter: true,
+            spam_min_pow: 16,
+            send_pow: 0,
+            send_pow_seal: false,
+        }
+    }
+}
+
+impl Preferences {
+    /// The signature for mail sent from `account`, empty if it doesn't have one.
+    pub fn signature(&self, account: &PublicKey) -> &str {
+        self.signatures
+            .get(&account.to_hex())
+            .map_or("", String::as_str)
+    }
+}
+
+/// Settings that give away too much to keep in plain text, like who mail gets forwarded to.
+/// They live in the encrypted [`LocalStore`], a copy under every account, and whichever copy
+/// is newest wins.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrivatePreferences {
+    /// Canned responses that can be inserted while composing.
+    pub templates: Vec<Template>,
+    /// Searches shown as folders in the side navbar.
+    pub saved_searches: Vec<SavedSearch>,
+    /// Rules run on incoming mail, in order.
+    pub rules: Vec<Rule>,
+    updated_at: u64,
+    // what was last written, so saving only writes when something changed
+    #[serde(skip)]
+    saved: String,
+}
+
+impl PrivatePreferences {
+    pub fn load(store: &LocalStore, accounts: &[Keys]) -> Self {
+        let mut newest = Self::default();
+        for keys in accounts {
+            match store.read::<Self>(keys, PRIVATE_NAMESPACE, PRIVATE_FILE) {
+                Ok(Some(copy)) if copy.updated_at > newest.updated_at => newest = copy,
+                Ok(_) => {}
+                Err(e) => error!("could not read private preferences: {}", e),
+            }
+        }
+        newest.saved = newest.snapshot();
+        for rule in &mut newest.rules {
+            rule.compile();
+        }
+
+        newest
+    }
+
+    fn snapshot(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    /// Writes a copy under every account if anything changed since the last save.
+    pub fn save(&mut self, store: &LocalStore, accounts: &[Keys]) {
+        if self.snapshot() == self.saved {
+            return;
+        }
+        self.updated_at = Timestamp::now().as_u64();
+        for keys in accounts {
+            if let Err(e) = store.write(keys, PRIVATE_NAMESPACE, PRIVATE_FILE, self) {
+                error!("could not save private preferences: {}", e);
+            }
+        }
+        self.saved = self.snapshot();
+    }
+}
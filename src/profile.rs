@@ -0,0 +1,195 @@
+use crate::relay::{RelayPool, Subscription};
+use nostr::{Event, Filter, JsonUtil, Kind, Metadata, PublicKey, ToBech32};
+use std::collections::{HashMap, HashSet};
+use tracing::{debug, error};
+
+/// The full metadata of the newest kind 0 event nostrdb has stored for `pubkey`.
+fn stored_metadata(ndb: &nostrdb::Ndb, pubkey: &PublicKey) -> Option<Metadata> {
+    let txn = nostrdb::Transaction::new(ndb).ok()?;
+    let author = pubkey.to_bytes();
+    let filter = nostrdb::Filter::new()
+        .authors(vec![&author])
+        .kinds(vec![Kind::Metadata.as_u16() as u64])
+        .build();
+
+    let results = match ndb.query(&txn, &[filter], 16) {
+        Ok(results) => results,
+        Err(e) => {
+            error!("could not read profile from nostrdb: {}", e);
+            return None;
+        }
+    };
+    results
+        .iter()
+        .filter_map(|result| result.note.json().ok())
+        .filter_map(|json| Event::from_json(json).ok())
+        .max_by_key(|event| event.created_at)
+        .and_then(|event| Metadata::from_json(&event.content).ok())
+}
+
+/// Someone's kind 0 metadata, as stored in nostrdb.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Profile {
+    pub name: Option<String>,
+    pub display_name: Option<String>,
+    pub picture: Option<String>,
+    pub nip05: Option<String>,
+    pub about: Option<String>,
+    pub lud16: Option<String>,
+}
+
+impl Profile {
+    /// The newest profile nostrdb has seen for `pubkey`, if any.
+    pub fn load(ndb: &nostrdb::Ndb, pubkey: &PublicKey) -> Option<Self> {
+        let txn = nostrdb::Transaction::new(ndb).ok()?;
+        let record = ndb.get_profile_by_pubkey(&txn, &pubkey.to_bytes()).ok()?;
+        let profile = record.record().profile()?;
+
+        let field = |value: Option<&str>| {
+            value
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(str::to_string)
+        };
+        Some(Self {
+            name: field(profile.name()),
+            display_name: field(profile.display_name()),
+            picture: field(profile.picture()),
+            nip05: field(profile.nip05()),
+            about: field(profile.about()),
+            lud16: field(profile.lud16()),
+        })
+    }
+
+    pub fn from_metadata(metadata: &Metadata) -> Self {
+        let field = |value: &Option<String>| {
+            value
+                .as_deref()
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(str::to_string)
+        };
+        Self {
+            name: field(&metadata.name),
+            display_name: field(&metadata.display_name),
+            picture: field(&metadata.picture),
+            nip05: field(&metadata.nip05),
+            about: field(&metadata.about),
+            lud16: field(&metadata.lud16),
+        }
+    }
+
+    /// The name this person would like to be called by, if they've set one.
+    pub fn best_name(&self) -> Option<&str> {
+        self.display_name.as_deref().or(self.name.as_deref())
+    }
+}
+
+/// A shortened npub, for when someone hasn't published a name.
+pub fn short_npub(pubkey: &PublicKey) -> String {
+    let npub = pubkey.to_bech32().unwrap();
+    format!("{}…{}", &npub[..10], &npub[npub.len() - 6..])
+}
+
+/// Asks our relays for the profiles of `pubkeys`. They end up in nostrdb as they arrive.
+fn request(relays: &mut RelayPool, pubkeys: Vec<PublicKey>) {
+    if pubkeys.is_empty() {
+        return;
+    }
+
+    let mut sub = Subscription::default();
+    sub.filter(Filter::new().kind(Kind::Metadata).authors(pubkeys));
+    if let Err(e) = relays.request(sub) {
+        error!("could not request profiles: {}", e);
+    }
+}
+
+/// Profiles shown in the interface. Read from nostrdb the first time they're needed, kept up to
+/// date as kind 0 events come in, and fetched from relays when we've never seen them.
+#[derive(Default)]
+pub struct ProfileCache {
+    // alongside the created_at of the event it came from, 0 when it was read from nostrdb
+    profiles: HashMap<PublicKey, Option<(Profile, u64)>>,
+    // everything in the newest kind 0 events, fields we don't show included; None when
+    // nostrdb had nothing either
+    metadata: HashMap<PublicKey, Option<Metadata>>,
+    requested: HashSet<PublicKey>,
+    generation: u64,
+}
+
+impl ProfileCache {
+    pub fn get(&mut self, ndb: &nostrdb::Ndb, pubkey: &PublicKey) -> Option<&Profile> {
+        self.profiles
+            .entry(*pubkey)
+            .or_insert_with(|| Profile::load(ndb, pubkey).map(|p| (p, 0)))
+            .as_ref()
+            .map(|(profile, _)| profile)
+    }
+
+    /// What to call `pubkey` in the interface.
+    pub fn display_name(&mut self, ndb: &nostrdb::Ndb, pubkey: &PublicKey) -> String {
+        self.get(ndb, pubkey)
+            .and_then(|p| p.best_name().map(str::to_string))
+            .unwrap_or_else(|| short_npub(pubkey))
+    }
+
+    /// Takes in a kind 0 event, keeping it if it's newer than what we have.
+    pub fn process_event(&mut self, event: &Event) {
+        if event.kind != Kind::Metadata {
+            return;
+        }
+        let metadata = match Metadata::from_json(&event.content) {
+            Ok(m) => m,
+            Err(e) => {
+                debug!("ignoring malformed profile {}: {}", event.id, e);
+                return;
+            }
+        };
+
+        let created_at = event.created_at.as_u64();
+        let newer = match self.profiles.get(&event.pubkey) {
+            Some(Some((_, seen))) => created_at > *seen,
+            _ => true,
+        };
+        if newer {
+            let profile = Profile::from_metadata(&metadata);
+            self.profiles
+                .insert(event.pubkey, Some((profile, created_at)));
+            self.metadata.insert(event.pubkey, Some(metadata));
+            self.generation += 1;
+        }
+    }
+
+    /// Goes up whenever a newer profile comes in, so names shown elsewhere can be looked up
+    /// again.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// The full metadata from the newest kind 0 event of `pubkey`, from this session or else
+    /// from what nostrdb stored before.
+    pub fn metadata(&mut self, ndb: &nostrdb::Ndb, pubkey: &PublicKey) -> Option<&Metadata> {
+        self.metadata
+            .entry(*pubkey)
+            .or_insert_with(|| stored_metadata(ndb, pubkey))
+            .as_ref()
+    }
+
+    /// Asks relays for whichever of `pubkeys` we have no profile for and haven't asked about
+    /// yet.
+    pub fn fetch(
+        &mut self,
+        relays: &mut RelayPool,
+        ndb: &nostrdb::Ndb,
+        pubkeys: impl IntoIterator<Item = PublicKey>,
+    ) {
+        let mut missing = Vec::new();
+        for pubkey in pubkeys {
+            if !self.requested.contains(&pubkey) && self.get(ndb, &pubkey).is_none() {
+                self.requested.insert(pubkey);
+                missing.push(pubkey);
+            }
+        }
+        request(relays, missing);
+    }
+}
@@ -8,19 +8,20 @@ use std::fmt::{self};
 use crate::error;
 
 #[derive(Debug, Eq, PartialEq)]
-pub struct CommandResult<'a> {
-    event_id: &'a str,
-    status: bool,
-    message: &'a str,
+pub struct CommandResult {
+    pub event_id: String,
+    pub status: bool,
+    pub message: String,
 }
 
 #[derive(Debug, Eq, PartialEq)]
 pub enum RelayMessage<'a> {
     Event(&'a str, &'a str),
-    OK(CommandResult<'a>),
+    OK(CommandResult),
     Eose(&'a str),
     Closed(&'a str, &'a str),
     Notice(&'a str),
+    Auth(String),
 }
 
 #[derive(Debug)]
@@ -64,11 +65,11 @@ impl<'a> RelayMessage<'a> {
         RelayMessage::Notice(msg)
     }
 
-    pub fn ok(event_id: &'a str, status: bool, message: &'a str) -> Self {
+    pub fn ok(event_id: &str, status: bool, message: &str) -> Self {
         RelayMessage::OK(CommandResult {
-            event_id,
+            event_id: event_id.to_string(),
             status,
-            message,
+            message: message.to_string(),
         })
     }
 
@@ -124,19 +125,20 @@ impl<'a> RelayMessage<'a> {
 
         // OK (NIP-20)
         // Relay response format: ["OK",<event_id>, <true|false>, <message>]
-        if &msg[0..=5] == "[\"OK\"," && msg.len() >= 78 {
-            // TODO: fix this
-            let event_id = &msg[7..71];
-            let booly = &msg[73..77];
-            let status: bool = if booly == "true" {
-                true
-            } else if booly == "false" {
-                false
-            } else {
-                return Err(error::Error::DecodeFailed);
-            };
+        if msg.starts_with("[\"OK\",") {
+            let (_, event_id, status, message): (String, String, bool, String) =
+                serde_json::from_str(msg).map_err(|_| error::Error::DecodeFailed)?;
+
+            return Ok(Self::ok(&event_id, status, &message));
+        }
+
+        // AUTH (NIP-42)
+        // Relay response format: ["AUTH", <challenge>]
+        if msg.starts_with("[\"AUTH\",") {
+            let (_, challenge): (String, String) =
+                serde_json::from_str(msg).map_err(|_| error::Error::DecodeFailed)?;
 
-            return Ok(Self::ok(event_id, status, "fixme"));
+            return Ok(Self::Auth(challenge));
         }
 
         Err(error::Error::DecodeFailed)
@@ -156,6 +158,9 @@ pub enum ClientMessage {
     Close {
         subscription_id: String,
     },
+    Auth {
+        event: Event,
+    },
 }
 
 impl From<super::Subscription> for ClientMessage {
@@ -197,6 +202,12 @@ impl Serialize for ClientMessage {
                 seq.serialize_element(subscription_id)?;
                 seq.end()
             }
+            ClientMessage::Auth { event } => {
+                let mut seq = serializer.serialize_seq(Some(2))?;
+                seq.serialize_element("AUTH")?;
+                seq.serialize_element(event)?;
+                seq.end()
+            }
         }
     }
 }
@@ -3,10 +3,10 @@ use ewebsock::{WsEvent, WsMessage};
 use tracing::{debug, error, info};
 
 mod pool;
-pub use pool::{RelayPool, RELAY_RECONNECT_SECONDS};
+pub use pool::{PublishStatus, RelayPool, RELAY_RECONNECT_SECONDS};
 
 mod message;
-pub use message::{ClientMessage, RelayMessage};
+pub use message::{ClientMessage, CommandResult, RelayMessage};
 
 mod subscription;
 pub use subscription::Subscription;
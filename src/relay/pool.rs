@@ -1,19 +1,51 @@
-use crate::error::Result;
+use crate::error::{Error, Result};
 use crate::relay::message::ClientMessage;
-use crate::relay::Subscription;
+use crate::relay::{CommandResult, Subscription};
 use crate::relay::{Relay, RelayStatus};
 use ewebsock::{WsEvent, WsMessage};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use tracing::{error, debug};
 use std::time::{Instant, Duration};
 
 pub const RELAY_RECONNECT_SECONDS: u64 = 5;
+/// How long a one-shot request waits on relays that haven't answered before it's dropped.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+/// How long we remember what relays said about an event we published.
+const PUBLISHED_TTL: Duration = Duration::from_secs(30 * 60);
+
+/// What a relay said about an event we published to it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PublishStatus {
+    Pending,
+    Accepted,
+    Rejected(String),
+}
+
+/// Where an event we published went, and when.
+struct Published {
+    statuses: HashMap<String, PublishStatus>,
+    sent_at: Instant,
+}
+
+/// A subscription that's only wanted until each relay has sent what it has stored.
+struct Request {
+    sub: Subscription,
+    // relays that have finished with it
+    done: HashSet<String>,
+    sent_at: Instant,
+}
 
 pub struct RelayPool {
     pub relays: HashMap<String, Relay>,
     pub subscriptions: HashMap<String, Subscription>,
+    // one-shot requests by subscription id
+    requests: HashMap<String, Request>,
     last_reconnect_attempt: Instant,
     last_ping: Instant,
+    // per event id, the status reported by every relay it was sent to
+    published: HashMap<String, Published>,
+    // the latest NIP-42 challenge each relay sent us
+    challenges: HashMap<String, String>,
 }
 
 impl RelayPool {
@@ -21,8 +53,11 @@ impl RelayPool {
         Self {
             relays: HashMap::new(),
             subscriptions: HashMap::new(),
+            requests: HashMap::new(),
             last_reconnect_attempt: Instant::now(),
             last_ping: Instant::now(),
+            published: HashMap::new(),
+            challenges: HashMap::new(),
         }
     }
 
@@ -53,6 +88,10 @@ impl RelayPool {
             }
             self.last_ping = now;
         }
+
+        self.drop_finished_requests();
+        self.published
+            .retain(|_, published| published.sent_at.elapsed() < PUBLISHED_TTL);
     }
 
     pub fn add_subscription(&mut self, sub: Subscription) -> Result<()> {
@@ -72,6 +111,58 @@ impl RelayPool {
         Ok(())
     }
 
+    /// Asks relays for what they have stored for `sub`, closing it on each relay once it's all
+    /// been sent (EOSE). Unlike [`Self::add_subscription`] nothing is left open, and relays
+    /// that already answered aren't asked again when they reconnect.
+    pub fn request(&mut self, sub: Subscription) -> Result<()> {
+        let payload = serde_json::to_string(&ClientMessage::from(sub.clone()))?;
+        self.requests.insert(
+            sub.id.clone(),
+            Request {
+                sub,
+                done: HashSet::new(),
+                sent_at: Instant::now(),
+            },
+        );
+
+        self.send(ewebsock::WsMessage::Text(payload))
+    }
+
+    /// Handles a relay's EOSE, closing the subscription there if it was a one-shot request.
+    pub fn handle_eose(&mut self, relay_url: &str, subscription_id: &str) {
+        let Some(request) = self.requests.get_mut(subscription_id) else {
+            return;
+        };
+        request.done.insert(relay_url.to_string());
+
+        let close = ClientMessage::Close {
+            subscription_id: subscription_id.to_string(),
+        };
+        let sent = serde_json::to_string(&close)
+            .map_err(Error::from)
+            .and_then(|payload| self.send_to(relay_url, ewebsock::WsMessage::Text(payload)));
+        if let Err(e) = sent {
+            debug!("could not close {} on {}: {}", subscription_id, relay_url, e);
+        }
+        self.drop_finished_requests();
+    }
+
+    /// Handles a relay closing one of our subscriptions itself.
+    pub fn handle_closed(&mut self, relay_url: &str, subscription_id: &str) {
+        if let Some(request) = self.requests.get_mut(subscription_id) {
+            request.done.insert(relay_url.to_string());
+            self.drop_finished_requests();
+        }
+    }
+
+    fn drop_finished_requests(&mut self) {
+        let relays = &self.relays;
+        self.requests.retain(|_, request| {
+            request.sent_at.elapsed() < REQUEST_TIMEOUT
+                && !relays.keys().all(|url| request.done.contains(url))
+        });
+    }
+
     pub fn add_url(
         &mut self,
         url: String,
@@ -87,20 +178,28 @@ impl RelayPool {
         self.relays.remove(url)
     }
 
-    pub fn try_recv(&mut self) -> Option<String> {
+    /// The next message from any relay, along with the url of the relay that sent it.
+    pub fn try_recv(&mut self) -> Option<(String, String)> {
         for relay in self.relays.values_mut() {
             let relay_url = relay.url.clone();
             if let Some(event) = relay.try_recv() {
                 use WsEvent::*;
                 match event {
                     Message(message) => {
-                        return self.handle_message(relay_url, message);
+                        return self
+                            .handle_message(relay_url.clone(), message)
+                            .map(|text| (relay_url, text));
                     }
                     Opened => {
-                        for sub in self.subscriptions.clone() {
+                        let unanswered = self
+                            .requests
+                            .values()
+                            .filter(|request| !request.done.contains(&relay_url))
+                            .map(|request| request.sub.clone());
+                        for sub in self.subscriptions.values().cloned().chain(unanswered) {
                             let client_message = ClientMessage::Req {
-                                subscription_id: sub.1.id,
-                                filters: sub.1.filters,
+                                subscription_id: sub.id,
+                                filters: sub.filters,
                             };
 
                             let payload = match serde_json::to_string(&client_message) {
@@ -164,6 +263,117 @@ impl RelayPool {
         Ok(())
     }
 
+    /// Every relay we're connected to right now, which is where [`Self::publish`] sends.
+    pub fn connected_urls(&self) -> Vec<String> {
+        self.relays
+            .values()
+            .filter(|relay| relay.status == RelayStatus::Connected)
+            .map(|relay| relay.url.clone())
+            .collect()
+    }
+
+    /// Sends a message to one relay only.
+    pub fn send_to(&mut self, relay_url: &str, message: ewebsock::WsMessage) -> Result<()> {
+        match self.relays.get_mut(relay_url) {
+            Some(relay) if relay.status == RelayStatus::Connected => relay.send(message),
+            _ => Err(Error::RelayNotConnected),
+        }
+    }
+
+    /// Publishes an event to one relay only, tracking its answer like [`Self::publish`] does.
+    pub fn publish_to(&mut self, relay_url: &str, event: &nostr::Event) -> Result<()> {
+        let payload = serde_json::to_string(&ClientMessage::Event {
+            event: event.clone(),
+        })?;
+        self.send_to(relay_url, ewebsock::WsMessage::Text(payload))?;
+        self.record_pending(&event.id, relay_url);
+
+        Ok(())
+    }
+
+    /// Sends an event to every connected relay, tracking each one it went out to. Fails only
+    /// if it reached none of them, so callers know nothing went out.
+    pub fn publish(&mut self, event: &nostr::Event) -> Result<()> {
+        let payload = serde_json::to_string(&ClientMessage::Event {
+            event: event.clone(),
+        })?;
+        let mut result = Err(Error::RelayNotConnected);
+        for url in self.connected_urls() {
+            match self.send_to(&url, ewebsock::WsMessage::Text(payload.clone())) {
+                Ok(()) => {
+                    self.record_pending(&event.id, &url);
+                    result = Ok(());
+                }
+                Err(e) => {
+                    error!("could not send event {} to {}: {}", event.id, url, e);
+                    if result.is_err() {
+                        result = Err(e);
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
+    fn record_pending(&mut self, event_id: &nostr::EventId, relay_url: &str) {
+        self.published
+            .entry(event_id.to_hex())
+            .or_insert_with(|| Published {
+                statuses: HashMap::new(),
+                sent_at: Instant::now(),
+            })
+            .statuses
+            .insert(relay_url.to_string(), PublishStatus::Pending);
+    }
+
+    /// Records a relay's OK message for an event we published.
+    pub fn handle_command_result(&mut self, relay_url: &str, result: &CommandResult) {
+        let Some(published) = self.published.get_mut(&result.event_id) else {
+            return;
+        };
+        let status = if result.status {
+            PublishStatus::Accepted
+        } else {
+            PublishStatus::Rejected(result.message.clone())
+        };
+        published.statuses.insert(relay_url.to_string(), status);
+    }
+
+    /// Remembers the challenge a relay sent, for when we need to authenticate to it.
+    pub fn handle_auth(&mut self, relay_url: &str, challenge: &str) {
+        self.challenges
+            .insert(relay_url.to_string(), challenge.to_string());
+    }
+
+    /// Authenticates to a relay as `keys` (NIP-42), answering the last challenge it sent.
+    pub fn authenticate(&mut self, relay_url: &str, keys: &nostr::Keys) -> Result<()> {
+        let challenge = self
+            .challenges
+            .get(relay_url)
+            .ok_or_else(|| Error::Generic(format!("{} hasn't sent a challenge", relay_url)))?;
+        let event = nostr::EventBuilder::new(nostr::Kind::Authentication, "")
+            .tags(vec![
+                nostr::Tag::custom(nostr::TagKind::Relay, vec![relay_url]),
+                nostr::Tag::custom(nostr::TagKind::Challenge, vec![challenge.as_str()]),
+            ])
+            .sign_with_keys(keys)
+            .map_err(|e| Error::Generic(e.to_string()))?;
+
+        let payload = serde_json::to_string(&ClientMessage::Auth { event })?;
+        self.send_to(relay_url, ewebsock::WsMessage::Text(payload))
+    }
+
+    /// What each relay said about a published event, if we published it.
+    pub fn publish_status(
+        &self,
+        event_id: &nostr::EventId,
+    ) -> Option<&HashMap<String, PublishStatus>> {
+        self.published
+            .get(&event_id.to_hex())
+            .map(|published| &published.statuses)
+    }
+
     pub fn ping_all(&mut self) -> Result<()> {
         for relay in self.relays.values_mut() {
             relay.ping();
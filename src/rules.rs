@@ -0,0 +1,460 @@
+use crate::attachments::Attachment;
+use crate::error::Result;
+use crate::local_store::LocalStore;
+use crate::mail_event::{MailMessage, AUTO_SUBMITTED};
+use crate::signature;
+use nostr::nips::nip59::UnwrappedGift;
+use nostr::{EventId, FromBech32, Keys, PublicKey, TagKind, ToBech32};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use tracing::error;
+
+const RULES_NAMESPACE: &str = "rules";
+const APPLIED_FILE: &str = "applied";
+
+/// Notifying, replying and forwarding only happen for mail this recent, so a new device
+/// catching up on old mail doesn't answer all of it again.
+pub const RECENT_SECONDS: u64 = 24 * 60 * 60;
+
+/// Reads `value` as an npub or hex pubkey.
+pub fn parse_pubkey(value: &str) -> Option<PublicKey> {
+    let value = value.trim();
+    PublicKey::from_bech32(value)
+        .or_else(|_| PublicKey::from_hex(value))
+        .ok()
+}
+
+/// Subject patterns compiled, by pattern, or why they don't compile.
+type Patterns = HashMap<String, std::result::Result<Regex, String>>;
+
+/// Something about an incoming message a rule can check.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Condition {
+    /// Sender's name contains this, or their npub or hex pubkey starts with it.
+    Sender(String),
+    /// Whether the sender is in the contacts of the account the mail came to.
+    InContacts(bool),
+    /// Subject matches this regular expression.
+    Subject(String),
+    /// Mail came to this account, as an npub or hex pubkey.
+    Account(String),
+    /// Whether the mail has attachments.
+    HasAttachment(bool),
+}
+
+impl Condition {
+    pub const DEFAULTS: [Self; 5] = [
+        Self::Sender(String::new()),
+        Self::InContacts(true),
+        Self::Subject(String::new()),
+        Self::Account(String::new()),
+        Self::HasAttachment(true),
+    ];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Self::Sender(_) => "Sender is",
+            Self::InContacts(_) => "Sender in contacts",
+            Self::Subject(_) => "Subject matches",
+            Self::Account(_) => "Sent to account",
+            Self::HasAttachment(_) => "Has attachments",
+        }
+    }
+
+    /// Why this condition can't match anything, if it can't.
+    fn problem(&self, patterns: &Patterns) -> Option<String> {
+        match self {
+            Self::Subject(pattern) => patterns.get(pattern)?.as_ref().err().cloned(),
+            Self::Account(account) if parse_pubkey(account).is_none() => {
+                Some("Not an npub or hex pubkey".to_string())
+            }
+            _ => None,
+        }
+    }
+
+    fn matches(&self, message: &Incoming, patterns: &Patterns) -> bool {
+        match self {
+            Self::Sender(value) => {
+                let value = value.trim().to_lowercase();
+                !value.is_empty()
+                    && (message.sender_name.to_lowercase().contains(&value)
+                        || message.sender.to_hex().starts_with(&value)
+                        || message
+                            .sender
+                            .to_bech32()
+                            .is_ok_and(|npub| npub.starts_with(&value)))
+            }
+            Self::InContacts(wanted) => message.in_contacts == *wanted,
+            Self::Subject(pattern) => patterns.get(pattern).is_some_and(|r| {
+                r.as_ref()
+                    .is_ok_and(|regex| regex.is_match(&message.subject))
+            }),
+            Self::Account(account) => parse_pubkey(account) == Some(message.account),
+            Self::HasAttachment(wanted) => (message.attachments > 0) == *wanted,
+        }
+    }
+}
+
+/// What a rule does to the mail it matches.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RuleAction {
+    Label(String),
+    MoveTo(String),
+    MarkRead,
+    Star,
+    Notify,
+    /// Replies to the sender with this text.
+    AutoReply(String),
+    /// Forwards to this npub or hex pubkey.
+    Forward(String),
+}
+
+impl RuleAction {
+    pub const DEFAULTS: [Self; 7] = [
+        Self::Label(String::new()),
+        Self::MoveTo(String::new()),
+        Self::MarkRead,
+        Self::Star,
+        Self::Notify,
+        Self::AutoReply(String::new()),
+        Self::Forward(String::new()),
+    ];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Self::Label(_) => "Add label",
+            Self::MoveTo(_) => "Move to folder",
+            Self::MarkRead => "Mark read",
+            Self::Star => "Star",
+            Self::Notify => "Notify me",
+            Self::AutoReply(_) => "Reply with",
+            Self::Forward(_) => "Forward to",
+        }
+    }
+
+    pub fn problem(&self) -> Option<String> {
+        match self {
+            Self::Label(name) | Self::MoveTo(name) if name.trim().is_empty() => {
+                Some("Needs a name".to_string())
+            }
+            Self::AutoReply(text) if text.trim().is_empty() => Some("Needs a reply".to_string()),
+            Self::Forward(to) if parse_pubkey(to).is_none() => {
+                Some("Not an npub or hex pubkey".to_string())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Conditions that all have to hold, and what to do when they do.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Rule {
+    pub name: String,
+    pub enabled: bool,
+    pub conditions: Vec<Condition>,
+    pub actions: Vec<RuleAction>,
+    // filled in by [`Self::compile`]
+    #[serde(skip)]
+    patterns: Patterns,
+}
+
+impl Default for Rule {
+    fn default() -> Self {
+        Self {
+            name: "New rule".to_string(),
+            enabled: true,
+            conditions: Vec::new(),
+            actions: Vec::new(),
+            patterns: Patterns::new(),
+        }
+    }
+}
+
+impl Rule {
+    /// Compiles the subject patterns, after the rule is loaded or edited. Only patterns it
+    /// hasn't seen get compiled, so this is cheap to call every frame.
+    pub fn compile(&mut self) {
+        let subjects: HashSet<&String> = self
+            .conditions
+            .iter()
+            .filter_map(|c| match c {
+                Condition::Subject(pattern) => Some(pattern),
+                _ => None,
+            })
+            .collect();
+        self.patterns
+            .retain(|pattern, _| subjects.contains(pattern));
+        for pattern in subjects {
+            if !self.patterns.contains_key(pattern) {
+                let compiled = Regex::new(pattern).map_err(|e| e.to_string());
+                self.patterns.insert(pattern.clone(), compiled);
+            }
+        }
+    }
+
+    /// Why `condition`, one of this rule's, can't match anything, if it can't.
+    pub fn problem(&self, condition: &Condition) -> Option<String> {
+        condition.problem(&self.patterns)
+    }
+
+    /// Whether the rule applies to `message`. A rule without conditions matches nothing, so
+    /// a half-made rule can't reply to every message that comes in.
+    pub fn matches(&self, message: &Incoming) -> bool {
+        self.enabled
+            && !self.conditions.is_empty()
+            && self
+                .conditions
+                .iter()
+                .all(|c| c.matches(message, &self.patterns))
+    }
+}
+
+/// What rules get to look at in a message.
+pub struct Incoming {
+    pub id: EventId,
+    pub account: PublicKey,
+    pub sender: PublicKey,
+    pub sender_name: String,
+    pub in_contacts: bool,
+    pub subject: String,
+    pub attachments: usize,
+    pub created_at: u64,
+    /// Sent by another client's rule rather than a person.
+    pub automatic: bool,
+}
+
+impl Incoming {
+    pub fn new(
+        id: EventId,
+        account: PublicKey,
+        gift: &UnwrappedGift,
+        sender_name: String,
+        in_contacts: bool,
+    ) -> Self {
+        let rumor = &gift.rumor;
+        Self {
+            id,
+            account,
+            sender: gift.sender,
+            sender_name,
+            in_contacts,
+            subject: rumor
+                .tags
+                .find(TagKind::Subject)
+                .and_then(|t| t.content())
+                .unwrap_or_default()
+                .to_string(),
+            attachments: Attachment::from_tags(&rumor.tags).len(),
+            created_at: rumor.created_at.as_u64(),
+            automatic: rumor
+                .tags
+                .iter()
+                .any(|t| t.kind() == TagKind::custom(AUTO_SUBMITTED)),
+        }
+    }
+
+    /// Recent enough to notify about, reply to or forward.
+    pub fn recent(&self, now: u64) -> bool {
+        self.created_at + RECENT_SECONDS >= now
+    }
+}
+
+/// An automatic reply to `gift`'s sender saying `text`.
+pub fn auto_reply(gift: &UnwrappedGift, text: &str) -> MailMessage {
+    let subject = gift
+        .rumor
+        .tags
+        .find(TagKind::Subject)
+        .and_then(|t| t.content())
+        .unwrap_or_default();
+    let subject = if subject.to_lowercase().starts_with("re:") {
+        subject.to_string()
+    } else {
+        format!("Re: {}", subject)
+    };
+
+    MailMessage {
+        to: vec![gift.sender],
+        cc: vec![],
+        bcc: vec![],
+        subject,
+        content: text.to_string(),
+        attachments: vec![],
+        auto_submitted: true,
+    }
+}
+
+/// `gift` forwarded to `to`, attachments included.
+pub fn forward(gift: &UnwrappedGift, sender_name: &str, to: PublicKey) -> MailMessage {
+    let subject = gift
+        .rumor
+        .tags
+        .find(TagKind::Subject)
+        .and_then(|t| t.content())
+        .unwrap_or_default();
+
+    MailMessage {
+        to: vec![to],
+        cc: vec![],
+        bcc: vec![],
+        subject: format!("Fwd: {}", subject),
+        content: format!(
+            "---------- Forwarded message ----------\nFrom: {} ({})\nSubject: {}\n\n{}",
+            sender_name,
+            gift.sender.to_bech32().unwrap_or_default(),
+            subject,
+            signature::strip(&gift.rumor.content)
+        ),
+        attachments: Attachment::from_tags(&gift.rumor.tags),
+        auto_submitted: true,
+    }
+}
+
+/// Which messages rules already ran on, per account, so they run once per message rather
+/// than every time a relay sends it again.
+#[derive(Default)]
+pub struct RuleLog {
+    applied: HashMap<PublicKey, HashSet<EventId>>,
+    // accounts with messages recorded since they were last written out
+    dirty: HashSet<PublicKey>,
+}
+
+impl RuleLog {
+    pub fn load(&mut self, store: &LocalStore, accounts: &[Keys]) {
+        self.applied.clear();
+        for keys in accounts {
+            let applied = match store.read(keys, RULES_NAMESPACE, APPLIED_FILE) {
+                Ok(a) => a.unwrap_or_default(),
+                Err(e) => {
+                    error!("could not read which messages rules ran on: {}", e);
+                    HashSet::new()
+                }
+            };
+            self.applied.insert(keys.public_key(), applied);
+        }
+    }
+
+    /// Records that rules ran on `id`. Returns false if they already had. Saved with the next
+    /// [`Self::save`], or [`Self::write`] for when that can't wait.
+    pub fn record(&mut self, keys: &Keys, id: EventId) -> bool {
+        let applied = self.applied.entry(keys.public_key()).or_default();
+        if !applied.insert(id) {
+            return false;
+        }
+        self.dirty.insert(keys.public_key());
+
+        true
+    }
+
+    /// Writes out `keys`' log right away.
+    pub fn write(&mut self, store: &LocalStore, keys: &Keys) -> Result<()> {
+        if let Some(applied) = self.applied.get(&keys.public_key()) {
+            store.write(keys, RULES_NAMESPACE, APPLIED_FILE, applied)?;
+        }
+        self.dirty.remove(&keys.public_key());
+
+        Ok(())
+    }
+
+    /// Writes out the accounts with messages recorded since the last save.
+    pub fn save(&mut self, store: &LocalStore, accounts: &[Keys]) {
+        for keys in accounts {
+            if !self.dirty.contains(&keys.public_key()) {
+                continue;
+            }
+            if let Err(e) = self.write(store, keys) {
+                error!("could not save which messages rules ran on: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn incoming(subject: &str) -> Incoming {
+        Incoming {
+            id: EventId::all_zeros(),
+            account: Keys::generate().public_key(),
+            sender: Keys::generate().public_key(),
+            sender_name: "Alice Liddell".to_string(),
+            in_contacts: true,
+            subject: subject.to_string(),
+            attachments: 0,
+            created_at: 0,
+            automatic: false,
+        }
+    }
+
+    fn rule(conditions: Vec<Condition>) -> Rule {
+        let mut rule = Rule {
+            conditions,
+            ..Default::default()
+        };
+        rule.compile();
+        rule
+    }
+
+    #[test]
+    fn sender_matches_names_and_keys() {
+        let message = incoming("hi");
+        let npub = message.sender.to_bech32().unwrap();
+        let patterns = Patterns::new();
+
+        assert!(Condition::Sender("alice".to_string()).matches(&message, &patterns));
+        assert!(Condition::Sender(npub[..12].to_string()).matches(&message, &patterns));
+        assert!(Condition::Sender(message.sender.to_hex()).matches(&message, &patterns));
+        assert!(!Condition::Sender("bob".to_string()).matches(&message, &patterns));
+        assert!(!Condition::Sender(" ".to_string()).matches(&message, &patterns));
+    }
+
+    #[test]
+    fn flags_and_accounts() {
+        let mut message = incoming("hi");
+        message.attachments = 2;
+        let patterns = Patterns::new();
+
+        assert!(Condition::InContacts(true).matches(&message, &patterns));
+        assert!(!Condition::InContacts(false).matches(&message, &patterns));
+        assert!(Condition::HasAttachment(true).matches(&message, &patterns));
+        assert!(Condition::Account(message.account.to_hex()).matches(&message, &patterns));
+        assert!(!Condition::Account(message.sender.to_hex()).matches(&message, &patterns));
+        assert!(!Condition::Account("nobody".to_string()).matches(&message, &patterns));
+    }
+
+    #[test]
+    fn subjects_match_compiled_patterns() {
+        let message = incoming("Re: Invoice #42");
+        let subject = Condition::Subject(r"(?i)invoice #\d+".to_string());
+
+        assert!(!subject.matches(&message, &Patterns::new()));
+        assert!(rule(vec![subject]).matches(&message));
+        assert!(!rule(vec![Condition::Subject("^Fwd:".to_string())]).matches(&message));
+    }
+
+    #[test]
+    fn bad_patterns_match_nothing_and_say_why() {
+        let condition = Condition::Subject("(unclosed".to_string());
+        let rule = rule(vec![condition.clone()]);
+
+        assert!(!rule.matches(&incoming("(unclosed")));
+        assert!(rule.problem(&condition).is_some());
+    }
+
+    #[test]
+    fn rules_need_every_condition() {
+        let message = incoming("hello");
+
+        assert!(!rule(vec![]).matches(&message));
+        assert!(rule(vec![Condition::InContacts(true)]).matches(&message));
+        let both = vec![Condition::InContacts(true), Condition::HasAttachment(true)];
+        assert!(!rule(both).matches(&message));
+
+        let mut disabled = rule(vec![Condition::InContacts(true)]);
+        disabled.enabled = false;
+        assert!(!disabled.matches(&message));
+    }
+}
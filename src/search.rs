@@ -0,0 +1,505 @@
+use crate::attachments::Attachment;
+use crate::local_store::LocalStore;
+use chrono::{Local, NaiveDate, TimeZone};
+use nostr::nips::nip59::UnwrappedGift;
+use nostr::{EventId, Keys, PublicKey, TagKind, ToBech32};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use tracing::error;
+
+const INDEX_NAMESPACE: &str = "search";
+const INDEX_FILE: &str = "index";
+
+/// The searchable parts of one decrypted message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Document {
+    account: PublicKey,
+    sender: PublicKey,
+    sender_name: String,
+    recipients: Vec<(PublicKey, String)>,
+    subject: String,
+    body: String,
+    attachments: Vec<String>,
+    created_at: u64,
+}
+
+impl Document {
+    /// Everything free-text search looks at.
+    fn text(&self) -> impl Iterator<Item = &str> {
+        [
+            self.subject.as_str(),
+            self.body.as_str(),
+            self.sender_name.as_str(),
+        ]
+        .into_iter()
+        .chain(self.attachments.iter().map(String::as_str))
+    }
+
+    fn words(&self) -> HashSet<String> {
+        self.text().flat_map(words).collect()
+    }
+}
+
+/// Lowercased runs of letters and digits, which is what the index is keyed by.
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// Whether `value`, as typed after `from:` or `to:`, names `pubkey`.
+fn names(value: &str, pubkey: &PublicKey, name: &str) -> bool {
+    name.to_lowercase().contains(value)
+        || pubkey.to_hex().starts_with(value)
+        || pubkey.to_bech32().is_ok_and(|npub| npub.starts_with(value))
+}
+
+/// Midnight at the start of a `YYYY-MM-DD` day, local time.
+fn day_start(value: &str) -> Option<u64> {
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+    let midnight = Local
+        .from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+        .earliest()?;
+    u64::try_from(midnight.timestamp()).ok()
+}
+
+/// Splits on whitespace, keeping `"quoted phrases"` together.
+fn tokens(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in text.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                current.push(c);
+            }
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+
+    tokens
+}
+
+/// A parsed search, e.g. `from:alice has:attachment after:2024-01-01 "quarterly report"`.
+///
+/// Plain words match the start of any word in the subject, body, sender name or attachment
+/// names; quoted phrases have to appear as written. Everything has to match.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Query {
+    pub words: Vec<String>,
+    pub phrases: Vec<String>,
+    pub from: Vec<String>,
+    pub to: Vec<String>,
+    pub subject: Vec<String>,
+    pub has_attachment: bool,
+    pub before: Option<u64>,
+    pub after: Option<u64>,
+    /// Parts of the query that couldn't be understood, like a malformed date.
+    pub problems: Vec<String>,
+}
+
+impl Query {
+    pub fn parse(text: &str) -> Self {
+        let mut query = Self::default();
+        for token in tokens(text) {
+            let (operator, value) = match token.split_once(':') {
+                Some((operator, value)) => (operator.to_lowercase(), value.trim_matches('"')),
+                None => (String::new(), token.as_str()),
+            };
+            let value = value.to_lowercase();
+
+            match operator.as_str() {
+                "from" => query.from.push(value),
+                "to" => query.to.push(value),
+                "subject" => query.subject.push(value),
+                "has" if value == "attachment" || value == "attachments" => {
+                    query.has_attachment = true
+                }
+                "before" | "after" => match day_start(&value) {
+                    Some(time) if operator == "before" => query.before = Some(time),
+                    Some(time) => query.after = Some(time),
+                    None => query
+                        .problems
+                        .push(format!("{}: wants a date like 2024-01-31", operator)),
+                },
+                _ if token.starts_with('"') => {
+                    let phrase = token.trim_matches('"').to_lowercase();
+                    if !phrase.is_empty() {
+                        query.phrases.push(phrase);
+                    }
+                }
+                // anything else, unknown operators included, is searched for as text
+                _ => query.words.extend(words(&token)),
+            }
+        }
+
+        query
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+            && self.phrases.is_empty()
+            && self.from.is_empty()
+            && self.to.is_empty()
+            && self.subject.is_empty()
+            && !self.has_attachment
+            && self.before.is_none()
+            && self.after.is_none()
+    }
+
+    /// Everything but the plain words, which the index answers.
+    fn matches(&self, document: &Document) -> bool {
+        let subject = document.subject.to_lowercase();
+
+        self.phrases.iter().all(|phrase| {
+            document
+                .text()
+                .any(|text| text.to_lowercase().contains(phrase))
+        }) && self
+            .from
+            .iter()
+            .all(|value| names(value, &document.sender, &document.sender_name))
+            && self.to.iter().all(|value| {
+                document
+                    .recipients
+                    .iter()
+                    .any(|(pubkey, name)| names(value, pubkey, name))
+            })
+            && self.subject.iter().all(|value| subject.contains(value))
+            && (!self.has_attachment || !document.attachments.is_empty())
+            && self.before.is_none_or(|time| document.created_at < time)
+            && self.after.is_none_or(|time| document.created_at >= time)
+    }
+}
+
+/// Full-text index over decrypted mail, saved per account through the encrypted
+/// [`LocalStore`].
+#[derive(Default)]
+pub struct SearchIndex {
+    documents: HashMap<EventId, Document>,
+    // every word to the messages it appears in, sorted so prefixes can be looked up
+    terms: BTreeMap<String, HashSet<EventId>>,
+    // accounts whose documents changed since they were last saved
+    dirty: HashSet<PublicKey>,
+    generation: u64,
+}
+
+impl SearchIndex {
+    pub fn load(&mut self, store: &LocalStore, accounts: &[Keys]) {
+        self.documents.clear();
+        self.terms.clear();
+        for keys in accounts {
+            let documents: HashMap<EventId, Document> =
+                match store.read(keys, INDEX_NAMESPACE, INDEX_FILE) {
+                    Ok(d) => d.unwrap_or_default(),
+                    Err(e) => {
+                        error!("could not read search index: {}", e);
+                        HashMap::new()
+                    }
+                };
+            for (id, document) in documents {
+                self.add(id, document);
+            }
+        }
+    }
+
+    /// Writes out the accounts whose documents changed.
+    pub fn save(&mut self, store: &LocalStore, accounts: &[Keys]) {
+        for keys in accounts {
+            let pubkey = keys.public_key();
+            if !self.dirty.contains(&pubkey) {
+                continue;
+            }
+            let documents: HashMap<&EventId, &Document> = self
+                .documents
+                .iter()
+                .filter(|(_, d)| d.account == pubkey)
+                .collect();
+            match store.write(keys, INDEX_NAMESPACE, INDEX_FILE, &documents) {
+                Ok(()) => {
+                    self.dirty.remove(&pubkey);
+                }
+                Err(e) => error!("could not save search index: {}", e),
+            }
+        }
+    }
+
+    /// Goes up whenever a message is indexed, dropped or renamed.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    fn add(&mut self, id: EventId, document: Document) {
+        self.generation += 1;
+        for word in document.words() {
+            self.terms.entry(word).or_default().insert(id);
+        }
+        self.documents.insert(id, document);
+    }
+
+    fn unindex(&mut self, id: &EventId) -> Option<Document> {
+        let document = self.documents.remove(id)?;
+        self.generation += 1;
+        for word in document.words() {
+            if let Some(ids) = self.terms.get_mut(&word) {
+                ids.remove(id);
+                if ids.is_empty() {
+                    self.terms.remove(&word);
+                }
+            }
+        }
+
+        Some(document)
+    }
+
+    /// Indexes a message that was just unwrapped for `account`. `name` looks up how people
+    /// are currently called.
+    pub fn insert(
+        &mut self,
+        account: PublicKey,
+        id: EventId,
+        gift: &UnwrappedGift,
+        mut name: impl FnMut(&PublicKey) -> String,
+    ) {
+        if self.documents.contains_key(&id) {
+            return;
+        }
+        let rumor = &gift.rumor;
+        let document = Document {
+            account,
+            sender: gift.sender,
+            sender_name: name(&gift.sender),
+            recipients: rumor
+                .tags
+                .public_keys()
+                .map(|pubkey| (*pubkey, name(pubkey)))
+                .collect(),
+            subject: rumor
+                .tags
+                .find(TagKind::Subject)
+                .and_then(|t| t.content())
+                .unwrap_or_default()
+                .to_string(),
+            body: rumor.content.clone(),
+            attachments: Attachment::from_tags(&rumor.tags)
+                .into_iter()
+                .map(|a| a.name)
+                .collect(),
+            created_at: rumor.created_at.as_u64(),
+        };
+
+        self.add(id, document);
+        self.dirty.insert(account);
+    }
+
+    /// Drops purged messages from the index.
+    pub fn remove(&mut self, ids: &[EventId]) {
+        for id in ids {
+            if let Some(document) = self.unindex(id) {
+                self.dirty.insert(document.account);
+            }
+        }
+    }
+
+    /// Picks up a new name for `pubkey` wherever they sent or received mail. Returns whether
+    /// any message changed.
+    pub fn rename(&mut self, pubkey: &PublicKey, name: &str) -> bool {
+        let ids: Vec<EventId> = self
+            .documents
+            .iter()
+            .filter(|(_, d)| {
+                (d.sender == *pubkey && d.sender_name != name)
+                    || d.recipients.iter().any(|(p, n)| p == pubkey && n != name)
+            })
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in &ids {
+            let id = *id;
+            let Some(mut document) = self.unindex(&id) else {
+                continue;
+            };
+            if document.sender == *pubkey {
+                document.sender_name = name.to_string();
+            }
+            for (_, recipient_name) in document.recipients.iter_mut().filter(|(p, _)| p == pubkey) {
+                *recipient_name = name.to_string();
+            }
+            self.dirty.insert(document.account);
+            self.add(id, document);
+        }
+
+        !ids.is_empty()
+    }
+
+    /// Whether the message `id` matches `query`, without searching everything else.
+    pub fn matches(&self, id: &EventId, query: &Query) -> bool {
+        let Some(document) = self.documents.get(id) else {
+            return false;
+        };
+        let words = document.words();
+
+        query
+            .words
+            .iter()
+            .all(|word| words.iter().any(|w| w.starts_with(word.as_str())))
+            && query.matches(document)
+    }
+
+    /// Messages with a word starting with `prefix`.
+    fn prefixed(&self, prefix: &str) -> HashSet<EventId> {
+        self.terms
+            .range(prefix.to_string()..)
+            .take_while(|(word, _)| word.starts_with(prefix))
+            .flat_map(|(_, ids)| ids.iter().copied())
+            .collect()
+    }
+
+    /// Every indexed message matching `query`.
+    pub fn search(&self, query: &Query) -> HashSet<EventId> {
+        let mut candidates: Option<HashSet<EventId>> = None;
+        for word in &query.words {
+            let hits = self.prefixed(word);
+            candidates = Some(match candidates {
+                Some(ids) => ids.intersection(&hits).copied().collect(),
+                None => hits,
+            });
+        }
+
+        match candidates {
+            Some(ids) => ids
+                .into_iter()
+                .filter(|id| self.documents.get(id).is_some_and(|d| query.matches(d)))
+                .collect(),
+            None => self
+                .documents
+                .iter()
+                .filter(|(_, d)| query.matches(d))
+                .map(|(id, _)| *id)
+                .collect(),
+        }
+    }
+}
+
+/// A search kept in the side navbar as a virtual folder.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedSearch {
+    pub name: String,
+    pub query: String,
+}
+
+/// The messages each saved search matches, kept up to date as mail is indexed so the navbar
+/// counts don't need a full search every frame.
+#[derive(Default)]
+pub struct SavedSearchHits {
+    hits: HashMap<String, HashSet<EventId>>,
+    generation: u64,
+}
+
+impl SavedSearchHits {
+    /// Runs every saved search against the whole index again.
+    pub fn refresh(&mut self, index: &SearchIndex, searches: &[SavedSearch]) {
+        self.generation += 1;
+        self.hits = searches
+            .iter()
+            .map(|s| (s.name.clone(), index.search(&Query::parse(&s.query))))
+            .collect();
+    }
+
+    /// Adds a message that was just indexed to the searches it matches.
+    pub fn ingest(&mut self, index: &SearchIndex, searches: &[SavedSearch], id: EventId) {
+        self.generation += 1;
+        for search in searches {
+            if index.matches(&id, &Query::parse(&search.query)) {
+                self.hits.entry(search.name.clone()).or_default().insert(id);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, ids: &[EventId]) {
+        self.generation += 1;
+        for hits in self.hits.values_mut() {
+            hits.retain(|id| !ids.contains(id));
+        }
+    }
+
+    /// Goes up whenever any search's hits may have changed.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Messages the saved search called `name` matches.
+    pub fn get(&self, name: &str) -> Option<&HashSet<EventId>> {
+        self.hits.get(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operators_are_picked_out() {
+        let query = Query::parse("From:Alice to:npub1abc subject:Invoice has:attachments");
+
+        assert_eq!(query.from, vec!["alice"]);
+        assert_eq!(query.to, vec!["npub1abc"]);
+        assert_eq!(query.subject, vec!["invoice"]);
+        assert!(query.has_attachment);
+        assert!(query.words.is_empty());
+        assert!(query.problems.is_empty());
+    }
+
+    #[test]
+    fn quotes_keep_phrases_together() {
+        let query = Query::parse(r#"lunch "Quarterly Report" subject:"team offsite""#);
+
+        assert_eq!(query.words, vec!["lunch"]);
+        assert_eq!(query.phrases, vec!["quarterly report"]);
+        assert_eq!(query.subject, vec!["team offsite"]);
+    }
+
+    #[test]
+    fn unknown_operators_are_searched_as_text() {
+        let query = Query::parse("color:blue has:pets");
+
+        assert_eq!(query.words, vec!["color", "blue", "has", "pets"]);
+        assert!(!query.has_attachment);
+    }
+
+    #[test]
+    fn dates_are_read_as_local_days() {
+        let query = Query::parse("after:2024-01-31 before:2024-02-29");
+
+        assert_eq!(query.after, day_start("2024-01-31"));
+        assert_eq!(query.before, day_start("2024-02-29"));
+        assert!(query.after.unwrap() < query.before.unwrap());
+        assert!(query.problems.is_empty());
+    }
+
+    #[test]
+    fn bad_dates_are_reported_not_searched() {
+        let query = Query::parse("before:2024-13-01 after:yesterday");
+
+        assert_eq!(query.before, None);
+        assert_eq!(query.after, None);
+        assert_eq!(query.problems.len(), 2);
+        assert!(query.is_empty());
+    }
+
+    #[test]
+    fn blank_queries_are_empty() {
+        assert!(Query::parse("").is_empty());
+        assert!(Query::parse("   ").is_empty());
+        assert!(!Query::parse("hello").is_empty());
+    }
+}
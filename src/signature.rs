@@ -0,0 +1,84 @@
+/// Separates the body of a mail from the signature, the same "-- " line email clients use.
+const DELIMITER: &str = "\n\n-- \n";
+
+/// The text a signature adds to a body, or nothing for an empty signature.
+pub fn block(signature: &str) -> String {
+    let signature = signature.trim_end();
+    if signature.is_empty() {
+        String::new()
+    } else {
+        format!("{}{}", DELIMITER, signature)
+    }
+}
+
+/// Swaps the signature `old` in `body` for `new`.
+///
+/// If `old` can't be found the user has edited it by hand, so the body is left alone rather
+/// than ending up with two signatures.
+pub fn replace(body: &mut String, old: &str, new: &str) {
+    let old = block(old);
+    let new = block(new);
+
+    if old.is_empty() {
+        body.push_str(&new);
+    } else if let Some(start) = body.find(&old) {
+        body.replace_range(start..start + old.len(), &new);
+    }
+}
+
+/// The body without its signature, or the whole body if it has none.
+pub fn strip(body: &str) -> &str {
+    let delimiter = &DELIMITER[1..];
+    if body.starts_with(&delimiter[1..]) {
+        return "";
+    }
+
+    match body.find(delimiter) {
+        Some(start) => body[..start].trim_end(),
+        None => body,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_signatures_add_nothing() {
+        assert_eq!(block(""), "");
+        assert_eq!(block("  \n"), "");
+        assert_eq!(block("Alice\n"), "\n\n-- \nAlice");
+    }
+
+    #[test]
+    fn replace_adds_swaps_and_removes() {
+        let mut body = "Hello".to_string();
+        replace(&mut body, "", "Alice");
+        assert_eq!(body, "Hello\n\n-- \nAlice");
+
+        replace(&mut body, "Alice", "Bob\nBob Co.");
+        assert_eq!(body, "Hello\n\n-- \nBob\nBob Co.");
+
+        replace(&mut body, "Bob\nBob Co.", "");
+        assert_eq!(body, "Hello");
+    }
+
+    #[test]
+    fn replace_leaves_edited_signatures_alone() {
+        let mut body = "Hello\n\n-- \nAlice, edited".to_string();
+        replace(&mut body, "Alice", "Bob");
+
+        assert_eq!(body, "Hello\n\n-- \nAlice, edited");
+    }
+
+    #[test]
+    fn strip_drops_the_signature() {
+        assert_eq!(strip("Hello\n\n-- \nAlice"), "Hello");
+        assert_eq!(strip("Hello  \n-- \nAlice"), "Hello");
+        assert_eq!(strip("-- \nAlice"), "");
+        assert_eq!(
+            strip("Hello -- not a signature"),
+            "Hello -- not a signature"
+        );
+    }
+}
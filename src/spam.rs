@@ -0,0 +1,555 @@
+use crate::error::Result;
+use crate::local_store::LocalStore;
+use crate::relay::{RelayPool, Subscription};
+use nostr::nips::nip13;
+use nostr::nips::nip59::UnwrappedGift;
+use nostr::{Event, EventId, Filter, JsonUtil, Keys, Kind, PublicKey, TagKind};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use tracing::error;
+
+const SPAM_NAMESPACE: &str = "spam";
+const FILTER_FILE: &str = "filter";
+
+/// Contact lists of our accounts and of everyone they follow, which is as far out as trust
+/// reaches.
+#[derive(Default)]
+pub struct WebOfTrust {
+    accounts: HashSet<PublicKey>,
+    // newest contact list we have from each author, with its created_at
+    lists: HashMap<PublicKey, (u64, HashSet<PublicKey>)>,
+    // everyone our accounts follow, kept up to date with `lists`
+    follows: HashSet<PublicKey>,
+    // everyone they follow, with how many of their lists each one is on
+    followed_by_follows: HashMap<PublicKey, u32>,
+    // authors whose lists we've asked relays for
+    requested: HashSet<PublicKey>,
+}
+
+/// Every kind 3 event nostrdb has from `authors`.
+fn stored_lists(ndb: &nostrdb::Ndb, authors: &[PublicKey]) -> Vec<Event> {
+    if authors.is_empty() {
+        return Vec::new();
+    }
+    let Ok(txn) = nostrdb::Transaction::new(ndb) else {
+        return Vec::new();
+    };
+    let keys: Vec<[u8; 32]> = authors.iter().map(|pk| pk.to_bytes()).collect();
+    let filter = nostrdb::Filter::new()
+        .authors(keys.iter().collect())
+        .kinds(vec![Kind::ContactList.as_u16() as u64])
+        .build();
+
+    match ndb.query(&txn, &[filter], authors.len() as i32) {
+        Ok(results) => results
+            .iter()
+            .filter_map(|result| result.note.json().ok())
+            .filter_map(|json| Event::from_json(json).ok())
+            .collect(),
+        Err(e) => {
+            error!("could not read contact lists from nostrdb: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+impl WebOfTrust {
+    /// Reads what nostrdb already has: our own lists first, then those of who they follow.
+    pub fn load(&mut self, ndb: &nostrdb::Ndb, accounts: &[Keys]) {
+        self.accounts = accounts.iter().map(|k| k.public_key()).collect();
+        self.lists.clear();
+        self.refresh();
+
+        let accounts: Vec<PublicKey> = self.accounts.iter().copied().collect();
+        for event in stored_lists(ndb, &accounts) {
+            self.process_event(&event);
+        }
+        let follows: Vec<PublicKey> = self.follows.iter().copied().collect();
+        for event in stored_lists(ndb, &follows) {
+            self.process_event(&event);
+        }
+    }
+
+    /// Takes in a contact list from one of our accounts or someone they follow. Returns true
+    /// if it changed who our accounts follow.
+    pub fn process_event(&mut self, event: &Event) -> bool {
+        if event.kind != Kind::ContactList {
+            return false;
+        }
+        let ours = self.accounts.contains(&event.pubkey);
+        if !ours && !self.follows.contains(&event.pubkey) {
+            return false;
+        }
+        let created_at = event.created_at.as_u64();
+        if self
+            .lists
+            .get(&event.pubkey)
+            .is_some_and(|(seen, _)| *seen >= created_at)
+        {
+            return false;
+        }
+
+        let follows: HashSet<PublicKey> = event
+            .tags
+            .iter()
+            .filter(|t| t.kind() == TagKind::p())
+            .filter_map(|t| t.content())
+            .filter_map(|hex| PublicKey::from_hex(hex).ok())
+            .collect();
+        let previous = self
+            .lists
+            .insert(event.pubkey, (created_at, follows.clone()));
+
+        if ours {
+            self.refresh();
+        } else {
+            // only one of the second hop lists changed, so just swap its people
+            if let Some((_, previous)) = previous {
+                self.uncount(&previous);
+            }
+            for pubkey in follows {
+                *self.followed_by_follows.entry(pubkey).or_default() += 1;
+            }
+        }
+
+        ours
+    }
+
+    fn uncount(&mut self, pubkeys: &HashSet<PublicKey>) {
+        for pubkey in pubkeys {
+            if let Some(count) = self.followed_by_follows.get_mut(pubkey) {
+                *count -= 1;
+                if *count == 0 {
+                    self.followed_by_follows.remove(pubkey);
+                }
+            }
+        }
+    }
+
+    /// Works out who our accounts follow, and who they follow, from scratch.
+    fn refresh(&mut self) {
+        self.follows = self
+            .accounts
+            .iter()
+            .filter_map(|account| self.lists.get(account))
+            .flat_map(|(_, follows)| follows.iter().copied())
+            .collect();
+        self.followed_by_follows.clear();
+        for (_, theirs) in self.follows.iter().filter_map(|f| self.lists.get(f)) {
+            for pubkey in theirs {
+                *self.followed_by_follows.entry(*pubkey).or_default() += 1;
+            }
+        }
+    }
+
+    /// Hops from our accounts to `pubkey` through follows: 0 for our own accounts, 1 for
+    /// people we follow, 2 for people they follow. `None` past that.
+    pub fn distance(&self, pubkey: &PublicKey) -> Option<u8> {
+        if self.accounts.contains(pubkey) {
+            Some(0)
+        } else if self.follows.contains(pubkey) {
+            Some(1)
+        } else if self.followed_by_follows.contains_key(pubkey) {
+            Some(2)
+        } else {
+            None
+        }
+    }
+
+    /// Asks relays for the contact lists of anyone we follow that we haven't asked about yet.
+    pub fn request(&mut self, relays: &mut RelayPool) {
+        let missing: Vec<PublicKey> = self
+            .follows
+            .iter()
+            .copied()
+            .filter(|pubkey| self.requested.insert(*pubkey))
+            .collect();
+        if missing.is_empty() {
+            return;
+        }
+
+        let mut sub = Subscription::default();
+        sub.filter(Filter::new().kind(Kind::ContactList).authors(missing));
+        if let Err(e) = relays.request(sub) {
+            error!("could not request contact lists: {}", e);
+        }
+    }
+}
+
+/// The most proof of work (NIP-13) done on the outer or inner layer of a message: the gift
+/// wrap or the rumor. The rumor's id is worked out rather than taken from the rumor itself,
+/// which the sender could have filled in with anything. The seal in between is left out, as
+/// getting at it means decrypting the wrap again, and we only ever mine it along with the wrap.
+pub fn pow(wrap: &Event, gift: &UnwrappedGift) -> u8 {
+    let rumor = &gift.rumor;
+    let rumor_id = EventId::new(
+        &rumor.pubkey,
+        &rumor.created_at,
+        &rumor.kind,
+        rumor.tags.as_slice(),
+        &rumor.content,
+    );
+
+    nip13::get_leading_zero_bits(wrap.id.as_bytes())
+        .max(nip13::get_leading_zero_bits(rumor_id.as_bytes()))
+}
+
+/// Lowercased words of a message, which the classifier counts.
+pub fn words(gift: &UnwrappedGift) -> HashSet<String> {
+    let subject = gift
+        .rumor
+        .tags
+        .find(TagKind::Subject)
+        .and_then(|t| t.content())
+        .unwrap_or_default();
+
+    [subject, gift.rumor.content.as_str()]
+        .into_iter()
+        .flat_map(|text| text.split(|c: char| !c.is_alphanumeric()))
+        .filter(|w| w.len() > 2)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Naive Bayes over the words of messages the user has marked as spam or not.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Classifier {
+    spam_messages: u32,
+    ham_messages: u32,
+    spam_words: HashMap<String, u32>,
+    ham_words: HashMap<String, u32>,
+}
+
+impl Classifier {
+    fn train(&mut self, words: &HashSet<String>, spam: bool, forget: bool) {
+        let (messages, counts) = if spam {
+            (&mut self.spam_messages, &mut self.spam_words)
+        } else {
+            (&mut self.ham_messages, &mut self.ham_words)
+        };
+
+        if forget {
+            *messages = messages.saturating_sub(1);
+            for word in words {
+                if let Some(count) = counts.get_mut(word) {
+                    *count = count.saturating_sub(1);
+                    if *count == 0 {
+                        counts.remove(word);
+                    }
+                }
+            }
+        } else {
+            *messages += 1;
+            for word in words {
+                *counts.entry(word.clone()).or_default() += 1;
+            }
+        }
+    }
+
+    /// Chance that a message with these words is spam, `None` until it has seen both kinds.
+    fn spam_probability(&self, words: &HashSet<String>) -> Option<f32> {
+        if self.spam_messages == 0 || self.ham_messages == 0 {
+            return None;
+        }
+
+        let total = (self.spam_messages + self.ham_messages) as f64;
+        let mut spam = (self.spam_messages as f64 / total).ln();
+        let mut ham = (self.ham_messages as f64 / total).ln();
+        for word in words {
+            let in_spam = self.spam_words.get(word).copied().unwrap_or(0) as f64;
+            let in_ham = self.ham_words.get(word).copied().unwrap_or(0) as f64;
+            // how many messages of each kind had the word, smoothed so unseen words count
+            spam += ((in_spam + 1.0) / (self.spam_messages as f64 + 2.0)).ln();
+            ham += ((in_ham + 1.0) / (self.ham_messages as f64 + 2.0)).ln();
+        }
+
+        Some((1.0 / (1.0 + (ham - spam).exp())) as f32)
+    }
+}
+
+/// Everything the filter has learned for one account.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct FilterData {
+    classifier: Classifier,
+    /// Messages marked as not spam minus messages marked as spam, per sender.
+    reputation: HashMap<PublicKey, i32>,
+    /// What the user said about each message they gave feedback on.
+    feedback: HashMap<EventId, bool>,
+    /// Messages already checked, so a verdict the user overrode isn't made again.
+    checked: HashSet<EventId>,
+}
+
+/// What the filter weighs up for a message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signals {
+    /// Hops from us through follows, see [`WebOfTrust::distance`].
+    pub distance: Option<u8>,
+    pub in_contacts: bool,
+    /// Leading zero bits, see [`pow`].
+    pub pow: u8,
+    pub reputation: i32,
+    /// From the classifier, `None` until it's been trained.
+    pub spam_probability: Option<f32>,
+}
+
+impl Signals {
+    /// Whether a message with these signals belongs in Spam, and why.
+    pub fn verdict(&self, min_pow: u8) -> (bool, String) {
+        if self.in_contacts || self.distance.is_some_and(|d| d <= 1) {
+            return (
+                false,
+                "sender is a contact or someone you follow".to_string(),
+            );
+        }
+        if self.reputation > 0 {
+            return (
+                false,
+                "you've marked mail from this sender as not spam".to_string(),
+            );
+        }
+        if self.reputation < 0 {
+            return (
+                true,
+                "you've marked mail from this sender as spam".to_string(),
+            );
+        }
+
+        // an untrained filter has nothing to go on, so it stays out of the way
+        let Some(mut score) = self.spam_probability else {
+            return (false, "the filter hasn't been trained yet".to_string());
+        };
+        let mut reasons = vec![format!("classifier says {:.0}%", score * 100.0)];
+        if self.distance == Some(2) {
+            score -= 0.25;
+            reasons.push("followed by someone you follow".to_string());
+        }
+        if self.pow >= min_pow {
+            score -= 0.25;
+            reasons.push(format!("{} bits of proof of work", self.pow));
+        } else if self.distance.is_none() {
+            score += 0.25;
+            reasons.push("unknown sender without proof of work".to_string());
+        }
+
+        (score >= 0.5, reasons.join(", "))
+    }
+}
+
+/// Spam filter state for every loaded account, kept in the encrypted [`LocalStore`].
+#[derive(Default)]
+pub struct SpamFilter {
+    accounts: HashMap<PublicKey, FilterData>,
+    // accounts with checks that haven't been written out yet
+    dirty: HashSet<PublicKey>,
+}
+
+impl SpamFilter {
+    pub fn load(&mut self, store: &LocalStore, accounts: &[Keys]) {
+        self.accounts.clear();
+        for keys in accounts {
+            let data = match store.read(keys, SPAM_NAMESPACE, FILTER_FILE) {
+                Ok(d) => d.unwrap_or_default(),
+                Err(e) => {
+                    error!("could not read spam filter: {}", e);
+                    FilterData::default()
+                }
+            };
+            self.accounts.insert(keys.public_key(), data);
+        }
+    }
+
+    fn write(&mut self, store: &LocalStore, keys: &Keys) -> Result<()> {
+        if let Some(data) = self.accounts.get(&keys.public_key()) {
+            store.write(keys, SPAM_NAMESPACE, FILTER_FILE, data)?;
+        }
+        self.dirty.remove(&keys.public_key());
+
+        Ok(())
+    }
+
+    /// Writes out the accounts with checks recorded since the last save.
+    pub fn save(&mut self, store: &LocalStore, accounts: &[Keys]) {
+        for keys in accounts {
+            if !self.dirty.contains(&keys.public_key()) {
+                continue;
+            }
+            if let Err(e) = self.write(store, keys) {
+                error!("could not save spam filter: {}", e);
+            }
+        }
+    }
+
+    /// Records that `id` has been checked. Returns false if it already had been. Saved with
+    /// the next [`Self::save`].
+    pub fn first_check(&mut self, keys: &Keys, id: EventId) -> bool {
+        let data = self.accounts.entry(keys.public_key()).or_default();
+        if !data.checked.insert(id) {
+            return false;
+        }
+        self.dirty.insert(keys.public_key());
+
+        true
+    }
+
+    /// Whether the user has said whether `id` is spam.
+    pub fn has_feedback(&self, account: &PublicKey, id: &EventId) -> bool {
+        self.accounts
+            .get(account)
+            .is_some_and(|data| data.feedback.contains_key(id))
+    }
+
+    /// The sender reputation and classifier parts of the signals for a message to `account`.
+    pub fn score(
+        &self,
+        account: &PublicKey,
+        sender: &PublicKey,
+        words: &HashSet<String>,
+    ) -> (i32, Option<f32>) {
+        match self.accounts.get(account) {
+            Some(data) => (
+                data.reputation.get(sender).copied().unwrap_or(0),
+                data.classifier.spam_probability(words),
+            ),
+            None => (0, None),
+        }
+    }
+
+    /// Learns from the user saying `id` is spam or not. Saying the opposite of an earlier
+    /// answer undoes what that one taught.
+    pub fn feedback(
+        &mut self,
+        store: &LocalStore,
+        keys: &Keys,
+        id: EventId,
+        sender: &PublicKey,
+        words: &HashSet<String>,
+        spam: bool,
+    ) -> Result<()> {
+        let data = self.accounts.entry(keys.public_key()).or_default();
+        let change = if spam { -1 } else { 1 };
+        match data.feedback.insert(id, spam) {
+            Some(earlier) if earlier == spam => return Ok(()),
+            Some(earlier) => {
+                data.classifier.train(words, earlier, true);
+                *data.reputation.entry(*sender).or_default() += 2 * change;
+            }
+            None => *data.reputation.entry(*sender).or_default() += change,
+        }
+        data.classifier.train(words, spam, false);
+
+        self.write(store, keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(text: &str) -> HashSet<String> {
+        text.split_whitespace().map(str::to_string).collect()
+    }
+
+    fn stranger(spam_probability: Option<f32>) -> Signals {
+        Signals {
+            distance: None,
+            in_contacts: false,
+            pow: 0,
+            reputation: 0,
+            spam_probability,
+        }
+    }
+
+    #[test]
+    fn untrained_classifier_has_no_opinion() {
+        let mut classifier = Classifier::default();
+        assert_eq!(classifier.spam_probability(&set("cheap pills")), None);
+
+        // one kind alone isn't enough either
+        classifier.train(&set("cheap pills now"), true, false);
+        assert_eq!(classifier.spam_probability(&set("cheap pills")), None);
+    }
+
+    #[test]
+    fn classifier_learns_both_kinds() {
+        let mut classifier = Classifier::default();
+        for _ in 0..5 {
+            classifier.train(&set("cheap pills winner"), true, false);
+            classifier.train(&set("meeting agenda tomorrow"), false, false);
+        }
+
+        let spam = classifier.spam_probability(&set("cheap winner")).unwrap();
+        let ham = classifier
+            .spam_probability(&set("agenda tomorrow"))
+            .unwrap();
+        assert!(spam > 0.9);
+        assert!(ham < 0.1);
+    }
+
+    #[test]
+    fn forgetting_undoes_training() {
+        let mut classifier = Classifier::default();
+        classifier.train(&set("cheap pills"), true, false);
+        classifier.train(&set("cheap pills"), true, true);
+
+        assert_eq!(classifier.spam_messages, 0);
+        assert!(classifier.spam_words.is_empty());
+    }
+
+    #[test]
+    fn untrained_filter_lets_strangers_through() {
+        let (spam, reason) = stranger(None).verdict(0);
+
+        assert!(!spam);
+        assert!(reason.contains("trained"));
+    }
+
+    #[test]
+    fn people_we_know_are_never_spam() {
+        let contact = Signals {
+            in_contacts: true,
+            ..stranger(Some(1.0))
+        };
+        let follow = Signals {
+            distance: Some(1),
+            ..stranger(Some(1.0))
+        };
+
+        assert!(!contact.verdict(0).0);
+        assert!(!follow.verdict(0).0);
+    }
+
+    #[test]
+    fn reputation_beats_the_classifier() {
+        let liked = Signals {
+            reputation: 1,
+            ..stranger(Some(1.0))
+        };
+        let disliked = Signals {
+            reputation: -1,
+            ..stranger(Some(0.0))
+        };
+
+        assert!(!liked.verdict(0).0);
+        assert!(disliked.verdict(0).0);
+    }
+
+    #[test]
+    fn proof_of_work_and_follows_of_follows_count() {
+        // an unknown sender without enough work is pushed over the line
+        assert!(stranger(Some(0.3)).verdict(8).0);
+
+        let worked = Signals {
+            pow: 8,
+            ..stranger(Some(0.7))
+        };
+        assert!(!worked.verdict(8).0);
+
+        let friend_of_friend = Signals {
+            distance: Some(2),
+            ..stranger(Some(0.6))
+        };
+        assert!(!friend_of_friend.verdict(8).0);
+    }
+}
@@ -0,0 +1,42 @@
+use eframe::egui;
+use std::sync::{Arc, Mutex};
+
+/// Work running on a background thread, polled from the UI every frame.
+pub struct Task<T> {
+    slot: Arc<Mutex<Option<T>>>,
+}
+
+impl<T: Send + 'static> Task<T> {
+    pub fn spawn(ctx: egui::Context, work: impl FnOnce() -> T + Send + 'static) -> Self {
+        let slot = Arc::new(Mutex::new(None));
+        let thread_slot = slot.clone();
+        std::thread::spawn(move || {
+            let result = work();
+            *thread_slot.lock().unwrap() = Some(result);
+            ctx.request_repaint();
+        });
+
+        Self { slot }
+    }
+}
+
+impl<T> Task<T> {
+    /// Takes the result out once the work has finished.
+    pub fn take(&self) -> Option<T> {
+        self.slot.lock().unwrap().take()
+    }
+}
+
+impl<T> Clone for Task<T> {
+    fn clone(&self) -> Self {
+        Self {
+            slot: self.slot.clone(),
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for Task<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Task").finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,40 @@
+use serde::{Deserialize, Serialize};
+
+/// Placeholders a template can use, with a short description for the settings screen.
+pub const PLACEHOLDERS: [(&str, &str); 4] = [
+    ("{recipient_name}", "names of the people in the To field"),
+    ("{recipient_npub}", "npubs of the people in the To field"),
+    ("{sender_name}", "name of the account sending the message"),
+    ("{date}", "today's date"),
+];
+
+/// A canned response that can be dropped into a message.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Template {
+    pub name: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// What the placeholders in a template get replaced with.
+#[derive(Debug, Clone, Default)]
+pub struct Values {
+    pub recipient_names: Vec<String>,
+    pub recipient_npubs: Vec<String>,
+    pub sender_name: String,
+    pub date: String,
+}
+
+impl Template {
+    /// The subject and body with every placeholder filled in.
+    pub fn fill(&self, values: &Values) -> (String, String) {
+        (fill(&self.subject, values), fill(&self.body, values))
+    }
+}
+
+fn fill(text: &str, values: &Values) -> String {
+    text.replace("{recipient_name}", &values.recipient_names.join(", "))
+        .replace("{recipient_npub}", &values.recipient_npubs.join(", "))
+        .replace("{sender_name}", &values.sender_name)
+        .replace("{date}", &values.date)
+}
@@ -0,0 +1,205 @@
+use chrono::{DateTime, Local, TimeZone};
+use std::time::Duration;
+
+const MINUTE: u64 = 60;
+const HOUR: u64 = 60 * MINUTE;
+const DAY: u64 = 24 * HOUR;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DateOrder {
+    DayMonthYear,
+    MonthDayYear,
+    YearMonthDay,
+}
+
+/// How dates and times are written where the user is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Locale {
+    order: DateOrder,
+    separator: char,
+    twelve_hour: bool,
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Self {
+            order: DateOrder::YearMonthDay,
+            separator: '-',
+            twelve_hour: false,
+        }
+    }
+}
+
+impl Locale {
+    /// The locale from `LC_ALL`, `LC_TIME` or `LANG`, in the order the C library checks them.
+    pub fn from_env() -> Self {
+        ["LC_ALL", "LC_TIME", "LANG"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .map(|tag| Self::from_tag(&tag))
+            .unwrap_or_default()
+    }
+
+    /// Reads a tag like `en_US.UTF-8` or `de-DE`.
+    pub fn from_tag(tag: &str) -> Self {
+        let tag = tag.split(['.', '@']).next().unwrap_or_default();
+        let mut parts = tag.split(['_', '-']);
+        let language = parts.next().unwrap_or_default().to_lowercase();
+        let region = parts.next().unwrap_or_default().to_uppercase();
+
+        if language.is_empty() || language == "c" || language == "posix" {
+            return Self::default();
+        }
+
+        let order = match (language.as_str(), region.as_str()) {
+            ("en", "US") | ("en", "PH") | ("es", "US") => DateOrder::MonthDayYear,
+            ("ja" | "zh" | "ko" | "hu" | "lt" | "sv" | "mn", _) => DateOrder::YearMonthDay,
+            ("en", "CA") | ("fr", "CA") => DateOrder::YearMonthDay,
+            _ => DateOrder::DayMonthYear,
+        };
+        let separator = match (language.as_str(), order) {
+            (_, DateOrder::YearMonthDay) => '-',
+            ("de" | "ru" | "pl" | "cs" | "sk" | "fi" | "nb" | "no" | "da" | "tr" | "uk", _) => {
+                '.'
+            }
+            ("nl", _) => '-',
+            _ => '/',
+        };
+        let twelve_hour = matches!(
+            (language.as_str(), region.as_str()),
+            ("en", "US" | "CA" | "AU" | "NZ" | "IN" | "PH") | ("es", "US" | "MX") | ("hi", _)
+        );
+
+        Self {
+            order,
+            separator,
+            twelve_hour,
+        }
+    }
+
+    fn local(timestamp: u64) -> DateTime<Local> {
+        Local
+            .timestamp_opt(timestamp as i64, 0)
+            .single()
+            .unwrap_or_else(Local::now)
+    }
+
+    /// The calendar date of `timestamp` in local time.
+    pub fn date(&self, timestamp: u64) -> String {
+        let pattern = match self.order {
+            DateOrder::DayMonthYear => format!("%d{0}%m{0}%Y", self.separator),
+            DateOrder::MonthDayYear => format!("%m{0}%d{0}%Y", self.separator),
+            DateOrder::YearMonthDay => format!("%Y{0}%m{0}%d", self.separator),
+        };
+        Self::local(timestamp).format(&pattern).to_string()
+    }
+
+    /// The time of day of `timestamp` in local time.
+    pub fn time(&self, timestamp: u64) -> String {
+        let pattern = if self.twelve_hour { "%-I:%M %p" } else { "%H:%M" };
+        Self::local(timestamp).format(pattern).to_string()
+    }
+
+    /// Date and time of `timestamp`, for tooltips and headers.
+    pub fn absolute(&self, timestamp: u64) -> String {
+        format!("{} {}", self.date(timestamp), self.time(timestamp))
+    }
+
+    /// How long ago `timestamp` was, e.g. "5 minutes ago". Anything older than a week gets its
+    /// date instead.
+    pub fn relative(&self, timestamp: u64, now: u64) -> String {
+        // clocks disagree, so treat the future as now
+        let age = now.saturating_sub(timestamp);
+        let plural = |n: u64, unit: &str| {
+            if n == 1 {
+                format!("1 {} ago", unit)
+            } else {
+                format!("{} {}s ago", n, unit)
+            }
+        };
+
+        if age < MINUTE {
+            "just now".to_string()
+        } else if age < HOUR {
+            plural(age / MINUTE, "minute")
+        } else if age < DAY {
+            plural(age / HOUR, "hour")
+        } else if age < 7 * DAY {
+            plural(age / DAY, "day")
+        } else {
+            self.date(timestamp)
+        }
+    }
+}
+
+/// How long until the relative time of `timestamp` reads differently.
+pub fn next_change(timestamp: u64, now: u64) -> Duration {
+    let age = now.saturating_sub(timestamp);
+    let unit = if age < HOUR {
+        MINUTE
+    } else if age < DAY {
+        HOUR
+    } else {
+        DAY
+    };
+
+    Duration::from_secs(unit - age % unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_pick_order_separator_and_clock() {
+        let us = Locale::from_tag("en_US.UTF-8");
+        assert_eq!(us.order, DateOrder::MonthDayYear);
+        assert_eq!(us.separator, '/');
+        assert!(us.twelve_hour);
+
+        let de = Locale::from_tag("de-DE");
+        assert_eq!(de.order, DateOrder::DayMonthYear);
+        assert_eq!(de.separator, '.');
+        assert!(!de.twelve_hour);
+
+        let ja = Locale::from_tag("ja_JP.UTF-8@calendar");
+        assert_eq!(ja.order, DateOrder::YearMonthDay);
+        assert_eq!(ja.separator, '-');
+
+        assert_eq!(Locale::from_tag("fr_FR").separator, '/');
+        assert_eq!(Locale::from_tag("en_CA").order, DateOrder::YearMonthDay);
+    }
+
+    #[test]
+    fn c_and_empty_tags_get_the_default() {
+        assert_eq!(Locale::from_tag("C"), Locale::default());
+        assert_eq!(Locale::from_tag("POSIX"), Locale::default());
+        assert_eq!(Locale::from_tag(""), Locale::default());
+    }
+
+    #[test]
+    fn relative_times() {
+        let locale = Locale::default();
+        let now = 1_700_000_000;
+
+        assert_eq!(locale.relative(now, now), "just now");
+        assert_eq!(locale.relative(now + 30, now), "just now");
+        assert_eq!(locale.relative(now - 60, now), "1 minute ago");
+        assert_eq!(locale.relative(now - 5 * MINUTE - 59, now), "5 minutes ago");
+        assert_eq!(locale.relative(now - 2 * HOUR, now), "2 hours ago");
+        assert_eq!(locale.relative(now - DAY, now), "1 day ago");
+        assert_eq!(locale.relative(now - 8 * DAY, now), locale.date(now - 8 * DAY));
+    }
+
+    #[test]
+    fn next_change_is_when_the_unit_ticks_over() {
+        let now = 1_700_000_000;
+
+        assert_eq!(next_change(now - 10, now), Duration::from_secs(50));
+        assert_eq!(next_change(now - 2 * MINUTE, now), Duration::from_secs(MINUTE));
+        assert_eq!(next_change(now - HOUR - 1, now), Duration::from_secs(HOUR - 1));
+        assert_eq!(next_change(now - 3 * DAY, now), Duration::from_secs(DAY));
+        assert_eq!(next_change(now + 10, now), Duration::from_secs(MINUTE));
+    }
+}
@@ -0,0 +1,296 @@
+use crate::attachments::{self, Attachment};
+use crate::error::{Error, Result};
+use crate::task::Task;
+use crate::Hoot;
+use eframe::egui::{self, Color32, Ui};
+use std::collections::HashMap;
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use tracing::{debug, error};
+
+/// Images bigger than this on either side aren't decoded at all.
+const MAX_IMAGE_SIDE: u32 = 16384;
+/// Upper bound on what the decoder may allocate, so a tiny file can't expand into gigabytes.
+const MAX_IMAGE_ALLOC: u64 = 256 * 1024 * 1024;
+/// Largest texture we hand to the GPU; bigger images are scaled down for the viewer.
+const MAX_TEXTURE_SIDE: u32 = 4096;
+const THUMBNAIL_SIDE: u32 = 256;
+/// Downloads kept in memory, decoded images and all. Past this, finished ones are dropped
+/// and have to be downloaded again.
+const MAX_DOWNLOADS: usize = 32;
+
+pub struct Downloaded {
+    pub data: Vec<u8>,
+    pub image: Option<DecodedImage>,
+}
+
+pub struct DecodedImage {
+    pub full: egui::ColorImage,
+    pub thumbnail: egui::ColorImage,
+}
+
+/// An image attachment ready to be shown. Textures are only uploaded once they're needed.
+pub struct Preview {
+    image: DecodedImage,
+    thumbnail: Option<egui::TextureHandle>,
+    full: Option<egui::TextureHandle>,
+    zoomed: bool,
+    zoom: f32,
+}
+
+pub enum DownloadState {
+    Downloading(Task<Result<Downloaded>>),
+    Done {
+        data: Vec<u8>,
+        saved_to: Option<PathBuf>,
+        preview: Option<Preview>,
+    },
+    Failed(String),
+}
+
+/// Downloaded attachments, keyed by the hash of their encrypted blob.
+#[derive(Default)]
+pub struct AttachmentsState {
+    pub downloads: HashMap<String, DownloadState>,
+}
+
+/// Decodes an image someone else sent us, within limits that keep a hostile file from eating
+/// all our memory.
+pub fn decode_untrusted(data: &[u8]) -> Result<image::DynamicImage> {
+    let mut limits = image::Limits::default();
+    limits.max_image_width = Some(MAX_IMAGE_SIDE);
+    limits.max_image_height = Some(MAX_IMAGE_SIDE);
+    limits.max_alloc = Some(MAX_IMAGE_ALLOC);
+
+    let mut reader = image::ImageReader::new(Cursor::new(data)).with_guessed_format()?;
+    reader.limits(limits);
+    reader.decode().map_err(|e| Error::Generic(e.to_string()))
+}
+
+pub fn to_color_image(image: &image::DynamicImage) -> egui::ColorImage {
+    let rgba = image.to_rgba8();
+    egui::ColorImage::from_rgba_unmultiplied(
+        [rgba.width() as usize, rgba.height() as usize],
+        rgba.as_raw(),
+    )
+}
+
+/// Where saved attachments go: the user's downloads folder, or ours if they don't have one.
+fn save_dir(storage_dir: &Path) -> PathBuf {
+    directories_next::UserDirs::new()
+        .and_then(|dirs| dirs.download_dir().map(Path::to_path_buf))
+        .unwrap_or_else(|| storage_dir.join("downloads"))
+}
+
+/// `name` in `dir`, or "name (1).ext" and so on if something already has that name.
+fn unused_path(dir: &Path, name: &std::ffi::OsStr) -> PathBuf {
+    let path = dir.join(name);
+    if !path.exists() {
+        return path;
+    }
+
+    let name = Path::new(name);
+    let stem = name.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = name
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    (1..)
+        .map(|n| dir.join(format!("{} ({}){}", stem, n, extension)))
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+fn decode_image(data: &[u8]) -> Result<DecodedImage> {
+    let mut image = decode_untrusted(data)?;
+
+    if image.width() > MAX_TEXTURE_SIDE || image.height() > MAX_TEXTURE_SIDE {
+        image = image.resize(
+            MAX_TEXTURE_SIDE,
+            MAX_TEXTURE_SIDE,
+            image::imageops::FilterType::Triangle,
+        );
+    }
+
+    Ok(DecodedImage {
+        thumbnail: to_color_image(&image.thumbnail(THUMBNAIL_SIDE, THUMBNAIL_SIDE)),
+        full: to_color_image(&image),
+    })
+}
+
+pub struct AttachmentsView {}
+
+impl AttachmentsView {
+    /// Starts downloading `attachment`, making room for it first if needed.
+    fn download(app: &mut Hoot, ctx: &egui::Context, attachment: &Attachment) {
+        let downloads = &mut app.state.attachments.downloads;
+        if downloads.len() >= MAX_DOWNLOADS {
+            downloads.retain(|_, download| matches!(download, DownloadState::Downloading(_)));
+        }
+
+        let to_fetch = attachment.clone();
+        let task = Task::spawn(ctx.clone(), move || {
+            let data = attachments::download(&to_fetch)?;
+            // decode off the UI thread; by now the bytes have been checked against the hashes
+            // in the rumor
+            let image = if to_fetch.mime.starts_with("image/") {
+                decode_image(&data)
+                    .map_err(|e| debug!("no preview for {}: {}", to_fetch.name, e))
+                    .ok()
+            } else {
+                None
+            };
+            Ok(Downloaded { data, image })
+        });
+        downloads.insert(attachment.sha256.clone(), DownloadState::Downloading(task));
+    }
+
+    pub fn ui(app: &mut Hoot, ui: &mut Ui, attachments: &[Attachment]) {
+        if attachments.is_empty() {
+            return;
+        }
+
+        ui.separator();
+        ui.label(format!("Attachments ({})", attachments.len()));
+
+        for attachment in attachments {
+            Self::poll(app, attachment);
+
+            ui.horizontal(|ui| {
+                ui.strong(&attachment.name);
+                ui.label(format!("{} · {} bytes", attachment.mime, attachment.size));
+
+                let storage_dir = app.storage_dir.clone();
+                let mut download = false;
+                match app.state.attachments.downloads.get_mut(&attachment.sha256) {
+                    None => {
+                        download = ui.button("Download").clicked();
+                    }
+                    Some(DownloadState::Downloading(_)) => {
+                        ui.spinner();
+                    }
+                    Some(DownloadState::Done { data, saved_to, .. }) => {
+                        ui.colored_label(Color32::DARK_GREEN, "✔ Verified");
+                        match saved_to {
+                            Some(path) => {
+                                ui.label(format!("Saved to {}", path.display()));
+                            }
+                            None => {
+                                if ui.button("Save").clicked() {
+                                    let dir = save_dir(&storage_dir);
+                                    // the name comes from the sender, so keep it inside `dir`
+                                    let name = Path::new(&attachment.name)
+                                        .file_name()
+                                        .map(|n| n.to_os_string())
+                                        .unwrap_or_else(|| attachment.sha256.clone().into());
+                                    let path = unused_path(&dir, &name);
+                                    // create_new, so a file that shows up meanwhile is left be
+                                    let written = std::fs::create_dir_all(&dir).and_then(|_| {
+                                        std::fs::OpenOptions::new()
+                                            .write(true)
+                                            .create_new(true)
+                                            .open(&path)?
+                                            .write_all(data.as_slice())
+                                    });
+                                    match written {
+                                        Ok(()) => *saved_to = Some(path),
+                                        Err(e) => {
+                                            error!("could not save {}: {}", attachment.name, e)
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    Some(DownloadState::Failed(e)) => {
+                        ui.colored_label(Color32::RED, format!("⊗ {}", e));
+                        download = ui.button("Retry").clicked();
+                    }
+                }
+                if download {
+                    Self::download(app, ui.ctx(), attachment);
+                }
+            });
+
+            if let Some(DownloadState::Done {
+                preview: Some(preview),
+                ..
+            }) = app.state.attachments.downloads.get_mut(&attachment.sha256)
+            {
+                Self::preview(ui, attachment, preview);
+            }
+        }
+    }
+
+    fn preview(ui: &mut Ui, attachment: &Attachment, preview: &mut Preview) {
+        let ctx = ui.ctx().clone();
+        let thumbnail = preview.thumbnail.get_or_insert_with(|| {
+            ctx.load_texture(
+                format!("{}-thumbnail", attachment.sha256),
+                preview.image.thumbnail.clone(),
+                egui::TextureOptions::default(),
+            )
+        });
+
+        let clicked = ui
+            .add(egui::ImageButton::new(
+                egui::load::SizedTexture::from_handle(thumbnail),
+            ))
+            .on_hover_text("Click to zoom")
+            .clicked();
+        if clicked {
+            preview.zoomed = true;
+        }
+
+        if !preview.zoomed {
+            return;
+        }
+
+        let full = preview.full.get_or_insert_with(|| {
+            ctx.load_texture(
+                attachment.sha256.clone(),
+                preview.image.full.clone(),
+                egui::TextureOptions::default(),
+            )
+        });
+        let zoom = &mut preview.zoom;
+        egui::Window::new(&attachment.name)
+            .id(egui::Id::new(("attachment_zoom", &attachment.sha256)))
+            .open(&mut preview.zoomed)
+            .show(&ctx, |ui| {
+                ui.add(egui::Slider::new(zoom, 0.1..=4.0).text("Zoom"));
+                egui::ScrollArea::both().show(ui, |ui| {
+                    ui.add(egui::Image::new(egui::load::SizedTexture::new(
+                        full.id(),
+                        full.size_vec2() * *zoom,
+                    )));
+                });
+            });
+    }
+
+    fn poll(app: &mut Hoot, attachment: &Attachment) {
+        let downloads = &mut app.state.attachments.downloads;
+        let finished = match downloads.get(&attachment.sha256) {
+            Some(DownloadState::Downloading(task)) => task.take(),
+            _ => None,
+        };
+
+        if let Some(result) = finished {
+            let state = match result {
+                Ok(downloaded) => DownloadState::Done {
+                    data: downloaded.data,
+                    saved_to: None,
+                    preview: downloaded.image.map(|image| Preview {
+                        image,
+                        thumbnail: None,
+                        full: None,
+                        zoomed: false,
+                        zoom: 1.0,
+                    }),
+                },
+                Err(e) => DownloadState::Failed(e.to_string()),
+            };
+            downloads.insert(attachment.sha256.clone(), state);
+        }
+    }
+}
@@ -1,15 +1,273 @@
-use crate::mail_event::MailMessage;
-use crate::relay::ClientMessage;
+use crate::attachments;
+use crate::drafts::Draft;
+use crate::mail_event::{Difficulty, MailMessage, Mining};
+use crate::nip05::{self, Nip05Cache, Verification};
+use crate::relay::{PublishStatus, RelayPool};
+use crate::signature;
+use crate::task::Task;
+use crate::ui::contacts::AddressField;
+use crate::templates::{self, Template};
+use crate::ui::composer::{Composer, ComposerState};
+use crate::ui::markdown::MarkdownView;
 use eframe::egui::{self, RichText};
-use nostr::{Keys, PublicKey};
+use nostr::nips::nip59::UnwrappedGift;
+use nostr::{Event, EventId, Keys, PublicKey, TagKind, Timestamp, ToBech32};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tracing::{debug, error, info};
 
+/// How long the user has to stop typing before the draft gets written to disk.
+const AUTOSAVE_DELAY: Duration = Duration::from_secs(2);
+
+/// How long relays get to accept sent mail before the window gives up waiting on them.
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(30);
+
 #[derive(Debug, Clone)]
 pub struct ComposeWindowState {
+    pub draft_id: String,
     pub subject: String,
     pub to_field: String,
+    pub cc_field: String,
     pub content: String,
     pub selected_account: Option<Keys>,
+    pub attachments: Vec<PathBuf>,
+    attachment_path: String,
+    show_preview: bool,
+    composer: ComposerState,
+    // account whose signature is currently in the body, and what that signature was
+    signed_with: Option<PublicKey>,
+    signature: String,
+    // set whenever the user edits something that hasn't been saved as a draft yet
+    last_edit: Option<Instant>,
+    // uploads attachments and wraps the message off the UI thread
+    sending: Option<Task<crate::error::Result<HashMap<PublicKey, Event>>>>,
+    // proof of work being done for `sending`, and how many hashes it should take
+    mining: Option<(Arc<Mining>, u64)>,
+    // gift wraps handed to relays, and when, until a relay accepts each of them
+    confirming: Option<(Vec<EventId>, Instant)>,
+    // gift wraps built for what's in the window now, sent again as they are on a retry so
+    // nobody gets the mail twice
+    signed: Option<HashMap<PublicKey, Event>>,
+    send_error: Option<String>,
+}
+
+impl ComposeWindowState {
+    pub fn new() -> Self {
+        Self {
+            draft_id: Draft::new_id(),
+            subject: String::new(),
+            to_field: String::new(),
+            cc_field: String::new(),
+            content: String::new(),
+            selected_account: None,
+            attachments: Vec::new(),
+            attachment_path: String::new(),
+            show_preview: false,
+            composer: ComposerState::default(),
+            signed_with: None,
+            signature: String::new(),
+            last_edit: None,
+            sending: None,
+            mining: None,
+            confirming: None,
+            signed: None,
+            send_error: None,
+        }
+    }
+
+    /// Reopens a draft. `signature` is the account's current signature, which the draft most
+    /// likely already contains.
+    pub fn from_draft(account: Keys, draft: &Draft, signature: &str) -> Self {
+        Self {
+            draft_id: draft.id.clone(),
+            subject: draft.subject.clone(),
+            to_field: draft.to_field.clone(),
+            cc_field: draft.cc_field.clone(),
+            content: draft.content.clone(),
+            signed_with: Some(account.public_key()),
+            signature: signature.to_string(),
+            selected_account: Some(account),
+            ..Self::new()
+        }
+    }
+
+    /// A reply to `gift` sent from `account`, with the original quoted below the signature.
+    pub fn reply(account: Keys, gift: &UnwrappedGift, signature: &str) -> Self {
+        let subject = gift
+            .rumor
+            .tags
+            .find(TagKind::Subject)
+            .and_then(|t| t.content())
+            .unwrap_or_default();
+        let subject = if subject.to_lowercase().starts_with("re:") {
+            subject.to_string()
+        } else {
+            format!("Re: {}", subject)
+        };
+
+        let sender = gift.sender.to_bech32().unwrap();
+        let quoted: Vec<String> = signature::strip(&gift.rumor.content)
+            .lines()
+            .map(|line| format!("> {}", line).trim_end().to_string())
+            .collect();
+
+        Self {
+            subject,
+            to_field: sender.clone(),
+            content: format!(
+                "{}\n\n{} wrote:\n{}",
+                signature::block(signature),
+                sender,
+                quoted.join("\n")
+            ),
+            signed_with: Some(account.public_key()),
+            signature: signature.to_string(),
+            selected_account: Some(account),
+            ..Self::new()
+        }
+    }
+
+    pub fn to_draft(&self) -> Draft {
+        Draft {
+            id: self.draft_id.clone(),
+            subject: self.subject.clone(),
+            to_field: self.to_field.clone(),
+            cc_field: self.cc_field.clone(),
+            content: self.content.clone(),
+            updated_at: 0,
+            deleted: false,
+        }
+    }
+
+    /// Whether mail from this window is on its way out, so closing it would lose track of it.
+    fn in_flight(&self) -> bool {
+        self.sending.is_some() || self.confirming.is_some()
+    }
+
+    /// Hands the gift wraps to relays and starts waiting for them to be accepted.
+    fn publish(&mut self, relays: &mut RelayPool, events: &HashMap<PublicKey, Event>) {
+        let mut failed = None;
+        for event in events.values() {
+            if let Err(e) = relays.publish(event) {
+                error!("could not send event to relays: {}", e);
+                failed = Some(e);
+            }
+        }
+        match failed {
+            Some(e) => self.send_error = Some(e.to_string()),
+            None => {
+                let ids = events.values().map(|e| e.id).collect();
+                self.confirming = Some((ids, Instant::now()));
+            }
+        }
+    }
+
+    /// Whether the user has edited this window since it was last saved.
+    pub fn has_unsaved_changes(&self) -> bool {
+        self.last_edit.is_some()
+    }
+
+    /// Takes on a newer copy of this window's draft, e.g. one synced from another device.
+    pub fn apply_draft(&mut self, draft: &Draft) {
+        self.subject = draft.subject.clone();
+        self.to_field = draft.to_field.clone();
+        self.cc_field = draft.cc_field.clone();
+        self.content = draft.content.clone();
+    }
+}
+
+/// One address typed into the To field.
+enum Recipient {
+    Key(PublicKey),
+    Nip05(String, Verification),
+    Invalid(String),
+}
+
+/// Every address in the To field, given as npubs, hex or NIP-05 identifiers. Identifiers that
+/// haven't been looked up yet start resolving in the background.
+fn recipients(ctx: &egui::Context, nip05: &mut Nip05Cache, to_field: &str) -> Vec<Recipient> {
+    let mut recipients = Vec::new();
+    for key_string in to_field.split_whitespace() {
+        if nip05::is_identifier(key_string) {
+            let verification = nip05.resolve(ctx, key_string);
+            recipients.push(Recipient::Nip05(key_string.to_string(), verification));
+            continue;
+        }
+
+        use nostr::FromBech32;
+        match PublicKey::from_bech32(key_string) {
+            Ok(k) => {
+                recipients.push(Recipient::Key(k));
+                continue;
+            }
+            Err(e) => debug!("could not parse public key as bech32: {}", e),
+        };
+
+        match PublicKey::from_hex(key_string) {
+            Ok(k) => recipients.push(Recipient::Key(k)),
+            Err(e) => {
+                debug!("could not parse public key as hex: {}", e);
+                recipients.push(Recipient::Invalid(key_string.to_string()));
+            }
+        };
+    }
+
+    recipients
+}
+
+/// The keys every address resolved to so far.
+fn resolved_keys(recipients: &[Recipient]) -> Vec<PublicKey> {
+    recipients
+        .iter()
+        .filter_map(|r| match r {
+            Recipient::Key(k) | Recipient::Nip05(_, Verification::Verified(k)) => Some(*k),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Shows what the NIP-05 identifiers among `recipients` resolved to.
+fn recipient_status(ui: &mut egui::Ui, recipients: &[Recipient]) {
+    for recipient in recipients {
+        let Recipient::Nip05(identifier, verification) = recipient else {
+            continue;
+        };
+        ui.horizontal(|ui| {
+            ui.small(identifier);
+            match verification {
+                Verification::Pending => {
+                    ui.spinner();
+                    ui.small("Looking up...");
+                }
+                Verification::Verified(pubkey) => {
+                    ui.colored_label(
+                        egui::Color32::DARK_GREEN,
+                        format!("✔ {}", pubkey.to_bech32().unwrap()),
+                    );
+                }
+                Verification::Failed(e) => {
+                    ui.colored_label(egui::Color32::RED, format!("✘ {}", e));
+                }
+            }
+        });
+    }
+}
+
+/// Why the message can't go out to these addresses yet, if there's a reason.
+fn unsendable(recipients: &[Recipient]) -> Option<String> {
+    recipients.iter().find_map(|r| match r {
+        Recipient::Key(_) | Recipient::Nip05(_, Verification::Verified(_)) => None,
+        Recipient::Nip05(id, Verification::Pending) => Some(format!("still looking up {}", id)),
+        Recipient::Nip05(id, Verification::Failed(e)) => {
+            Some(format!("could not resolve {}: {}", id, e))
+        }
+        Recipient::Invalid(token) => Some(format!(
+            "{} is not a public key or NIP-05 identifier",
+            token
+        )),
+    })
 }
 
 pub struct ComposeWindow {}
@@ -21,20 +279,51 @@ impl ComposeWindow {
             .compose_window
             .get_mut(&id)
             .expect("no state found for id");
-        egui::Window::new(&state.subject)
+        let mut open = true;
+        let mut sent = false;
+        let mut edited = false;
+        let mut chosen_template: Option<Template> = None;
+        let window = egui::Window::new(&state.subject)
             .id(id)
+            .open(&mut open)
             .show(ui.ctx(), |ui| {
                 ui.label("Hello!");
                 ui.vertical(|ui| {
+                    let contacts = app.contacts.all();
                     ui.horizontal(|ui| {
                         ui.label("To:");
-                        ui.text_edit_singleline(&mut state.to_field);
+                        edited |= AddressField::ui(
+                            ui,
+                            id.with("to"),
+                            &mut state.to_field,
+                            &contacts,
+                            &app.ndb,
+                            &mut app.profiles,
+                            &mut app.nip05,
+                            &mut app.avatars,
+                        );
+                    });
+                    let to = recipients(ui.ctx(), &mut app.nip05, &state.to_field);
+                    recipient_status(ui, &to);
+                    ui.horizontal(|ui| {
+                        ui.label("CC:");
+                        edited |= AddressField::ui(
+                            ui,
+                            id.with("cc"),
+                            &mut state.cc_field,
+                            &contacts,
+                            &app.ndb,
+                            &mut app.profiles,
+                            &mut app.nip05,
+                            &mut app.avatars,
+                        );
                     });
+                    let cc = recipients(ui.ctx(), &mut app.nip05, &state.cc_field);
+                    recipient_status(ui, &cc);
 
                     {
                         // god this is such a fucking mess
                         let accounts = app.account_manager.loaded_keys.clone();
-                        use nostr::ToBech32;
                         let mut formatted_key = String::new();
                         if state.selected_account.is_some() {
                             formatted_key = state
@@ -50,67 +339,393 @@ impl ComposeWindow {
                             .selected_text(format!("{}", formatted_key))
                             .show_ui(ui, |ui| {
                                 for key in accounts {
-                                    ui.selectable_value(
-                                        &mut state.selected_account,
-                                        Some(key.clone()),
-                                        key.public_key().to_bech32().unwrap(),
-                                    );
+                                    edited |= ui
+                                        .selectable_value(
+                                            &mut state.selected_account,
+                                            Some(key.clone()),
+                                            key.public_key().to_bech32().unwrap(),
+                                        )
+                                        .changed();
                                 }
                             });
                     }
 
+                    // picking a different account brings its signature along
+                    let selected = state.selected_account.as_ref().map(|k| k.public_key());
+                    if let Some(pubkey) = selected.filter(|pk| state.signed_with != Some(*pk)) {
+                        let signature = app.preferences.signature(&pubkey).to_string();
+                        signature::replace(&mut state.content, &state.signature, &signature);
+                        state.signed_with = Some(pubkey);
+                        state.signature = signature;
+                    }
+
                     ui.horizontal(|ui| {
                         ui.label("Subject:");
-                        ui.text_edit_singleline(&mut state.subject);
+                        edited |= ui.text_edit_singleline(&mut state.subject).changed();
                     });
-                    ui.label("Body:");
-                    ui.text_edit_multiline(&mut state.content);
+                    ui.horizontal(|ui| {
+                        ui.label("Body:");
+                        ui.checkbox(&mut state.show_preview, "Preview");
+                        ui.menu_button("Insert Template", |ui| {
+                            if app.private.templates.is_empty() {
+                                ui.label("No templates yet. Add some in Settings.");
+                            }
+                            for template in &app.private.templates {
+                                if ui.button(&template.name).clicked() {
+                                    chosen_template = Some(template.clone());
+                                    ui.close_menu();
+                                }
+                            }
+                        });
+                        if ui.button("Save as Template").clicked() {
+                            let name = if state.subject.is_empty() {
+                                "Untitled".to_string()
+                            } else {
+                                state.subject.clone()
+                            };
+                            app.private.templates.push(Template {
+                                name,
+                                subject: state.subject.clone(),
+                                body: signature::strip(&state.content).to_string(),
+                            });
+                        }
+                    });
+                    let body_id = id.with("body");
+                    let spell_checker = app.spell_checker.as_ref();
+                    if state.show_preview {
+                        ui.columns(2, |columns| {
+                            edited |= Composer::ui(
+                                &mut columns[0],
+                                body_id,
+                                &mut state.content,
+                                &mut state.composer,
+                                spell_checker,
+                            );
+                            MarkdownView::preview(
+                                &mut columns[1],
+                                &mut app.state.markdown,
+                                &state.content,
+                            );
+                        });
+                    } else {
+                        edited |= Composer::ui(
+                            ui,
+                            body_id,
+                            &mut state.content,
+                            &mut state.composer,
+                            spell_checker,
+                        );
+                    }
+
+                    ui.label("Attachments:");
+                    let mut attachment_to_remove: Option<usize> = None;
+                    for (index, path) in state.attachments.iter().enumerate() {
+                        ui.horizontal(|ui| {
+                            ui.label(path.display().to_string());
+                            if ui.small_button("Remove").clicked() {
+                                attachment_to_remove = Some(index);
+                            }
+                        });
+                    }
+                    if let Some(index) = attachment_to_remove {
+                        state.attachments.remove(index);
+                        state.signed = None;
+                    }
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(&mut state.attachment_path);
+                        if ui.button("Attach").clicked() && !state.attachment_path.is_empty() {
+                            state
+                                .attachments
+                                .push(PathBuf::from(state.attachment_path.trim()));
+                            state.attachment_path = String::new();
+                            state.signed = None;
+                        }
+                    });
+                    ui.small("Or drop files onto this window.");
+                    if !state.attachments.is_empty() && app.preferences.blob_server.trim().is_empty()
+                    {
+                        ui.colored_label(
+                            egui::Color32::RED,
+                            "Set an attachment server in settings to send attachments.",
+                        );
+                    }
+
+                    if state.selected_account.is_none() {
+                        ui.small("Select an account to save this message as a draft.");
+                    }
+
+                    if let Some(e) = &state.send_error {
+                        ui.colored_label(egui::Color32::RED, format!("Could not send: {}", e));
+                    }
+
+                    // the mail goes out to every relay we're connected to, so it has to get
+                    // past all of them
+                    let relay_pow = app.nip11.min_pow(&app.relays.connected_urls());
+                    let wrap_pow = app.preferences.send_pow.max(relay_pow);
+                    let difficulty = Difficulty {
+                        wrap: wrap_pow,
+                        seal: if app.preferences.send_pow_seal {
+                            wrap_pow
+                        } else {
+                            0
+                        },
+                    };
+                    if state.sending.is_none() && wrap_pow > 0 {
+                        let note = if relay_pow > app.preferences.send_pow {
+                            format!("{} bits of proof of work, as your relays ask", wrap_pow)
+                        } else {
+                            format!("{} bits of proof of work", wrap_pow)
+                        };
+                        ui.small(note);
+                    }
 
-                    if ui.button("Send").clicked() {
+                    if let Some((mining, expected)) = &state.mining {
+                        let attempts = mining.attempts();
+                        ui.horizontal(|ui| {
+                            ui.add(
+                                egui::ProgressBar::new(
+                                    (attempts as f32 / *expected as f32).min(0.99),
+                                )
+                                .desired_width(200.0)
+                                .text(format!("Proof of work: {} hashes", attempts)),
+                            );
+                            if ui.button("Cancel").clicked() {
+                                mining.cancel();
+                            }
+                        });
+                        // the progress bar only moves when something repaints
+                        ui.ctx().request_repaint_after(Duration::from_millis(100));
+                    } else if state.sending.is_some() || state.confirming.is_some() {
+                        ui.horizontal(|ui| {
+                            ui.spinner();
+                            ui.label("Sending...");
+                        });
+                        ui.small("The window stays open until a relay has taken the mail.");
+                    } else if ui.button("Send").clicked() {
                         if state.selected_account.is_none() {
                             error!("No Account Selected!");
                             return;
                         }
-                        // convert to field into PublicKey object
-                        let to_field = state.to_field.clone();
-
-                        let mut recipient_keys: Vec<PublicKey> = Vec::new();
-                        for key_string in to_field.split_whitespace() {
-                            use nostr::FromBech32;
-                            match PublicKey::from_bech32(key_string) {
-                                Ok(k) => recipient_keys.push(k),
-                                Err(e) => debug!("could not parse public key as bech32: {}", e),
-                            };
-
-                            match PublicKey::from_hex(key_string) {
-                                Ok(k) => recipient_keys.push(k),
-                                Err(e) => debug!("could not parse public key as hex: {}", e),
-                            };
+                        if let Some(events) = state.signed.take() {
+                            // a retry, so the same copies go out again
+                            state.send_error = None;
+                            state.publish(&mut app.relays, &events);
+                            state.signed = Some(events);
+                            return;
+                        }
+                        if let Some(reason) = unsendable(&to).or_else(|| unsendable(&cc)) {
+                            state.send_error = Some(reason);
+                            return;
                         }
-
                         let mut msg = MailMessage {
-                            to: recipient_keys,
-                            cc: vec![],
+                            to: resolved_keys(&to),
+                            cc: resolved_keys(&cc),
                             bcc: vec![],
                             subject: state.subject.clone(),
                             content: state.content.clone(),
+                            attachments: vec![],
+                            auto_submitted: false,
                         };
-                        let events_to_send =
-                            msg.to_events(&state.selected_account.clone().unwrap());
-
-                        info!("new events! {:?}", events_to_send);
-                        // send over wire
-                        for event in events_to_send {
-                            match serde_json::to_string(&ClientMessage::Event { event: event.1 }) {
-                                Ok(v) => match app.relays.send(ewebsock::WsMessage::Text(v)) {
-                                    Ok(r) => r,
-                                    Err(e) => error!("could not send event to relays: {}", e),
-                                },
-                                Err(e) => error!("could not serialize event: {}", e),
-                            };
+                        let keys = state.selected_account.clone().unwrap();
+                        let paths = state.attachments.clone();
+                        let server = if paths.is_empty() {
+                            None
+                        } else {
+                            match attachments::blob_server(&app.preferences.blob_server) {
+                                Ok(server) => Some(server),
+                                Err(e) => {
+                                    state.send_error = Some(e.to_string());
+                                    return;
+                                }
+                            }
+                        };
+                        state.send_error = None;
+                        let mining = Arc::new(Mining::default());
+                        if difficulty != Difficulty::default() {
+                            let expected = difficulty.expected_attempts(msg.recipients().len());
+                            state.mining = Some((mining.clone(), expected.max(1)));
                         }
+                        state.sending = Some(Task::spawn(ui.ctx().clone(), move || {
+                            if let Some(server) = &server {
+                                for path in &paths {
+                                    msg.attachments.push(attachments::upload_file(
+                                        server.as_ref(),
+                                        &keys,
+                                        path,
+                                    )?);
+                                }
+                            }
+                            if difficulty == Difficulty::default() {
+                                Ok(msg.to_events(&keys))
+                            } else {
+                                msg.to_events_with_pow(&keys, difficulty, &mining)
+                            }
+                        }));
                     }
                 });
             });
+
+        if let Some(template) = chosen_template {
+            let recipients = resolved_keys(&recipients(ui.ctx(), &mut app.nip05, &state.to_field));
+            // names we don't know yet resolve to short npubs now and properly next time
+            app.profiles
+                .fetch(&mut app.relays, &app.ndb, recipients.iter().copied());
+
+            let values = templates::Values {
+                recipient_names: recipients
+                    .iter()
+                    .map(|pk| app.profiles.display_name(&app.ndb, pk))
+                    .collect(),
+                recipient_npubs: recipients.iter().map(|pk| pk.to_bech32().unwrap()).collect(),
+                sender_name: state
+                    .selected_account
+                    .as_ref()
+                    .map(|k| app.profiles.display_name(&app.ndb, &k.public_key()))
+                    .unwrap_or_default(),
+                date: app.locale.date(Timestamp::now().as_u64()),
+            };
+            let (subject, body) = template.fill(&values);
+            if state.subject.is_empty() {
+                state.subject = subject;
+            }
+            Composer::insert(
+                ui.ctx(),
+                id.with("body"),
+                &mut state.content,
+                &mut state.composer,
+                &body,
+            );
+            edited = true;
+        }
+
+        // files dropped while hovering this window get attached to it
+        if let Some(window) = window {
+            let dropped = ui.ctx().input(|i| {
+                let hovered = i
+                    .pointer
+                    .hover_pos()
+                    .is_some_and(|pos| window.response.rect.contains(pos));
+                if hovered {
+                    i.raw.dropped_files.clone()
+                } else {
+                    Vec::new()
+                }
+            });
+            for file in dropped {
+                if let Some(path) = file.path {
+                    state.attachments.push(path);
+                    state.signed = None;
+                }
+            }
+        }
+
+        if let Some(result) = state.sending.as_ref().and_then(|task| task.take()) {
+            state.sending = None;
+            state.mining = None;
+            match result {
+                Ok(events_to_send) => {
+                    info!("new events! {:?}", events_to_send);
+                    // send over wire
+                    state.publish(&mut app.relays, &events_to_send);
+                    state.signed = Some(events_to_send);
+                }
+                Err(e) => {
+                    error!("could not send message: {}", e);
+                    state.send_error = Some(e.to_string());
+                }
+            }
+        }
+
+        // the mail only counts as sent once a relay has taken every copy of it
+        if let Some((ids, sent_at)) = state.confirming.clone() {
+            let statuses: Vec<Vec<PublishStatus>> = ids
+                .iter()
+                .map(|id| {
+                    app.relays
+                        .publish_status(id)
+                        .map(|s| s.values().cloned().collect())
+                        .unwrap_or_default()
+                })
+                .collect();
+            let accepted = statuses
+                .iter()
+                .all(|s| s.contains(&PublishStatus::Accepted));
+            let rejected = statuses.iter().find_map(|s| {
+                let reasons: Vec<&str> = s
+                    .iter()
+                    .filter_map(|status| match status {
+                        PublishStatus::Rejected(reason) => Some(reason.as_str()),
+                        _ => None,
+                    })
+                    .collect();
+                (!s.is_empty() && reasons.len() == s.len()).then(|| reasons.join(", "))
+            });
+
+            if accepted {
+                state.confirming = None;
+                sent = true;
+            } else if let Some(reasons) = rejected {
+                state.confirming = None;
+                state.send_error = Some(format!("Every relay rejected it: {}", reasons));
+            } else if sent_at.elapsed() >= CONFIRM_TIMEOUT {
+                state.confirming = None;
+                state.send_error = Some("No relay confirmed that it got the message".to_string());
+            } else {
+                ui.ctx().request_repaint_after(Duration::from_millis(250));
+            }
+        }
+
+        if edited {
+            state.last_edit = Some(Instant::now());
+            state.signed = None;
+        }
+
+        // closing now would lose track of mail that may already be delivered
+        if !open && state.in_flight() {
+            open = true;
+        }
+
+        if sent {
+            let keys = state.selected_account.clone().unwrap();
+            // nothing to take back if the draft never got saved
+            if app.drafts.contains(&keys.public_key(), &state.draft_id) {
+                match app.drafts.delete(&app.local_store, &keys, &state.draft_id) {
+                    Ok(tombstone) => crate::drafts::publish(&mut app.relays, &keys, &tombstone),
+                    Err(e) => error!("could not delete draft after sending: {}", e),
+                }
+            }
+            app.state.compose_window.remove(&id);
+            return;
+        }
+
+        // save once the user pauses typing, or right away if the window is being closed
+        if let Some(last_edit) = state.last_edit {
+            let settled = last_edit.elapsed() >= AUTOSAVE_DELAY;
+            if let Some(keys) = &state.selected_account {
+                if settled || !open {
+                    let accounts = &app.account_manager.loaded_keys;
+                    match app
+                        .drafts
+                        .save(&app.local_store, accounts, keys, state.to_draft())
+                    {
+                        Ok((draft, tombstones)) => {
+                            crate::drafts::publish(&mut app.relays, keys, &draft);
+                            for (previous, tombstone) in &tombstones {
+                                crate::drafts::publish(&mut app.relays, previous, tombstone);
+                            }
+                            state.last_edit = None;
+                        }
+                        Err(e) => error!("could not save draft: {}", e),
+                    }
+                } else {
+                    ui.ctx()
+                        .request_repaint_after(AUTOSAVE_DELAY.saturating_sub(last_edit.elapsed()));
+                }
+            }
+        }
+
+        if !open {
+            app.state.compose_window.remove(&id);
+        }
     }
 }
@@ -0,0 +1,335 @@
+use eframe::egui::{self, Color32, KeyboardShortcut, Modifiers, RichText, Stroke, Ui};
+use egui::text::{CCursor, CCursorRange, LayoutJob, TextFormat};
+use egui::widgets::text_edit::TextEditState;
+use std::time::{Duration, Instant};
+
+/// Typing within this long of the previous edit is undone together with it.
+const UNDO_GROUP: Duration = Duration::from_secs(1);
+const MAX_UNDO: usize = 100;
+
+const BOLD: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, egui::Key::B);
+const ITALIC: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, egui::Key::I);
+const CODE: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, egui::Key::E);
+const UNDO: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, egui::Key::Z);
+const REDO: KeyboardShortcut =
+    KeyboardShortcut::new(Modifiers::COMMAND.plus(Modifiers::SHIFT), egui::Key::Z);
+
+/// Decides which words the composer underlines as misspelled.
+pub trait SpellChecker {
+    fn is_correct(&self, word: &str) -> bool;
+}
+
+/// Treats every word as correct, for when no dictionary is available.
+pub struct NoSpellCheck;
+
+impl SpellChecker for NoSpellCheck {
+    fn is_correct(&self, _word: &str) -> bool {
+        true
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Bold,
+    Italic,
+    Code,
+    List,
+    Quote,
+}
+
+/// Undo history for one composer.
+#[derive(Debug, Clone, Default)]
+pub struct ComposerState {
+    undo: Vec<String>,
+    redo: Vec<String>,
+    last_edit: Option<Instant>,
+}
+
+impl ComposerState {
+    fn checkpoint(&mut self, text: String) {
+        self.undo.push(text);
+        if self.undo.len() > MAX_UNDO {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+}
+
+fn byte_index(text: &str, char_index: usize) -> usize {
+    text.char_indices()
+        .nth(char_index)
+        .map_or(text.len(), |(i, _)| i)
+}
+
+type Selection = std::ops::Range<usize>;
+
+/// Surrounds the selection with `marker`, keeping the same text selected.
+fn wrap(text: &str, selection: Selection, marker: &str) -> (String, Selection) {
+    let start = byte_index(text, selection.start);
+    let end = byte_index(text, selection.end);
+    let new_text = format!(
+        "{}{}{}{}{}",
+        &text[..start],
+        marker,
+        &text[start..end],
+        marker,
+        &text[end..]
+    );
+    let shift = marker.chars().count();
+
+    (new_text, selection.start + shift..selection.end + shift)
+}
+
+/// Puts the selection in a fenced code block and selects the whole block.
+fn fence(text: &str, selection: Selection) -> (String, Selection) {
+    let start = byte_index(text, selection.start);
+    let end = byte_index(text, selection.end);
+    let block = format!("```\n{}\n```", &text[start..end]);
+    let new_text = format!("{}{}{}", &text[..start], block, &text[end..]);
+
+    (
+        new_text,
+        selection.start..selection.start + block.chars().count(),
+    )
+}
+
+/// Prefixes every line the selection touches and selects those lines.
+fn prefix_lines(text: &str, selection: Selection, prefix: &str) -> (String, Selection) {
+    let start = byte_index(text, selection.start);
+    let end = byte_index(text, selection.end);
+    let line_start = text[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = text[end..].find('\n').map_or(text.len(), |i| end + i);
+
+    let lines: Vec<String> = text[line_start..line_end]
+        .split('\n')
+        .map(|line| format!("{}{}", prefix, line))
+        .collect();
+    let block = lines.join("\n");
+    let new_text = format!("{}{}{}", &text[..line_start], block, &text[line_end..]);
+    let first = text[..line_start].chars().count();
+
+    (new_text, first..first + block.chars().count())
+}
+
+/// Applies `format` to the selected characters, returning the new text and what to select in it.
+fn apply(format: Format, text: &str, selection: Selection) -> (String, Selection) {
+    let multiline = {
+        let start = byte_index(text, selection.start);
+        let end = byte_index(text, selection.end);
+        text[start..end].contains('\n')
+    };
+
+    match format {
+        Format::Bold => wrap(text, selection, "**"),
+        Format::Italic => wrap(text, selection, "_"),
+        Format::Code if multiline => fence(text, selection),
+        Format::Code => wrap(text, selection, "`"),
+        Format::List => prefix_lines(text, selection, "- "),
+        Format::Quote => prefix_lines(text, selection, "> "),
+    }
+}
+
+/// Lays out the text with misspelled words underlined.
+fn spell_checked(ui: &Ui, text: &str, checker: &dyn SpellChecker) -> LayoutJob {
+    let font_id = egui::TextStyle::Body.resolve(ui.style());
+    let plain = TextFormat::simple(font_id.clone(), ui.visuals().text_color());
+    let misspelled = TextFormat {
+        underline: Stroke::new(1.0, Color32::RED),
+        ..plain.clone()
+    };
+
+    let mut job = LayoutJob::default();
+    let mut word_start: Option<usize> = None;
+    let flush = |job: &mut LayoutJob, range: std::ops::Range<usize>, is_word: bool| {
+        let word = &text[range];
+        let format = if is_word && !checker.is_correct(word) {
+            misspelled.clone()
+        } else {
+            plain.clone()
+        };
+        job.append(word, 0.0, format);
+    };
+
+    let mut last = 0;
+    for (i, c) in text.char_indices() {
+        let is_word_char = c.is_alphabetic() || c == '\'';
+        match (word_start, is_word_char) {
+            (None, true) => {
+                flush(&mut job, last..i, false);
+                word_start = Some(i);
+                last = i;
+            }
+            (Some(s), false) => {
+                flush(&mut job, s..i, true);
+                word_start = None;
+                last = i;
+            }
+            _ => {}
+        }
+    }
+    match word_start {
+        Some(s) => flush(&mut job, s..text.len(), true),
+        None => flush(&mut job, last..text.len(), false),
+    }
+
+    job
+}
+
+pub struct Composer {}
+
+impl Composer {
+    /// Inserts `snippet` at the cursor of the composer with `id`, or at the very start if it
+    /// hasn't been clicked into yet.
+    pub fn insert(
+        ctx: &egui::Context,
+        id: egui::Id,
+        text: &mut String,
+        state: &mut ComposerState,
+        snippet: &str,
+    ) {
+        let mut edit_state = TextEditState::load(ctx, id).unwrap_or_default();
+        let at = edit_state
+            .cursor
+            .char_range()
+            .map_or(0, |range| range.sorted()[0].index);
+
+        let mut new_text = text.clone();
+        new_text.insert_str(byte_index(text, at), snippet);
+        state.checkpoint(std::mem::replace(text, new_text));
+        state.last_edit = None;
+
+        let end = CCursor::new(at + snippet.chars().count());
+        edit_state
+            .cursor
+            .set_char_range(Some(CCursorRange::one(end)));
+        edit_state.store(ctx, id);
+    }
+
+    /// A multiline markdown editor with a formatting toolbar. Returns whether `text` changed.
+    pub fn ui(
+        ui: &mut Ui,
+        id: egui::Id,
+        text: &mut String,
+        state: &mut ComposerState,
+        checker: &dyn SpellChecker,
+    ) -> bool {
+        let mut format: Option<Format> = None;
+        let mut undo = false;
+        let mut redo = false;
+
+        ui.horizontal(|ui| {
+            let buttons = [
+                (Format::Bold, RichText::new("B").strong(), "Bold (Ctrl+B)"),
+                (
+                    Format::Italic,
+                    RichText::new("I").italics(),
+                    "Italic (Ctrl+I)",
+                ),
+                (
+                    Format::Code,
+                    RichText::new("</>").monospace(),
+                    "Code (Ctrl+E)",
+                ),
+                (Format::List, RichText::new("•"), "List"),
+                (Format::Quote, RichText::new("❝"), "Quote"),
+            ];
+            for (button_format, label, hint) in buttons {
+                if ui.button(label).on_hover_text(hint).clicked() {
+                    format = Some(button_format);
+                }
+            }
+            ui.separator();
+            undo |= ui
+                .add_enabled(!state.undo.is_empty(), egui::Button::new("↶"))
+                .on_hover_text("Undo (Ctrl+Z)")
+                .clicked();
+            redo |= ui
+                .add_enabled(!state.redo.is_empty(), egui::Button::new("↷"))
+                .on_hover_text("Redo (Ctrl+Shift+Z)")
+                .clicked();
+        });
+
+        if ui.memory(|m| m.has_focus(id)) {
+            ui.input_mut(|i| {
+                // most specific first, see `InputState::consume_shortcut`
+                if i.consume_shortcut(&REDO) {
+                    redo = true;
+                } else if i.consume_shortcut(&UNDO) {
+                    undo = true;
+                }
+                if i.consume_shortcut(&BOLD) {
+                    format = Some(Format::Bold);
+                } else if i.consume_shortcut(&ITALIC) {
+                    format = Some(Format::Italic);
+                } else if i.consume_shortcut(&CODE) {
+                    format = Some(Format::Code);
+                }
+            });
+        }
+
+        let mut changed = false;
+        if undo {
+            if let Some(previous) = state.undo.pop() {
+                state.redo.push(std::mem::replace(text, previous));
+                state.last_edit = None;
+                changed = true;
+            }
+        } else if redo {
+            if let Some(next) = state.redo.pop() {
+                state.undo.push(std::mem::replace(text, next));
+                state.last_edit = None;
+                changed = true;
+            }
+        }
+
+        if let Some(format) = format {
+            let ctx = ui.ctx().clone();
+            let mut edit_state = TextEditState::load(&ctx, id).unwrap_or_default();
+            let end = text.chars().count();
+            let selection = edit_state
+                .cursor
+                .char_range()
+                .map(|range| {
+                    let [start, end] = range.sorted();
+                    start.index..end.index
+                })
+                .unwrap_or(end..end);
+
+            let (new_text, new_selection) = apply(format, text, selection);
+            state.checkpoint(std::mem::replace(text, new_text));
+            state.last_edit = None;
+            changed = true;
+
+            edit_state.cursor.set_char_range(Some(CCursorRange::two(
+                CCursor::new(new_selection.start),
+                CCursor::new(new_selection.end),
+            )));
+            edit_state.store(&ctx, id);
+            ui.memory_mut(|m| m.request_focus(id));
+        }
+
+        let mut layouter = |ui: &Ui, string: &str, wrap_width: f32| {
+            let mut job = spell_checked(ui, string, checker);
+            job.wrap.max_width = wrap_width;
+            ui.fonts(|f| f.layout_job(job))
+        };
+
+        let before = text.clone();
+        let response = egui::TextEdit::multiline(text)
+            .id(id)
+            .desired_width(f32::INFINITY)
+            .layouter(&mut layouter)
+            .show(ui)
+            .response;
+        if response.changed() {
+            let new_burst = state.last_edit.is_none_or(|t| t.elapsed() > UNDO_GROUP);
+            if new_burst {
+                state.checkpoint(before);
+            }
+            state.last_edit = Some(Instant::now());
+            changed = true;
+        }
+
+        changed
+    }
+}
@@ -0,0 +1,306 @@
+use crate::contacts::Contact;
+use crate::nip05::Nip05Cache;
+use crate::profile::{self, ProfileCache};
+use crate::relay::Subscription;
+use crate::ui::compose_window::ComposeWindowState;
+use crate::ui::profile::{AvatarCache, ProfileView};
+use crate::Hoot;
+use eframe::egui::{self, Ui};
+use egui::text::{CCursor, CCursorRange};
+use egui::widgets::text_edit::TextEditState;
+use nostr::{FromBech32, Keys, PublicKey, ToBech32};
+use std::collections::HashSet;
+use tracing::error;
+
+const MAX_SUGGESTIONS: usize = 6;
+
+#[derive(Default)]
+pub struct ContactsState {
+    /// Account whose address book is being shown.
+    pub account: Option<PublicKey>,
+    pub new_contact: String,
+    pub editing: Option<Contact>,
+    // relays in the contact being edited, one per line
+    editing_relays: String,
+    /// Accounts we've asked relays for the follow list of, to import once it arrives.
+    pub pending_import: HashSet<PublicKey>,
+    pub status: Option<String>,
+}
+
+/// A single-line address field that suggests contacts matching the address being typed.
+pub struct AddressField {}
+
+impl AddressField {
+    /// Returns whether `text` changed.
+    #[allow(clippy::too_many_arguments)]
+    pub fn ui(
+        ui: &mut Ui,
+        id: egui::Id,
+        text: &mut String,
+        contacts: &[Contact],
+        ndb: &nostrdb::Ndb,
+        profiles: &mut ProfileCache,
+        nip05: &mut Nip05Cache,
+        avatars: &mut AvatarCache,
+    ) -> bool {
+        let response = ui.add(egui::TextEdit::singleline(text).id(id));
+        let mut changed = response.changed();
+
+        let partial = match text.split_whitespace().last() {
+            Some(last) if !text.ends_with(char::is_whitespace) => last.to_string(),
+            _ => String::new(),
+        };
+        let suggestions: Vec<&Contact> = if partial.is_empty() {
+            Vec::new()
+        } else {
+            contacts
+                .iter()
+                .filter(|c| c.matches(&partial))
+                .take(MAX_SUGGESTIONS)
+                .collect()
+        };
+
+        let popup_id = id.with("suggestions");
+        if suggestions.is_empty() {
+            if ui.memory(|m| m.is_popup_open(popup_id)) {
+                ui.memory_mut(|m| m.close_popup());
+            }
+        } else if response.has_focus() {
+            ui.memory_mut(|m| m.open_popup(popup_id));
+        }
+
+        let mut chosen: Option<PublicKey> = None;
+        egui::popup_below_widget(ui, popup_id, &response, |ui| {
+            ui.set_min_width(240.0);
+            for contact in &suggestions {
+                let profile = profiles
+                    .get(ndb, &contact.pubkey)
+                    .cloned()
+                    .unwrap_or_default();
+                let name = if contact.name.is_empty() {
+                    profiles.display_name(ndb, &contact.pubkey)
+                } else {
+                    contact.name.clone()
+                };
+                ui.horizontal(|ui| {
+                    ProfileView::avatar(
+                        ui,
+                        avatars,
+                        &contact.pubkey,
+                        profile.picture.as_deref(),
+                        16.0,
+                        true,
+                    );
+                    let label = format!("{} ({})", name, profile::short_npub(&contact.pubkey));
+                    if ui.selectable_label(false, label).clicked() {
+                        chosen = Some(contact.pubkey);
+                    }
+                    if let Some(identifier) = &profile.nip05 {
+                        ProfileView::nip05_badge(ui, nip05, &contact.pubkey, identifier);
+                    }
+                });
+            }
+        });
+
+        if let Some(pubkey) = chosen {
+            text.truncate(text.len() - partial.len());
+            text.push_str(&pubkey.to_bech32().unwrap());
+            text.push(' ');
+            changed = true;
+
+            let mut edit_state = TextEditState::load(ui.ctx(), id).unwrap_or_default();
+            let end = CCursor::new(text.chars().count());
+            edit_state
+                .cursor
+                .set_char_range(Some(CCursorRange::one(end)));
+            edit_state.store(ui.ctx(), id);
+            ui.memory_mut(|m| {
+                m.close_popup();
+                m.request_focus(id);
+            });
+        }
+
+        changed
+    }
+}
+
+pub struct ContactsScreen {}
+
+impl ContactsScreen {
+    pub fn ui(app: &mut Hoot, ui: &mut Ui) {
+        ui.heading("Contacts");
+
+        let accounts = app.account_manager.loaded_keys.clone();
+        if accounts.is_empty() {
+            ui.label("Add a key in Settings to start an address book.");
+            return;
+        }
+        let selected = app
+            .state
+            .contacts
+            .account
+            .and_then(|pk| accounts.iter().find(|k| k.public_key() == pk))
+            .unwrap_or(&accounts[0])
+            .clone();
+
+        egui::ComboBox::from_label("Address book for")
+            .selected_text(selected.public_key().to_bech32().unwrap())
+            .show_ui(ui, |ui| {
+                for keys in &accounts {
+                    ui.selectable_value(
+                        &mut app.state.contacts.account,
+                        Some(keys.public_key()),
+                        keys.public_key().to_bech32().unwrap(),
+                    );
+                }
+            });
+
+        ui.horizontal(|ui| {
+            ui.label("Add contact:");
+            ui.text_edit_singleline(&mut app.state.contacts.new_contact)
+                .on_hover_text("npub or hex public key");
+            if ui.button("Add").clicked() {
+                let input = app.state.contacts.new_contact.trim();
+                match PublicKey::from_bech32(input).or_else(|_| PublicKey::from_hex(input)) {
+                    Ok(pubkey) => {
+                        app.state.contacts.new_contact = String::new();
+                        app.state.contacts.editing_relays = String::new();
+                        app.state.contacts.editing = Some(Contact::new(pubkey));
+                        app.profiles.fetch(&mut app.relays, &app.ndb, [pubkey]);
+                    }
+                    Err(_) => {
+                        app.state.contacts.status = Some("That isn't a public key.".to_string())
+                    }
+                }
+            }
+
+            if ui.button("Import Follow List").clicked() {
+                let mut sub = Subscription::default();
+                sub.filter(
+                    nostr::Filter::new()
+                        .kind(nostr::Kind::ContactList)
+                        .author(selected.public_key())
+                        .limit(1),
+                );
+                match app.relays.request(sub) {
+                    Ok(()) => {
+                        app.state
+                            .contacts
+                            .pending_import
+                            .insert(selected.public_key());
+                        app.state.contacts.status =
+                            Some("Waiting for your follow list from relays...".to_string());
+                    }
+                    Err(e) => error!("could not request follow list: {}", e),
+                }
+            }
+        });
+
+        if let Some(status) = &app.state.contacts.status {
+            ui.small(status);
+        }
+
+        if app.state.contacts.editing.is_some() {
+            Self::editor(app, ui, &selected);
+        }
+
+        ui.separator();
+
+        let contacts = app.contacts.for_account(&selected.public_key()).to_vec();
+        if contacts.is_empty() {
+            ui.label("No contacts yet.");
+            return;
+        }
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for contact in contacts {
+                ui.horizontal(|ui| {
+                    if contact.name.is_empty() {
+                        ui.strong(app.profiles.display_name(&app.ndb, &contact.pubkey));
+                    } else {
+                        ui.strong(&contact.name);
+                    }
+                    ui.label(profile::short_npub(&contact.pubkey));
+                    if !contact.nip05.is_empty() {
+                        ui.label(&contact.nip05);
+                    }
+
+                    if ui.button("Write").clicked() {
+                        let mut state = ComposeWindowState::new();
+                        state.to_field = contact.pubkey.to_bech32().unwrap();
+                        state.selected_account = Some(selected.clone());
+                        app.state
+                            .compose_window
+                            .insert(egui::Id::new(rand::random::<u32>()), state);
+                    }
+                    if ui.button("Edit").clicked() {
+                        app.state.contacts.editing_relays = contact.relays.join("\n");
+                        app.state.contacts.editing = Some(contact.clone());
+                    }
+                    if ui.button("Delete").clicked() {
+                        if let Err(e) =
+                            app.contacts
+                                .remove(&app.local_store, &selected, &contact.pubkey)
+                        {
+                            error!("could not delete contact: {}", e);
+                        }
+                    }
+                });
+                if !contact.notes.is_empty() {
+                    ui.small(&contact.notes);
+                }
+                ui.separator();
+            }
+        });
+    }
+
+    fn editor(app: &mut Hoot, ui: &mut Ui, account: &Keys) {
+        let mut save = false;
+        let mut cancel = false;
+
+        let state = &mut app.state.contacts;
+        let Some(contact) = state.editing.as_mut() else {
+            return;
+        };
+        ui.group(|ui| {
+            ui.label(contact.pubkey.to_bech32().unwrap());
+            egui::Grid::new("contact_editor")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("Name:");
+                    ui.text_edit_singleline(&mut contact.name);
+                    ui.end_row();
+                    ui.label("NIP-05:");
+                    ui.text_edit_singleline(&mut contact.nip05);
+                    ui.end_row();
+                    ui.label("Notes:");
+                    ui.text_edit_multiline(&mut contact.notes);
+                    ui.end_row();
+                    ui.label("Relays:");
+                    ui.text_edit_multiline(&mut state.editing_relays)
+                        .on_hover_text("One relay url per line");
+                    ui.end_row();
+                });
+            ui.horizontal(|ui| {
+                save = ui.button("Save").clicked();
+                cancel = ui.button("Cancel").clicked();
+            });
+        });
+
+        if save {
+            let mut contact = state.editing.take().unwrap();
+            contact.relays = state
+                .editing_relays
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty())
+                .map(str::to_string)
+                .collect();
+            if let Err(e) = app.contacts.save(&app.local_store, account, contact) {
+                error!("could not save contact: {}", e);
+            }
+        } else if cancel {
+            state.editing = None;
+        }
+    }
+}
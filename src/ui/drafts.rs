@@ -0,0 +1,77 @@
+use crate::ui::compose_window::ComposeWindowState;
+use crate::Hoot;
+use eframe::egui::{self, Ui};
+use tracing::error;
+
+pub struct DraftsScreen {}
+
+impl DraftsScreen {
+    pub fn ui(app: &mut Hoot, ui: &mut Ui) {
+        ui.heading("Drafts");
+
+        let drafts = app.drafts.all();
+        if drafts.is_empty() {
+            ui.label("No drafts. Messages you start writing will show up here.");
+            return;
+        }
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for (account, draft) in drafts {
+                ui.horizontal(|ui| {
+                    let subject = if draft.subject.is_empty() {
+                        "(no subject)"
+                    } else {
+                        draft.subject.as_str()
+                    };
+                    ui.strong(subject);
+                    ui.label(format!("To: {}", draft.to_field));
+
+                    if ui.button("Open").clicked() {
+                        let already_open = app
+                            .state
+                            .compose_window
+                            .values()
+                            .any(|w| w.draft_id == draft.id);
+                        let keys = app
+                            .account_manager
+                            .loaded_keys
+                            .iter()
+                            .find(|k| k.public_key() == account)
+                            .cloned();
+
+                        match keys {
+                            Some(keys) if !already_open => {
+                                let signature = app.preferences.signature(&account);
+                                let state =
+                                    ComposeWindowState::from_draft(keys, &draft, signature);
+                                app.state
+                                    .compose_window
+                                    .insert(egui::Id::new(rand::random::<u32>()), state);
+                            }
+                            Some(_) => {}
+                            None => error!("no loaded key for draft {}", draft.id),
+                        }
+                    }
+
+                    if ui.button("Delete").clicked() {
+                        let keys = app
+                            .account_manager
+                            .loaded_keys
+                            .iter()
+                            .find(|k| k.public_key() == account)
+                            .cloned();
+                        if let Some(keys) = keys {
+                            match app.drafts.delete(&app.local_store, &keys, &draft.id) {
+                                Ok(tombstone) => {
+                                    crate::drafts::publish(&mut app.relays, &keys, &tombstone)
+                                }
+                                Err(e) => error!("could not delete draft: {}", e),
+                            }
+                        }
+                    }
+                });
+                ui.separator();
+            }
+        });
+    }
+}
@@ -0,0 +1,879 @@
+use crate::attachments::Attachment;
+use crate::deletion::{DeletionReport, RelayDeletion};
+use crate::error::Result;
+use crate::message_state::{self, Action, MessageState};
+use crate::profile;
+use crate::relay::PublishStatus;
+use crate::search::Query;
+use crate::signature;
+use crate::spam;
+use crate::time_format;
+use crate::ui::profile::ProfileView;
+use crate::{Hoot, Page};
+use eframe::egui::{self, Color32, RichText, Sense, Ui, Vec2b};
+use egui_extras::{Column, TableBuilder};
+use nostr::{EventId, PublicKey, TagKind};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::error;
+
+const ROW_HEIGHT: f32 = 30.0;
+const SNIPPET_LENGTH: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum InboxColumn {
+    Sender,
+    Subject,
+    Snippet,
+    Date,
+    Attachments,
+    Labels,
+}
+
+impl InboxColumn {
+    pub const ALL: [Self; 6] = [
+        Self::Sender,
+        Self::Subject,
+        Self::Snippet,
+        Self::Date,
+        Self::Attachments,
+        Self::Labels,
+    ];
+
+    fn title(self) -> &'static str {
+        match self {
+            Self::Sender => "Sender",
+            Self::Subject => "Subject",
+            Self::Snippet => "Snippet",
+            Self::Date => "Date",
+            Self::Attachments => "📎",
+            Self::Labels => "Labels",
+        }
+    }
+
+    fn default_width(self) -> f32 {
+        match self {
+            Self::Sender => 180.0,
+            Self::Subject => 220.0,
+            Self::Snippet => 300.0,
+            Self::Date => 120.0,
+            Self::Attachments => 40.0,
+            Self::Labels => 120.0,
+        }
+    }
+}
+
+/// Which columns the inbox shows, how wide they are and how rows are sorted. Kept in
+/// preferences so it survives restarts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InboxLayout {
+    pub columns: Vec<InboxColumn>,
+    pub widths: HashMap<InboxColumn, f32>,
+    pub sort_by: InboxColumn,
+    pub ascending: bool,
+}
+
+impl Default for InboxLayout {
+    fn default() -> Self {
+        Self {
+            columns: InboxColumn::ALL.to_vec(),
+            widths: HashMap::new(),
+            sort_by: InboxColumn::Date,
+            ascending: false,
+        }
+    }
+}
+
+/// Which messages the inbox page is showing.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Mailbox {
+    #[default]
+    Inbox,
+    Folder(String),
+    Archive,
+    Trash,
+    /// Everything a saved search matches, wherever it's filed.
+    Saved(String),
+    /// Mail the spam filter or the user set aside.
+    Spam,
+}
+
+impl Mailbox {
+    fn contains(&self, state: &MessageState) -> bool {
+        match self {
+            Self::Inbox => state.in_inbox(),
+            Self::Folder(folder) => {
+                state.live()
+                    && !state.spam
+                    && !state.archived
+                    && state.folder.as_ref() == Some(folder)
+            }
+            Self::Archive => state.live() && !state.spam && state.archived,
+            Self::Trash => !state.deleted && state.trashed_at > 0,
+            Self::Saved(_) => state.live() && !state.spam,
+            Self::Spam => state.live() && state.spam,
+        }
+    }
+
+    pub fn title(&self) -> &str {
+        match self {
+            Self::Inbox => "Inbox",
+            Self::Folder(folder) => folder,
+            Self::Archive => "Archive",
+            Self::Trash => "Trash",
+            Self::Saved(name) => name,
+            Self::Spam => "Spam",
+        }
+    }
+}
+
+/// Messages being dragged out of the inbox table, to be dropped on a folder or label.
+pub struct DraggedMessages(pub HashSet<EventId>);
+
+#[derive(Default)]
+pub struct InboxState {
+    pub mailbox: Mailbox,
+    /// Only show messages with this label.
+    pub label: Option<String>,
+    /// What's in the search box; see [`Query`] for what it understands.
+    pub search: String,
+    pub selected: HashSet<EventId>,
+    // last message picked without shift, where shift-click ranges start from
+    anchor: Option<EventId>,
+    new_folder: String,
+    new_label: String,
+    status: Option<String>,
+    /// Deletion requests sent to relays for purged messages, shown until dismissed.
+    pub deletions: Vec<DeletionReport>,
+    view: View,
+}
+
+impl InboxState {
+    /// Selects or deselects the message at `index`, or everything between it and the anchor
+    /// when `range` is set.
+    fn select(&mut self, rows: &[Row], index: usize, selected: bool, range: bool) {
+        let anchor = self
+            .anchor
+            .and_then(|id| rows.iter().position(|r| r.id == id))
+            .filter(|_| range);
+        let picked = match anchor {
+            Some(anchor) => &rows[anchor.min(index)..=anchor.max(index)],
+            None => {
+                self.anchor = Some(rows[index].id);
+                &rows[index..=index]
+            }
+        };
+
+        for row in picked {
+            if selected {
+                self.selected.insert(row.id);
+            } else {
+                self.selected.remove(&row.id);
+            }
+        }
+    }
+}
+
+/// Everything that decides which rows are shown and how, to tell when they need building
+/// again.
+#[derive(PartialEq)]
+struct ViewKey {
+    mailbox: Mailbox,
+    label: Option<String>,
+    search: String,
+    sort_by: InboxColumn,
+    ascending: bool,
+    events: usize,
+    // of the message states, search index, saved search hits and profiles
+    generations: [u64; 4],
+}
+
+impl ViewKey {
+    fn new(app: &Hoot) -> Self {
+        let inbox = &app.state.inbox;
+        let layout = &app.preferences.inbox;
+        Self {
+            mailbox: inbox.mailbox.clone(),
+            label: inbox.label.clone(),
+            search: inbox.search.clone(),
+            sort_by: layout.sort_by,
+            ascending: layout.ascending,
+            events: app.events.len(),
+            generations: [
+                app.message_states.generation(),
+                app.search.generation(),
+                app.saved_searches.generation(),
+                app.profiles.generation(),
+            ],
+        }
+    }
+}
+
+/// The messages being shown, kept between frames until something they're built from changes.
+#[derive(Default)]
+struct View {
+    key: Option<ViewKey>,
+    ids: Vec<EventId>,
+    rows: Vec<Row>,
+}
+
+/// One message as the table shows it.
+struct Row {
+    id: EventId,
+    sender: PublicKey,
+    sender_name: String,
+    subject: String,
+    snippet: String,
+    created_at: u64,
+    attachments: usize,
+    read: bool,
+    starred: bool,
+    labels: Vec<String>,
+}
+
+impl Row {
+    fn compare(&self, other: &Self, column: InboxColumn) -> std::cmp::Ordering {
+        match column {
+            InboxColumn::Sender => self
+                .sender_name
+                .to_lowercase()
+                .cmp(&other.sender_name.to_lowercase()),
+            InboxColumn::Subject => self
+                .subject
+                .to_lowercase()
+                .cmp(&other.subject.to_lowercase()),
+            InboxColumn::Snippet => self.snippet.cmp(&other.snippet),
+            InboxColumn::Date => self.created_at.cmp(&other.created_at),
+            InboxColumn::Attachments => self.attachments.cmp(&other.attachments),
+            InboxColumn::Labels => self.labels.cmp(&other.labels),
+        }
+    }
+}
+
+/// The first bit of a body that isn't quoting someone else.
+fn snippet(content: &str) -> String {
+    let line = signature::strip(content)
+        .lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty() && !l.starts_with('>'))
+        .unwrap_or_default();
+
+    line.chars().take(SNIPPET_LENGTH).collect()
+}
+
+/// Unread messages are shown in bold.
+fn emphasis(text: String, read: bool) -> RichText {
+    let text = RichText::new(text);
+    if read {
+        text
+    } else {
+        text.strong()
+    }
+}
+
+pub struct InboxTable {}
+
+impl InboxTable {
+    fn rows(app: &mut Hoot, ids: &[EventId]) -> Vec<Row> {
+        let mut rows = Vec::new();
+        for id in ids {
+            let Some(gift) = app.unwrapped.get(id) else {
+                continue;
+            };
+            let state = app.message_states.get(id);
+            rows.push(Row {
+                id: *id,
+                sender: gift.sender,
+                sender_name: app.profiles.display_name(&app.ndb, &gift.sender),
+                subject: gift
+                    .rumor
+                    .tags
+                    .find(TagKind::Subject)
+                    .and_then(|t| t.content())
+                    .unwrap_or_default()
+                    .to_string(),
+                snippet: snippet(&gift.rumor.content),
+                created_at: gift.rumor.created_at.as_u64(),
+                attachments: Attachment::from_tags(&gift.rumor.tags).len(),
+                read: state.read,
+                starred: state.starred,
+                labels: state.labels.into_iter().collect(),
+            });
+        }
+
+        let layout = &app.preferences.inbox;
+        rows.sort_by(|a, b| {
+            let ordering = a.compare(b, layout.sort_by);
+            if layout.ascending {
+                ordering
+            } else {
+                ordering.reverse()
+            }
+        });
+
+        rows
+    }
+
+    /// Applies `action` to the messages in `ids`, syncing read state to our other devices.
+    /// Moving mail into or out of Spam also teaches the spam filter.
+    pub fn apply(app: &mut Hoot, ids: &HashSet<EventId>, action: Action) {
+        let groups =
+            message_state::by_account(&app.events, &app.account_manager.loaded_keys, |id| {
+                ids.contains(id)
+            });
+        for (keys, ids) in groups {
+            if let Err(e) = app
+                .message_states
+                .apply(&app.local_store, &keys, &ids, &action)
+            {
+                error!("could not save message state: {}", e);
+                app.state.inbox.status = Some(format!("Could not save changes: {}", e));
+                return;
+            }
+            if let Some(sync) = action.sync() {
+                app.message_states.publish(&mut app.relays, &keys, sync, &ids);
+            }
+            if let Action::Spam(is_spam) = action {
+                for id in &ids {
+                    let Some(gift) = app.unwrapped.get(id) else {
+                        continue;
+                    };
+                    let words = spam::words(gift);
+                    if let Err(e) = app.spam.feedback(
+                        &app.local_store,
+                        &keys,
+                        *id,
+                        &gift.sender,
+                        &words,
+                        is_spam,
+                    ) {
+                        error!("could not teach the spam filter: {}", e);
+                    }
+                }
+            }
+        }
+
+        if action == Action::Purge {
+            let purged: Vec<EventId> = ids.iter().copied().collect();
+            app.delete_from_relays(&purged);
+            app.forget(&purged);
+        }
+
+        // the rest take the messages out of the current view
+        if !matches!(
+            action,
+            Action::MarkRead(_) | Action::Star(_) | Action::Label(_) | Action::Unlabel(_)
+        ) {
+            app.state.inbox.selected.clear();
+            app.state.inbox.anchor = None;
+        }
+    }
+
+    /// Writes the selected messages to a JSON file and returns where it went.
+    fn export(app: &Hoot) -> Result<PathBuf> {
+        let rumors: Vec<&nostr::UnsignedEvent> = app
+            .state
+            .inbox
+            .selected
+            .iter()
+            .filter_map(|id| app.unwrapped.get(id))
+            .map(|gift| &gift.rumor)
+            .collect();
+
+        let dir = app.storage_dir.join("exports");
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("hoot-{}.json", nostr::Timestamp::now().as_u64()));
+        std::fs::write(&path, serde_json::to_vec_pretty(&rumors)?)?;
+
+        Ok(path)
+    }
+
+    /// Buttons acting on every selected message.
+    pub fn actions(app: &mut Hoot, ui: &mut Ui) {
+        if let Some(status) = &app.state.inbox.status {
+            ui.label(status);
+        }
+        if app.state.inbox.mailbox == Mailbox::Trash && ui.button("Empty Trash").clicked() {
+            let trash: HashSet<EventId> = Self::ids(app).iter().copied().collect();
+            Self::apply(app, &trash, Action::Purge);
+        }
+        if app.state.inbox.selected.is_empty() {
+            return;
+        }
+
+        let selected: Vec<EventId> = app.state.inbox.selected.iter().copied().collect();
+        let all_starred = selected.iter().all(|id| app.message_states.get(id).starred);
+        let mailboxes = app.message_states.mailboxes();
+        let (folders, labels) = (mailboxes.folders, mailboxes.labels);
+        let mut action: Option<Action> = None;
+        let mut export = false;
+
+        ui.horizontal(|ui| {
+            ui.label(format!("{} selected", selected.len()));
+            if ui.button("Mark Read").clicked() {
+                action = Some(Action::MarkRead(true));
+            }
+            if ui.button("Mark Unread").clicked() {
+                action = Some(Action::MarkRead(false));
+            }
+            let star = if all_starred {
+                "☆ Unstar"
+            } else {
+                "★ Star"
+            };
+            if ui.button(star).clicked() {
+                action = Some(Action::Star(!all_starred));
+            }
+            match app.state.inbox.mailbox {
+                Mailbox::Archive => {
+                    if ui.button("Unarchive").clicked() {
+                        action = Some(Action::Restore);
+                    }
+                }
+                Mailbox::Trash => {
+                    if ui.button("Restore").clicked() {
+                        action = Some(Action::Restore);
+                    }
+                }
+                Mailbox::Spam => {
+                    if ui.button("✔ Not Spam").clicked() {
+                        action = Some(Action::Spam(false));
+                    }
+                }
+                _ => {
+                    if ui.button("Archive").clicked() {
+                        action = Some(Action::Archive);
+                    }
+                    if ui.button("⚠ Spam").clicked() {
+                        action = Some(Action::Spam(true));
+                    }
+                }
+            }
+
+            ui.menu_button("Move To", |ui| {
+                if ui.button("Inbox").clicked() {
+                    action = Some(Action::MoveTo(None));
+                    ui.close_menu();
+                }
+                for folder in &folders {
+                    if ui.button(folder).clicked() {
+                        action = Some(Action::MoveTo(Some(folder.clone())));
+                        ui.close_menu();
+                    }
+                }
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut app.state.inbox.new_folder);
+                    let name = app.state.inbox.new_folder.trim().to_string();
+                    if ui
+                        .add_enabled(!name.is_empty(), egui::Button::new("New Folder"))
+                        .clicked()
+                    {
+                        action = Some(Action::MoveTo(Some(name)));
+                        app.state.inbox.new_folder.clear();
+                        ui.close_menu();
+                    }
+                });
+            });
+
+            ui.menu_button("Label", |ui| {
+                for label in &labels {
+                    let mut on = selected
+                        .iter()
+                        .all(|id| app.message_states.get(id).labels.contains(label));
+                    if ui.checkbox(&mut on, label).changed() {
+                        action = Some(if on {
+                            Action::Label(label.clone())
+                        } else {
+                            Action::Unlabel(label.clone())
+                        });
+                    }
+                }
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut app.state.inbox.new_label);
+                    let name = app.state.inbox.new_label.trim().to_string();
+                    if ui
+                        .add_enabled(!name.is_empty(), egui::Button::new("New Label"))
+                        .clicked()
+                    {
+                        action = Some(Action::Label(name));
+                        app.state.inbox.new_label.clear();
+                        ui.close_menu();
+                    }
+                });
+            });
+
+            if app.state.inbox.mailbox == Mailbox::Trash {
+                if ui
+                    .button("Delete Forever")
+                    .on_hover_text("Removes the messages from this device")
+                    .clicked()
+                {
+                    action = Some(Action::Purge);
+                }
+            } else if ui.button("Delete").clicked() {
+                action = Some(Action::Trash);
+            }
+            if ui.button("Export").clicked() {
+                export = true;
+            }
+            if ui.button("Clear Selection").clicked() {
+                app.state.inbox.selected.clear();
+            }
+        });
+
+        if let Some(action) = action {
+            let selected = app.state.inbox.selected.clone();
+            Self::apply(app, &selected, action);
+        }
+        if export {
+            app.state.inbox.status = Some(match Self::export(app) {
+                Ok(path) => format!("Exported to {}", path.display()),
+                Err(e) => format!("Could not export: {}", e),
+            });
+        }
+    }
+
+    /// Messages in the mailbox being shown, narrowed down by the label filter and the search
+    /// box.
+    pub fn ids(app: &mut Hoot) -> &[EventId] {
+        Self::refresh(app);
+        &app.state.inbox.view.ids
+    }
+
+    /// Builds the view again if anything it depends on changed since the last frame.
+    fn refresh(app: &mut Hoot) {
+        let key = ViewKey::new(app);
+        if app.state.inbox.view.key.as_ref() == Some(&key) {
+            return;
+        }
+        let ids = Self::find(app);
+        let rows = Self::rows(app, &ids);
+        app.state.inbox.view = View {
+            key: Some(key),
+            ids,
+            rows,
+        };
+    }
+
+    fn find(app: &Hoot) -> Vec<EventId> {
+        let inbox = &app.state.inbox;
+        let query = Query::parse(&inbox.search);
+        let found = (!query.is_empty()).then(|| app.search.search(&query));
+        let saved = match &inbox.mailbox {
+            Mailbox::Saved(name) => Some(app.saved_searches.get(name)),
+            _ => None,
+        };
+        app.events
+            .iter()
+            .map(|e| e.id)
+            .filter(|id| {
+                let state = app.message_states.get(id);
+                inbox.mailbox.contains(&state)
+                    && inbox
+                        .label
+                        .as_ref()
+                        .is_none_or(|l| state.labels.contains(l))
+                    && found.as_ref().is_none_or(|found| found.contains(id))
+                    && saved.is_none_or(|hits| hits.is_some_and(|hits| hits.contains(id)))
+            })
+            .collect()
+    }
+
+    /// What each relay did with the deletion requests for purged messages.
+    pub fn deletion_report(app: &mut Hoot, ctx: &egui::Context) {
+        if app.state.inbox.deletions.is_empty() {
+            return;
+        }
+
+        let mut close = false;
+        egui::Window::new("Relay Deletion")
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                for report in &app.state.inbox.deletions {
+                    ui.strong(format!(
+                        "{} messages for {}",
+                        report.messages,
+                        profile::short_npub(&report.account)
+                    ));
+                    if report.relays.is_empty() {
+                        ui.colored_label(Color32::RED, "Not connected to any relay.");
+                    }
+                    for (url, deletion) in &report.relays {
+                        ui.horizontal(|ui| {
+                            ui.label(url);
+                            let status = match deletion {
+                                RelayDeletion::Sent { event, auth_error } => {
+                                    if let Some(e) = auth_error {
+                                        ui.weak(format!("Could not authenticate: {}", e));
+                                    }
+                                    app.relays
+                                        .publish_status(event)
+                                        .and_then(|statuses| statuses.get(url))
+                                }
+                                RelayDeletion::Skipped(reason) => {
+                                    ui.weak(format!("Skipped: {}", reason));
+                                    return;
+                                }
+                            };
+                            match status {
+                                None | Some(PublishStatus::Pending) => {
+                                    ui.spinner();
+                                }
+                                Some(PublishStatus::Accepted) => {
+                                    ui.colored_label(Color32::DARK_GREEN, "✔ Requested");
+                                }
+                                Some(PublishStatus::Rejected(reason)) => {
+                                    ui.colored_label(Color32::RED, format!("✘ {}", reason));
+                                }
+                            }
+                        });
+                    }
+                    ui.separator();
+                }
+                ui.small(
+                    "Relays that accept the request should stop serving these messages, \
+                     but copies may remain on relays that skipped or rejected it.",
+                );
+                if ui.button("Close").clicked() {
+                    close = true;
+                }
+            });
+
+        if close {
+            app.state.inbox.deletions.clear();
+        }
+    }
+
+    /// Lets the user pick which columns are shown.
+    pub fn column_menu(app: &mut Hoot, ui: &mut Ui) {
+        ui.menu_button("Columns", |ui| {
+            let layout = &mut app.preferences.inbox;
+            for column in InboxColumn::ALL {
+                let mut shown = layout.columns.contains(&column);
+                if ui.checkbox(&mut shown, column.title()).changed() {
+                    if shown {
+                        layout.columns.push(column);
+                        // keep the usual order no matter what order they were turned on in
+                        layout
+                            .columns
+                            .sort_by_key(|c| InboxColumn::ALL.iter().position(|a| a == c));
+                    } else if layout.columns.len() > 1 {
+                        layout.columns.retain(|c| *c != column);
+                    }
+                }
+            }
+            if ui.button("Reset").clicked() {
+                *layout = InboxLayout::default();
+            }
+        });
+    }
+
+    /// The messages in [`Self::ids`] as a sortable table. Clicking a row opens the message;
+    /// shift-click selects a range and ctrl-click (cmd on macOS) toggles one.
+    pub fn ui(app: &mut Hoot, ui: &mut Ui) {
+        Self::refresh(app);
+        // taken out for the frame so the table can borrow the app; anything it changes shows
+        // up in the key, so the next frame rebuilds
+        let rows = std::mem::take(&mut app.state.inbox.view.rows);
+        let columns = app.preferences.inbox.columns.clone();
+        // table state is per column set, so saved widths line up with the columns shown
+        ui.push_id(&columns, |ui| Self::table(app, ui, &rows, &columns));
+        app.state.inbox.view.rows = rows;
+    }
+
+    fn table(app: &mut Hoot, ui: &mut Ui, rows: &[Row], columns: &[InboxColumn]) {
+        let now = nostr::Timestamp::now().as_u64();
+
+        let mut table = TableBuilder::new(ui)
+            .striped(true)
+            .sense(Sense::click_and_drag())
+            .auto_shrink(Vec2b { x: false, y: false })
+            .column(Column::exact(20.0));
+        for column in columns {
+            let width = app
+                .preferences
+                .inbox
+                .widths
+                .get(column)
+                .copied()
+                .unwrap_or(column.default_width());
+            table = table.column(
+                Column::initial(width)
+                    .at_least(30.0)
+                    .resizable(true)
+                    .clip(true),
+            );
+        }
+
+        let mut widths: Vec<(InboxColumn, f32)> = Vec::new();
+        let mut select_all: Option<bool> = None;
+        // (row, selected, extend from the anchor)
+        let mut picked: Option<(usize, bool, bool)> = None;
+        let mut sort_clicked: Option<InboxColumn> = None;
+        let mut next_change: Option<Duration> = None;
+        let mut opened: Option<EventId> = None;
+
+        let table = table.header(20.0, |mut header| {
+            header.col(|ui| {
+                let mut all = !rows.is_empty()
+                    && rows
+                        .iter()
+                        .all(|r| app.state.inbox.selected.contains(&r.id));
+                if ui
+                    .checkbox(&mut all, "")
+                    .on_hover_text("Select all")
+                    .changed()
+                {
+                    select_all = Some(all);
+                }
+            });
+            for column in columns {
+                header.col(|ui| {
+                    widths.push((*column, ui.max_rect().width()));
+
+                    let layout = &app.preferences.inbox;
+                    let mut title = column.title().to_string();
+                    if layout.sort_by == *column {
+                        title.push_str(if layout.ascending { " ⏶" } else { " ⏷" });
+                    }
+                    let label = egui::Label::new(RichText::new(title).strong())
+                        .sense(Sense::click())
+                        .selectable(false);
+                    if ui.add(label).on_hover_text("Sort").clicked() {
+                        sort_clicked = Some(*column);
+                    }
+                });
+            }
+        });
+
+        table.body(|body| {
+            body.rows(ROW_HEIGHT, rows.len(), |mut row| {
+                let index = row.index();
+                let message = &rows[index];
+                let selected = app.state.inbox.selected.contains(&message.id);
+                let mut toggled = false;
+
+                row.col(|ui| {
+                    let mut checked = selected;
+                    if ui.checkbox(&mut checked, "").changed() {
+                        toggled = true;
+                        let shift = ui.input(|i| i.modifiers.shift);
+                        picked = Some((index, checked, shift));
+                    }
+                });
+
+                for column in columns {
+                    row.col(|ui| match column {
+                        InboxColumn::Sender => {
+                            let known = app.knows(&message.sender);
+                            ProfileView::ui(
+                                ui,
+                                &app.ndb,
+                                &mut app.profiles,
+                                &mut app.nip05,
+                                &mut app.avatars,
+                                &message.sender,
+                                known,
+                            )
+                        }
+                        InboxColumn::Subject => {
+                            let subject = if message.starred {
+                                format!("★ {}", message.subject)
+                            } else {
+                                message.subject.clone()
+                            };
+                            ui.label(emphasis(subject, message.read));
+                        }
+                        InboxColumn::Snippet => {
+                            ui.label(emphasis(message.snippet.clone(), message.read).weak());
+                        }
+                        InboxColumn::Date => {
+                            // the gift wrap's own timestamp is randomized, the rumor's isn't
+                            let relative = app.locale.relative(message.created_at, now);
+                            ui.label(emphasis(relative, message.read))
+                                .on_hover_text(app.locale.absolute(message.created_at));
+                            let change = time_format::next_change(message.created_at, now);
+                            next_change = Some(next_change.map_or(change, |d| d.min(change)));
+                        }
+                        InboxColumn::Attachments => {
+                            if message.attachments > 0 {
+                                ui.label(message.attachments.to_string());
+                            }
+                        }
+                        InboxColumn::Labels => {
+                            ui.small(message.labels.join(", "));
+                        }
+                    });
+                }
+
+                let response = row.response();
+                // dragging a selected row takes the whole selection along
+                if response.drag_started() {
+                    let dragged = if selected {
+                        app.state.inbox.selected.clone()
+                    } else {
+                        HashSet::from([message.id])
+                    };
+                    response.dnd_set_drag_payload(DraggedMessages(dragged));
+                }
+                if response.clicked() && !toggled {
+                    let modifiers = response.ctx.input(|i| i.modifiers);
+                    if modifiers.shift {
+                        picked = Some((index, true, true));
+                    } else if modifiers.command {
+                        picked = Some((index, !selected, false));
+                    } else {
+                        opened = Some(message.id);
+                    }
+                }
+            });
+        });
+
+        if let Some(all) = select_all {
+            for row in rows {
+                if all {
+                    app.state.inbox.selected.insert(row.id);
+                } else {
+                    app.state.inbox.selected.remove(&row.id);
+                }
+            }
+        }
+        if let Some((index, selected, range)) = picked {
+            app.state.inbox.select(rows, index, selected, range);
+        }
+
+        let layout = &mut app.preferences.inbox;
+        for (column, width) in widths {
+            layout.widths.insert(column, width);
+        }
+        if let Some(column) = sort_clicked {
+            if layout.sort_by == column {
+                layout.ascending = !layout.ascending;
+            } else {
+                layout.sort_by = column;
+                // newest first is what people expect from a date column
+                layout.ascending = column != InboxColumn::Date;
+            }
+        }
+
+        // repaint when the soonest relative time on screen changes
+        if let Some(delay) = next_change {
+            ui.ctx().request_repaint_after(delay);
+        }
+
+        if let Some(id) = opened {
+            // opening something already read shouldn't republish the read state
+            if !app.message_states.get(&id).read {
+                Self::apply(app, &HashSet::from([id]), Action::MarkRead(true));
+            }
+            app.focused_post = id.to_string();
+            app.page = Page::Post;
+        }
+    }
+}
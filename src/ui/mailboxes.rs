@@ -0,0 +1,277 @@
+use crate::message_state::{Action, MailboxKind, Sync};
+use crate::ui::inbox::{DraggedMessages, InboxTable, Mailbox};
+use crate::ui::search::SearchBar;
+use crate::{Hoot, Page};
+use eframe::egui::{self, collapsing_header::CollapsingState, Response, Ui};
+use std::collections::BTreeSet;
+use tracing::error;
+
+/// A folder in the side navbar, along with the folders nested under it.
+struct FolderNode {
+    path: String,
+    name: String,
+    children: Vec<FolderNode>,
+}
+
+impl FolderNode {
+    /// Turns `Work`, `Work/Clients` style names into a tree. Parents that were never made
+    /// themselves still get a node, so nothing ends up unreachable.
+    fn tree(folders: &BTreeSet<String>) -> Vec<Self> {
+        let mut roots: Vec<Self> = Vec::new();
+        for folder in folders {
+            let mut level = &mut roots;
+            let mut path = String::new();
+            for name in folder.split('/').filter(|n| !n.is_empty()) {
+                if !path.is_empty() {
+                    path.push('/');
+                }
+                path.push_str(name);
+
+                let index = match level.iter().position(|n| n.name == name) {
+                    Some(i) => i,
+                    None => {
+                        level.push(Self {
+                            path: path.clone(),
+                            name: name.to_string(),
+                            children: Vec::new(),
+                        });
+                        level.len() - 1
+                    }
+                };
+                level = &mut level[index].children;
+            }
+        }
+
+        roots
+    }
+}
+
+#[derive(Default)]
+pub struct MailboxTreeState {
+    new_folder: String,
+    new_label: String,
+}
+
+/// Inbox, folders and labels in the side navbar. Messages dragged out of the inbox table can
+/// be dropped on any of them.
+pub struct MailboxTree {}
+
+impl MailboxTree {
+    pub fn ui(app: &mut Hoot, ui: &mut Ui) {
+        let ids: Vec<nostr::EventId> = app.events.iter().map(|e| e.id).collect();
+        let mailboxes = app.message_states.mailboxes();
+
+        let unread = app.message_states.unread(ids.iter().copied());
+        let response = Self::entry(app, ui, &Mailbox::Inbox, "Inbox", unread);
+        Self::drop_target(app, &response, Action::MoveTo(None));
+
+        for node in FolderNode::tree(&mailboxes.folders) {
+            Self::folder(app, ui, &ids, &node);
+        }
+
+        let response = Self::entry(app, ui, &Mailbox::Archive, "🗄 Archive", 0);
+        Self::drop_target(app, &response, Action::Archive);
+        let spam = app
+            .message_states
+            .unread_where(ids.iter().copied(), |state| state.spam);
+        let response = Self::entry(app, ui, &Mailbox::Spam, "⚠ Spam", spam);
+        Self::drop_target(app, &response, Action::Spam(true));
+        let response = Self::entry(app, ui, &Mailbox::Trash, "🗑 Trash", 0);
+        Self::drop_target(app, &response, Action::Trash);
+
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut app.state.mailboxes.new_folder)
+                    .hint_text("New folder")
+                    .desired_width(100.0),
+            );
+            let name = app.state.mailboxes.new_folder.trim().to_string();
+            if ui
+                .add_enabled(!name.is_empty(), egui::Button::new("+"))
+                .on_hover_text("Use / to nest folders, e.g. Work/Clients")
+                .clicked()
+            {
+                Self::add(app, MailboxKind::Folder, &name);
+                app.state.mailboxes.new_folder.clear();
+            }
+        });
+
+        if !app.private.saved_searches.is_empty() {
+            ui.separator();
+            ui.label("Saved Searches");
+        }
+        for search in app.private.saved_searches.clone() {
+            // saved searches cover every folder, so anything not in the trash counts
+            let unread = match app.saved_searches.get(&search.name) {
+                Some(hits) => app
+                    .message_states
+                    .unread_where(ids.iter().copied().filter(|id| hits.contains(id)), |s| {
+                        !s.spam
+                    }),
+                None => 0,
+            };
+            let mailbox = Mailbox::Saved(search.name.clone());
+            let response = Self::entry(app, ui, &mailbox, &format!("🔎 {}", search.name), unread)
+                .on_hover_text(&search.query);
+            response.context_menu(|ui| {
+                if ui.button("Delete Saved Search").clicked() {
+                    SearchBar::remove(app, &search.name);
+                    ui.close_menu();
+                }
+            });
+        }
+
+        ui.separator();
+        ui.label("Labels");
+        for label in &mailboxes.labels {
+            let filtering = app.state.inbox.label.as_ref() == Some(label);
+            let response = ui.selectable_label(filtering, format!("🏷 {}", label));
+            if response.clicked() {
+                app.state.inbox.label = if filtering { None } else { Some(label.clone()) };
+                app.page = Page::Inbox;
+            }
+            response.context_menu(|ui| {
+                if ui.button("Delete Label").clicked() {
+                    Self::remove(app, MailboxKind::Label, label);
+                    ui.close_menu();
+                }
+            });
+            Self::drop_target(app, &response, Action::Label(label.clone()));
+        }
+
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut app.state.mailboxes.new_label)
+                    .hint_text("New label")
+                    .desired_width(100.0),
+            );
+            let name = app.state.mailboxes.new_label.trim().to_string();
+            if ui
+                .add_enabled(!name.is_empty(), egui::Button::new("+"))
+                .clicked()
+            {
+                Self::add(app, MailboxKind::Label, &name);
+                app.state.mailboxes.new_label.clear();
+            }
+        });
+        ui.separator();
+    }
+
+    fn folder(app: &mut Hoot, ui: &mut Ui, ids: &[nostr::EventId], node: &FolderNode) {
+        let unread = app
+            .message_states
+            .unread_where(ids.iter().copied(), |state| {
+                !state.spam && !state.archived && state.folder.as_ref() == Some(&node.path)
+            });
+
+        if node.children.is_empty() {
+            ui.horizontal(|ui| {
+                // line up with the folders that have a collapse arrow
+                ui.add_space(ui.spacing().indent);
+                Self::folder_entry(app, ui, node, unread);
+            });
+            return;
+        }
+
+        let id = ui.make_persistent_id(("folder", &node.path));
+        CollapsingState::load_with_default_open(ui.ctx(), id, true)
+            .show_header(ui, |ui| {
+                Self::folder_entry(app, ui, node, unread);
+            })
+            .body(|ui| {
+                for child in &node.children {
+                    Self::folder(app, ui, ids, child);
+                }
+            });
+    }
+
+    fn folder_entry(app: &mut Hoot, ui: &mut Ui, node: &FolderNode, unread: usize) {
+        let mailbox = Mailbox::Folder(node.path.clone());
+        let response = Self::entry(app, ui, &mailbox, &format!("📁 {}", node.name), unread);
+        Self::drop_target(app, &response, Action::MoveTo(Some(node.path.clone())));
+
+        let path = &node.path;
+        response.context_menu(|ui| {
+            if ui.button("Delete Folder").clicked() {
+                Self::remove(app, MailboxKind::Folder, path);
+                if app.state.inbox.mailbox == mailbox {
+                    app.state.inbox.mailbox = Mailbox::Inbox;
+                }
+                ui.close_menu();
+            }
+        });
+    }
+
+    fn entry(
+        app: &mut Hoot,
+        ui: &mut Ui,
+        mailbox: &Mailbox,
+        name: &str,
+        unread: usize,
+    ) -> Response {
+        let text = if unread > 0 {
+            format!("{} ({})", name, unread)
+        } else {
+            name.to_string()
+        };
+        let current = app.page == Page::Inbox && app.state.inbox.mailbox == *mailbox;
+        let response = ui.selectable_label(current, text);
+        if response.clicked() {
+            Self::open(app, mailbox.clone());
+        }
+
+        response
+    }
+
+    fn open(app: &mut Hoot, mailbox: Mailbox) {
+        app.state.inbox.mailbox = mailbox;
+        app.state.inbox.selected.clear();
+        app.page = Page::Inbox;
+    }
+
+    /// Applies `action` to whatever gets dropped on `response`.
+    fn drop_target(app: &mut Hoot, response: &Response, action: Action) {
+        if response.dnd_hover_payload::<DraggedMessages>().is_some() {
+            response.ctx.highlight_widget(response.id);
+        }
+        if let Some(dragged) = response.dnd_release_payload::<DraggedMessages>() {
+            InboxTable::apply(app, &dragged.0, action);
+        }
+    }
+
+    /// New folders and labels go to every account, so they're there whichever one mail
+    /// arrives for.
+    fn add(app: &mut Hoot, kind: MailboxKind, name: &str) {
+        for keys in &app.account_manager.loaded_keys {
+            if let Err(e) = app
+                .message_states
+                .add_mailbox(&app.local_store, keys, kind, name)
+            {
+                error!("could not save folders: {}", e);
+                continue;
+            }
+            app.message_states
+                .publish(&mut app.relays, keys, Sync::Folders, &[]);
+        }
+    }
+
+    fn remove(app: &mut Hoot, kind: MailboxKind, name: &str) {
+        for keys in &app.account_manager.loaded_keys {
+            let refiled = match app
+                .message_states
+                .remove_mailbox(&app.local_store, keys, kind, name)
+            {
+                Ok(refiled) => refiled,
+                Err(e) => {
+                    error!("could not save folders: {}", e);
+                    continue;
+                }
+            };
+            app.message_states
+                .publish(&mut app.relays, keys, Sync::Folders, &refiled);
+        }
+        if kind == MailboxKind::Label && app.state.inbox.label.as_deref() == Some(name) {
+            app.state.inbox.label = None;
+        }
+    }
+}
@@ -0,0 +1,388 @@
+use eframe::egui::{self, Color32, RichText, Ui};
+use nostr::EventId;
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use std::collections::HashMap;
+
+/// Link schemes we're willing to hand to the OS.
+const OPENABLE_SCHEMES: [&str; 3] = ["https://", "http://", "mailto:"];
+/// Parsed message bodies kept around; past this they're parsed again as they're opened.
+const MAX_PARSED: usize = 64;
+
+#[derive(Default)]
+pub struct MarkdownState {
+    /// Shows message bodies as their raw markdown instead of rendering them.
+    pub show_source: bool,
+    /// Link the user clicked, waiting for them to confirm before it gets opened.
+    pub pending_link: Option<String>,
+    // message bodies by the message they're from, so they aren't parsed every frame
+    parsed: HashMap<EventId, Vec<Block>>,
+    // the compose preview, alongside the text it was parsed from
+    preview: Option<(String, Vec<Block>)>,
+}
+
+#[derive(Default, Clone)]
+struct Span {
+    text: String,
+    strong: bool,
+    emphasis: bool,
+    strikethrough: bool,
+    code: bool,
+    link: Option<String>,
+}
+
+enum Inline {
+    Span(Span),
+    Break,
+}
+
+enum Block {
+    Heading(HeadingLevel, Vec<Inline>),
+    Paragraph(Vec<Inline>),
+    Code(String),
+    Quote(Vec<Block>),
+    List(Option<u64>, Vec<Vec<Block>>),
+    Rule,
+}
+
+enum Container {
+    Quote(Vec<Block>),
+    List(Option<u64>, Vec<Vec<Block>>),
+    Item(Vec<Block>),
+}
+
+/// Turns the flat event stream from pulldown-cmark into a tree of blocks we can lay out.
+#[derive(Default)]
+struct Builder {
+    root: Vec<Block>,
+    containers: Vec<Container>,
+    // the paragraph or heading currently collecting inline content
+    inline: Option<(Option<HeadingLevel>, Vec<Inline>)>,
+    code: Option<String>,
+    strong: usize,
+    emphasis: usize,
+    strikethrough: usize,
+    links: Vec<String>,
+}
+
+impl Builder {
+    fn push_block(&mut self, block: Block) {
+        let blocks = match self.containers.last_mut() {
+            Some(Container::Quote(blocks)) | Some(Container::Item(blocks)) => blocks,
+            // blocks never sit directly inside a list, only inside its items
+            Some(Container::List(..)) | None => &mut self.root,
+        };
+        blocks.push(block);
+    }
+
+    fn close_inline(&mut self) {
+        if let Some((level, inlines)) = self.inline.take() {
+            let block = match level {
+                Some(level) => Block::Heading(level, inlines),
+                None => Block::Paragraph(inlines),
+            };
+            self.push_block(block);
+        }
+    }
+
+    fn push_text(&mut self, text: &str, code: bool) {
+        if let Some(block) = self.code.as_mut() {
+            block.push_str(text);
+            return;
+        }
+
+        let span = Span {
+            text: text.to_string(),
+            strong: self.strong > 0,
+            emphasis: self.emphasis > 0,
+            strikethrough: self.strikethrough > 0,
+            code,
+            link: self.links.last().cloned(),
+        };
+        // tight list items have their text without a paragraph around it
+        self.inline
+            .get_or_insert_with(|| (None, Vec::new()))
+            .1
+            .push(Inline::Span(span));
+    }
+
+    fn push_break(&mut self) {
+        if let Some((_, inlines)) = self.inline.as_mut() {
+            inlines.push(Inline::Break);
+        }
+    }
+
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => match tag {
+                Tag::Paragraph => {
+                    self.close_inline();
+                    self.inline = Some((None, Vec::new()));
+                }
+                Tag::Heading { level, .. } => {
+                    self.close_inline();
+                    self.inline = Some((Some(level), Vec::new()));
+                }
+                Tag::BlockQuote(_) => {
+                    self.close_inline();
+                    self.containers.push(Container::Quote(Vec::new()));
+                }
+                Tag::CodeBlock(_) => {
+                    self.close_inline();
+                    self.code = Some(String::new());
+                }
+                Tag::List(start) => {
+                    self.close_inline();
+                    self.containers.push(Container::List(start, Vec::new()));
+                }
+                Tag::Item => {
+                    self.close_inline();
+                    self.containers.push(Container::Item(Vec::new()));
+                }
+                Tag::Emphasis => self.emphasis += 1,
+                Tag::Strong => self.strong += 1,
+                Tag::Strikethrough => self.strikethrough += 1,
+                Tag::Link { dest_url, .. } => self.links.push(dest_url.to_string()),
+                Tag::Image { dest_url, .. } => {
+                    // remote images would tell the sender we opened the mail, so they're only
+                    // ever shown as links
+                    self.links.push(dest_url.to_string());
+                    self.push_text("🖼 ", false);
+                }
+                _ => {}
+            },
+            Event::End(tag) => match tag {
+                TagEnd::Paragraph | TagEnd::Heading(_) => self.close_inline(),
+                TagEnd::BlockQuote => {
+                    self.close_inline();
+                    if let Some(Container::Quote(blocks)) = self.containers.pop() {
+                        self.push_block(Block::Quote(blocks));
+                    }
+                }
+                TagEnd::CodeBlock => {
+                    if let Some(code) = self.code.take() {
+                        self.push_block(Block::Code(code.trim_end().to_string()));
+                    }
+                }
+                TagEnd::List(_) => {
+                    self.close_inline();
+                    if let Some(Container::List(start, items)) = self.containers.pop() {
+                        self.push_block(Block::List(start, items));
+                    }
+                }
+                TagEnd::Item => {
+                    self.close_inline();
+                    if let Some(Container::Item(blocks)) = self.containers.pop() {
+                        if let Some(Container::List(_, items)) = self.containers.last_mut() {
+                            items.push(blocks);
+                        }
+                    }
+                }
+                TagEnd::Emphasis => self.emphasis = self.emphasis.saturating_sub(1),
+                TagEnd::Strong => self.strong = self.strong.saturating_sub(1),
+                TagEnd::Strikethrough => self.strikethrough = self.strikethrough.saturating_sub(1),
+                TagEnd::Link | TagEnd::Image => {
+                    self.links.pop();
+                }
+                _ => {}
+            },
+            Event::Text(text) => self.push_text(&text, false),
+            Event::Code(text) => self.push_text(&text, true),
+            // html is never interpreted, just shown as written
+            Event::Html(text) | Event::InlineHtml(text) => self.push_text(&text, false),
+            Event::SoftBreak => self.push_text(" ", false),
+            Event::HardBreak => self.push_break(),
+            Event::Rule => {
+                self.close_inline();
+                self.push_block(Block::Rule);
+            }
+            Event::TaskListMarker(done) => self.push_text(if done { "☑ " } else { "☐ " }, false),
+            _ => {}
+        }
+    }
+
+    fn finish(mut self) -> Vec<Block> {
+        self.close_inline();
+        self.root
+    }
+}
+
+fn parse(source: &str) -> Vec<Block> {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_TASKLISTS);
+
+    let mut builder = Builder::default();
+    for event in Parser::new_ext(source, options) {
+        builder.event(event);
+    }
+
+    builder.finish()
+}
+
+pub struct MarkdownView {}
+
+impl MarkdownView {
+    /// Renders `source`, the body of the message `id`, as markdown. Clicked links are only
+    /// recorded in `state`, see [`MarkdownView::link_confirmation`].
+    pub fn ui(ui: &mut Ui, state: &mut MarkdownState, id: EventId, source: &str) {
+        if !state.parsed.contains_key(&id) && state.parsed.len() >= MAX_PARSED {
+            state.parsed.clear();
+        }
+        let blocks = state.parsed.entry(id).or_insert_with(|| parse(source));
+        Self::blocks(ui, &mut state.pending_link, blocks);
+    }
+
+    /// Renders `source` as markdown while it's being written, parsing it again only when it
+    /// changes.
+    pub fn preview(ui: &mut Ui, state: &mut MarkdownState, source: &str) {
+        if state
+            .preview
+            .as_ref()
+            .is_none_or(|(parsed, _)| parsed != source)
+        {
+            state.preview = Some((source.to_string(), parse(source)));
+        }
+        if let Some((_, blocks)) = &state.preview {
+            Self::blocks(ui, &mut state.pending_link, blocks);
+        }
+    }
+
+    fn blocks(ui: &mut Ui, pending_link: &mut Option<String>, blocks: &[Block]) {
+        for (index, block) in blocks.iter().enumerate() {
+            match block {
+                Block::Heading(level, inlines) => {
+                    let size = match level {
+                        HeadingLevel::H1 => 24.0,
+                        HeadingLevel::H2 => 20.0,
+                        HeadingLevel::H3 => 17.0,
+                        _ => 15.0,
+                    };
+                    Self::inlines(ui, pending_link, inlines, Some(size));
+                }
+                Block::Paragraph(inlines) => Self::inlines(ui, pending_link, inlines, None),
+                Block::Code(code) => {
+                    egui::Frame::group(ui.style()).show(ui, |ui| {
+                        ui.add(egui::Label::new(RichText::new(code).monospace()));
+                    });
+                }
+                Block::Quote(blocks) => {
+                    let response = ui.indent(ui.id().with(index), |ui| {
+                        Self::blocks(ui, pending_link, blocks)
+                    });
+                    let rect = response.response.rect;
+                    ui.painter().vline(
+                        rect.left(),
+                        rect.y_range(),
+                        ui.visuals().widgets.noninteractive.bg_stroke,
+                    );
+                }
+                Block::List(start, items) => {
+                    for (index, item) in items.iter().enumerate() {
+                        let marker = match start {
+                            Some(n) => format!("{}.", n + index as u64),
+                            None => "•".to_string(),
+                        };
+                        ui.horizontal_top(|ui| {
+                            ui.label(marker);
+                            ui.vertical(|ui| Self::blocks(ui, pending_link, item));
+                        });
+                    }
+                }
+                Block::Rule => {
+                    ui.separator();
+                }
+            }
+        }
+    }
+
+    fn inlines(
+        ui: &mut Ui,
+        pending_link: &mut Option<String>,
+        inlines: &[Inline],
+        size: Option<f32>,
+    ) {
+        ui.horizontal_wrapped(|ui| {
+            ui.spacing_mut().item_spacing.x = 0.0;
+            for inline in inlines {
+                let span = match inline {
+                    Inline::Span(span) => span,
+                    Inline::Break => {
+                        ui.end_row();
+                        continue;
+                    }
+                };
+
+                let mut text = RichText::new(&span.text);
+                if let Some(size) = size {
+                    text = text.size(size).strong();
+                }
+                if span.strong {
+                    text = text.strong();
+                }
+                if span.emphasis {
+                    text = text.italics();
+                }
+                if span.strikethrough {
+                    text = text.strikethrough();
+                }
+                if span.code {
+                    text = text.code();
+                }
+
+                match &span.link {
+                    Some(url) => {
+                        if ui.link(text).on_hover_text(url).clicked() {
+                            *pending_link = Some(url.clone());
+                        }
+                    }
+                    None => {
+                        ui.label(text);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Asks before opening the link the user clicked, showing where it really goes.
+    pub fn link_confirmation(ctx: &egui::Context, state: &mut MarkdownState) {
+        let Some(url) = state.pending_link.clone() else {
+            return;
+        };
+
+        let openable = OPENABLE_SCHEMES
+            .iter()
+            .any(|scheme| url.to_ascii_lowercase().starts_with(scheme));
+        let mut close = false;
+        egui::Window::new("Open link?")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label("This link goes to:");
+                ui.add(egui::Label::new(RichText::new(&url).monospace()).wrap(true));
+                if !openable {
+                    ui.colored_label(Color32::RED, "Hoot won't open this kind of link.");
+                }
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(openable, egui::Button::new("Open"))
+                        .clicked()
+                    {
+                        ctx.open_url(egui::OpenUrl::new_tab(&url));
+                        close = true;
+                    }
+                    if ui.button("Copy").clicked() {
+                        ctx.copy_text(url.clone());
+                        close = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        close = true;
+                    }
+                });
+            });
+
+        if close {
+            state.pending_link = None;
+        }
+    }
+}
@@ -1,7 +1,17 @@
 use eframe::egui;
 
+pub mod attachments;
 pub mod compose_window;
+pub mod composer;
+pub mod contacts;
+pub mod drafts;
+pub mod inbox;
+pub mod mailboxes;
+pub mod markdown;
+pub mod notifications;
 pub mod onboarding;
+pub mod profile;
+pub mod search;
 pub mod settings;
 
 pub trait View {
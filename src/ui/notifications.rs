@@ -0,0 +1,79 @@
+use crate::message_state::Action;
+use crate::ui::inbox::InboxTable;
+use crate::{Hoot, Page};
+use eframe::egui::{self, Align2, Vec2};
+use nostr::EventId;
+use std::collections::HashSet;
+
+/// New mail a rule asked to be told about.
+pub struct Notification {
+    pub id: EventId,
+    pub sender_name: String,
+    pub subject: String,
+}
+
+#[derive(Default)]
+pub struct NotificationsState {
+    pub pending: Vec<Notification>,
+    // how many of `pending` the window manager has been asked to flag already
+    announced: usize,
+}
+
+/// Notifications in the bottom right corner, until they're opened or dismissed.
+pub struct Notifications {}
+
+impl Notifications {
+    pub fn ui(app: &mut Hoot, ctx: &egui::Context) {
+        let state = &mut app.state.notifications;
+        if state.pending.len() > state.announced {
+            ctx.send_viewport_cmd(egui::ViewportCommand::RequestUserAttention(
+                egui::UserAttentionType::Informational,
+            ));
+        }
+        state.announced = state.pending.len();
+        if state.pending.is_empty() {
+            return;
+        }
+
+        let mut opened = None;
+        let mut dismissed = None;
+        egui::Window::new("New Mail")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(Align2::RIGHT_BOTTOM, Vec2::new(-10.0, -10.0))
+            .show(ctx, |ui| {
+                for (index, notification) in state.pending.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.vertical(|ui| {
+                            ui.strong(&notification.sender_name);
+                            ui.label(&notification.subject);
+                        });
+                        if ui.button("Open").clicked() {
+                            opened = Some(index);
+                        }
+                        if ui.small_button("✕").clicked() {
+                            dismissed = Some(index);
+                        }
+                    });
+                }
+                if state.pending.len() > 1 && ui.button("Dismiss All").clicked() {
+                    state.pending.clear();
+                }
+            });
+
+        if let Some(index) = dismissed {
+            state.pending.remove(index);
+        }
+        if let Some(index) = opened {
+            let notification = state.pending.remove(index);
+            InboxTable::apply(
+                app,
+                &HashSet::from([notification.id]),
+                Action::MarkRead(true),
+            );
+            app.focused_post = notification.id.to_string();
+            app.page = Page::Post;
+        }
+        app.state.notifications.announced = app.state.notifications.pending.len();
+    }
+}
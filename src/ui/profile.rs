@@ -0,0 +1,371 @@
+use crate::error::Result;
+use crate::http;
+use crate::nip05::Nip05Cache;
+use crate::profile::ProfileCache;
+use crate::relay::PublishStatus;
+use crate::task::Task;
+use crate::ui::attachments;
+use crate::Hoot;
+use eframe::egui::{self, Color32, Sense, Ui, Vec2};
+use nostr::{EventBuilder, EventId, JsonUtil, Metadata, PublicKey, ToBech32};
+use std::collections::HashMap;
+use tracing::{debug, error};
+
+/// Pictures are never shown bigger than this, so that's all we keep of them.
+const AVATAR_SIDE: u32 = 64;
+/// How many pictures we hold on to before starting over.
+const MAX_AVATARS: usize = 512;
+
+enum Avatar {
+    Loading(Task<Result<egui::ColorImage>>),
+    Ready(egui::TextureHandle),
+    Failed,
+}
+
+/// Profile pictures, keyed by URL. Fetching one tells its host our IP address, so strangers'
+/// pictures are only fetched when asked for.
+#[derive(Default)]
+pub struct AvatarCache {
+    avatars: HashMap<String, Avatar>,
+}
+
+impl AvatarCache {
+    fn fetch(&mut self, ctx: &egui::Context, url: &str) {
+        if self.avatars.contains_key(url) {
+            return;
+        }
+        if self.avatars.len() >= MAX_AVATARS {
+            self.avatars
+                .retain(|_, avatar| matches!(avatar, Avatar::Loading(_)));
+        }
+
+        let to_fetch = url.to_string();
+        let task = Task::spawn(ctx.clone(), move || {
+            let data = http::media_client().get(&to_fetch)?;
+            let image = attachments::decode_untrusted(&data)?;
+            Ok(attachments::to_color_image(
+                &image.thumbnail(AVATAR_SIDE, AVATAR_SIDE),
+            ))
+        });
+        self.avatars.insert(url.to_string(), Avatar::Loading(task));
+    }
+
+    fn texture(&mut self, ctx: &egui::Context, url: &str) -> Option<&egui::TextureHandle> {
+        let finished = match self.avatars.get(url) {
+            Some(Avatar::Loading(task)) => task.take(),
+            _ => None,
+        };
+        if let Some(result) = finished {
+            let avatar = match result {
+                Ok(image) => Avatar::Ready(ctx.load_texture(
+                    format!("avatar-{}", url),
+                    image,
+                    egui::TextureOptions::default(),
+                )),
+                Err(e) => {
+                    debug!("could not load picture {}: {}", url, e);
+                    Avatar::Failed
+                }
+            };
+            self.avatars.insert(url.to_string(), avatar);
+        }
+
+        match self.avatars.get(url) {
+            Some(Avatar::Ready(texture)) => Some(texture),
+            _ => None,
+        }
+    }
+}
+
+pub struct ProfileView {}
+
+impl ProfileView {
+    /// Avatar, name and NIP-05 badge of `pubkey` on one line. `known` is whether their
+    /// picture may be fetched without asking.
+    #[allow(clippy::too_many_arguments)]
+    pub fn ui(
+        ui: &mut Ui,
+        ndb: &nostrdb::Ndb,
+        profiles: &mut ProfileCache,
+        nip05: &mut Nip05Cache,
+        avatars: &mut AvatarCache,
+        pubkey: &PublicKey,
+        known: bool,
+    ) {
+        let name = profiles.display_name(ndb, pubkey);
+        let profile = profiles.get(ndb, pubkey).cloned().unwrap_or_default();
+
+        ui.horizontal(|ui| {
+            Self::avatar(ui, avatars, pubkey, profile.picture.as_deref(), 20.0, known);
+            ui.label(name);
+            if let Some(identifier) = &profile.nip05 {
+                Self::nip05_badge(ui, nip05, pubkey, identifier);
+            }
+        });
+    }
+
+    /// Someone's profile picture, or a dot in a color picked from their key until there is
+    /// one. Pictures of people we don't `known` are only fetched once the dot is clicked.
+    pub fn avatar(
+        ui: &mut Ui,
+        avatars: &mut AvatarCache,
+        pubkey: &PublicKey,
+        picture: Option<&str>,
+        size: f32,
+        known: bool,
+    ) {
+        let size = Vec2::splat(size);
+        let url = picture.filter(|url| url.starts_with("https://"));
+        if let Some(url) = url.filter(|_| known) {
+            avatars.fetch(ui.ctx(), url);
+        }
+
+        let ctx = ui.ctx().clone();
+        if let Some(texture) = url.and_then(|url| avatars.texture(&ctx, url)) {
+            ui.add(
+                egui::Image::new(egui::load::SizedTexture::from_handle(texture))
+                    .fit_to_exact_size(size)
+                    .rounding(size.x / 2.0),
+            );
+            return;
+        }
+
+        let unasked = url.filter(|url| !avatars.avatars.contains_key(*url));
+        let sense = if unasked.is_some() {
+            Sense::click()
+        } else {
+            Sense::hover()
+        };
+        let (rect, response) = ui.allocate_exact_size(size, sense);
+        let bytes = pubkey.to_bytes();
+        let color = Color32::from_rgb(bytes[0], bytes[1], bytes[2]);
+        ui.painter()
+            .circle_filled(rect.center(), size.x / 2.0, color);
+
+        if let Some(url) = unasked {
+            if response
+                .on_hover_text("Click to load their picture")
+                .clicked()
+            {
+                avatars.fetch(&ctx, url);
+            }
+        }
+    }
+
+    /// A check mark with the identifier, shown only once it's been verified to point at
+    /// `pubkey`.
+    pub fn nip05_badge(ui: &mut Ui, nip05: &mut Nip05Cache, pubkey: &PublicKey, identifier: &str) {
+        if !nip05.verifies(ui.ctx(), identifier, pubkey) {
+            return;
+        }
+
+        // `_@domain` is how a domain names its owner, so just show the domain
+        let shown = identifier.strip_prefix("_@").unwrap_or(identifier);
+        ui.colored_label(Color32::DARK_GREEN, format!("✔ {}", shown))
+            .on_hover_text("Verified NIP-05 identifier");
+    }
+}
+
+/// The profile being edited under Settings → My Profile.
+#[derive(Default)]
+pub struct ProfileEditorState {
+    pub account: Option<PublicKey>,
+    // account the fields below were filled in from
+    loaded_for: Option<PublicKey>,
+    name: String,
+    display_name: String,
+    about: String,
+    picture: String,
+    nip05: String,
+    lud16: String,
+    published: Option<EventId>,
+    error: Option<String>,
+}
+
+impl ProfileEditorState {
+    fn load(&mut self, pubkey: PublicKey, metadata: &Metadata) {
+        let field = |value: &Option<String>| value.clone().unwrap_or_default();
+        self.name = field(&metadata.name);
+        self.display_name = field(&metadata.display_name);
+        self.about = field(&metadata.about);
+        self.picture = field(&metadata.picture);
+        self.nip05 = field(&metadata.nip05);
+        self.lud16 = field(&metadata.lud16);
+        self.loaded_for = Some(pubkey);
+        self.published = None;
+        self.error = None;
+    }
+
+    /// `base` with the edited fields written over it, so fields we don't edit survive.
+    fn apply(&self, mut base: Metadata) -> Metadata {
+        let field = |value: &str| {
+            let value = value.trim();
+            (!value.is_empty()).then(|| value.to_string())
+        };
+        base.name = field(&self.name);
+        base.display_name = field(&self.display_name);
+        base.about = field(&self.about);
+        base.picture = field(&self.picture);
+        base.nip05 = field(&self.nip05);
+        base.lud16 = field(&self.lud16);
+
+        base
+    }
+}
+
+pub struct ProfileEditor {}
+
+impl ProfileEditor {
+    pub fn ui(app: &mut Hoot, ui: &mut Ui) {
+        let accounts = app.account_manager.loaded_keys.clone();
+        if accounts.is_empty() {
+            ui.label("Add a key to set up a profile.");
+            return;
+        }
+        let keys = app
+            .state
+            .settings
+            .profile
+            .account
+            .and_then(|pk| accounts.iter().find(|k| k.public_key() == pk))
+            .unwrap_or(&accounts[0])
+            .clone();
+        let pubkey = keys.public_key();
+
+        egui::ComboBox::from_label("Account")
+            .selected_text(pubkey.to_bech32().unwrap())
+            .show_ui(ui, |ui| {
+                for keys in &accounts {
+                    ui.selectable_value(
+                        &mut app.state.settings.profile.account,
+                        Some(keys.public_key()),
+                        keys.public_key().to_bech32().unwrap(),
+                    );
+                }
+            });
+
+        // what we know about this account from relays, with every field it has
+        let current = app
+            .profiles
+            .metadata(&app.ndb, &pubkey)
+            .cloned()
+            .unwrap_or_default();
+
+        let state = &mut app.state.settings.profile;
+        if state.loaded_for != Some(pubkey) {
+            state.load(pubkey, &current);
+        }
+
+        egui::Grid::new("profile_editor")
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("Name:");
+                ui.text_edit_singleline(&mut state.name);
+                ui.end_row();
+                ui.label("Display name:");
+                ui.text_edit_singleline(&mut state.display_name);
+                ui.end_row();
+                ui.label("About:");
+                ui.text_edit_multiline(&mut state.about);
+                ui.end_row();
+                ui.label("Picture URL:");
+                ui.text_edit_singleline(&mut state.picture);
+                ui.end_row();
+                ui.label("NIP-05:");
+                ui.text_edit_singleline(&mut state.nip05);
+                ui.end_row();
+                ui.label("Lightning address:");
+                ui.text_edit_singleline(&mut state.lud16);
+                ui.end_row();
+            });
+
+        ui.add_space(10.0);
+        ui.label("Preview:");
+        ui.group(|ui| {
+            ui.horizontal(|ui| {
+                let picture = Some(state.picture.trim()).filter(|p| !p.is_empty());
+                ProfileView::avatar(ui, &mut app.avatars, &pubkey, picture, 48.0, true);
+                ui.vertical(|ui| {
+                    let name = state.name.trim();
+                    let display_name = state.display_name.trim();
+                    ui.strong(if display_name.is_empty() {
+                        name
+                    } else {
+                        display_name
+                    });
+                    if !name.is_empty() {
+                        ui.small(format!("@{}", name));
+                    }
+
+                    let nip05 = state.nip05.trim();
+                    if current.nip05.as_deref() == Some(nip05) {
+                        ProfileView::nip05_badge(ui, &mut app.nip05, &pubkey, nip05);
+                    } else if !nip05.is_empty() {
+                        ui.small(format!("{} (not verified yet)", nip05));
+                    }
+                });
+            });
+            if !state.about.trim().is_empty() {
+                ui.label(state.about.trim());
+            }
+            if !state.lud16.trim().is_empty() {
+                ui.small(format!("⚡ {}", state.lud16.trim()));
+            }
+        });
+
+        ui.horizontal(|ui| {
+            if ui.button("Publish").clicked() {
+                let metadata = state.apply(current.clone());
+                match EventBuilder::metadata(&metadata).sign_with_keys(&keys) {
+                    Ok(event) => {
+                        if let Err(e) = app.relays.publish(&event) {
+                            error!("could not publish profile: {}", e);
+                        }
+                        // show it right away instead of waiting for a relay to echo it back
+                        app.profiles.process_event(&event);
+                        let message = format!("[\"EVENT\",\"hoot\",{}]", event.as_json());
+                        if let Err(e) = app.ndb.process_event(&message) {
+                            error!("could not store profile: {}", e);
+                        }
+                        state.published = Some(event.id);
+                        state.error = None;
+                    }
+                    Err(e) => state.error = Some(e.to_string()),
+                }
+            }
+            if ui.button("Revert").clicked() {
+                state.load(pubkey, &current);
+            }
+        });
+
+        if let Some(e) = &state.error {
+            ui.colored_label(Color32::RED, format!("Could not sign profile: {}", e));
+        }
+
+        if let Some(event_id) = &state.published {
+            match app.relays.publish_status(event_id) {
+                Some(statuses) if !statuses.is_empty() => {
+                    for (url, status) in statuses {
+                        ui.horizontal(|ui| {
+                            ui.label(url);
+                            match status {
+                                PublishStatus::Pending => {
+                                    ui.spinner();
+                                }
+                                PublishStatus::Accepted => {
+                                    ui.colored_label(Color32::DARK_GREEN, "✔ Saved");
+                                }
+                                PublishStatus::Rejected(reason) => {
+                                    ui.colored_label(Color32::RED, format!("✘ {}", reason));
+                                }
+                            }
+                        });
+                    }
+                }
+                _ => {
+                    ui.colored_label(Color32::RED, "Not connected to any relay.");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use crate::search::{Query, SavedSearch};
+use crate::ui::inbox::Mailbox;
+use crate::{Hoot, Page};
+use eframe::egui::{self, Color32, Ui};
+
+const HELP: &str = "Words match the start of any word in the subject, body, sender or \
+attachment names. \"Quoted phrases\" have to match as written.\n\n\
+from:alice  sent by someone whose name or npub matches\n\
+to:bob  sent to someone whose name or npub matches\n\
+subject:invoice  subject contains this\n\
+has:attachment  has at least one attachment\n\
+before:2024-06-01  after:2024-01-01  sent before or on/after a day";
+
+#[derive(Default)]
+pub struct SearchBarState {
+    // name for saving the current search under
+    name: String,
+}
+
+/// The search box in the top panel. Results show up in the inbox table as you type.
+pub struct SearchBar {}
+
+impl SearchBar {
+    pub fn ui(app: &mut Hoot, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.label("🔍");
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut app.state.inbox.search)
+                    .hint_text("Search mail, e.g. from:alice has:attachment after:2024-01-01")
+                    .desired_width(400.0),
+            );
+            if response.changed() {
+                app.page = Page::Inbox;
+                app.state.inbox.selected.clear();
+            }
+            if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                app.state.inbox.search.clear();
+            }
+
+            if !app.state.inbox.search.is_empty() && ui.small_button("✕").clicked() {
+                app.state.inbox.search.clear();
+            }
+            ui.label("❓").on_hover_text(HELP);
+
+            if !app.state.inbox.search.trim().is_empty() {
+                ui.menu_button("💾 Save Search", |ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut app.state.search.name)
+                            .hint_text("Folder name"),
+                    );
+                    let name = app.state.search.name.trim().to_string();
+                    if ui
+                        .add_enabled(!name.is_empty(), egui::Button::new("Save"))
+                        .clicked()
+                    {
+                        let query = app.state.inbox.search.trim().to_string();
+                        Self::save(app, name, query);
+                        ui.close_menu();
+                    }
+                });
+            }
+
+            for problem in Query::parse(&app.state.inbox.search).problems {
+                ui.colored_label(Color32::RED, problem);
+            }
+        });
+    }
+
+    /// Saves `query` as a folder called `name`, replacing one with the same name, and opens
+    /// it in place of the search.
+    fn save(app: &mut Hoot, name: String, query: String) {
+        let searches = &mut app.private.saved_searches;
+        searches.retain(|s| s.name != name);
+        searches.push(SavedSearch {
+            name: name.clone(),
+            query,
+        });
+        app.saved_searches.refresh(&app.search, searches);
+
+        app.state.search.name.clear();
+        app.state.inbox.search.clear();
+        app.state.inbox.mailbox = Mailbox::Saved(name);
+        app.state.inbox.selected.clear();
+        app.page = Page::Inbox;
+    }
+
+    pub fn remove(app: &mut Hoot, name: &str) {
+        app.private.saved_searches.retain(|s| s.name != name);
+        app.saved_searches
+            .refresh(&app.search, &app.private.saved_searches);
+        if app.state.inbox.mailbox == Mailbox::Saved(name.to_string()) {
+            app.state.inbox.mailbox = Mailbox::Inbox;
+        }
+    }
+}
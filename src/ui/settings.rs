@@ -1,3 +1,4 @@
+use crate::rules::{Condition, Rule, RuleAction};
 use crate::Hoot;
 use eframe::egui::{self, Color32, Direction, Layout, Sense, Ui, Vec2};
 use egui_tabs::Tabs;
@@ -6,12 +7,18 @@ use tracing::error;
 #[derive(Default)]
 pub struct SettingsState {
     pub new_relay_url: String,
+    pub profile: crate::ui::profile::ProfileEditorState,
+    // the rule last previewed, and what it matched
+    rule_preview: Option<(usize, Vec<String>)>,
 }
 
 enum Tab {
     Profile = 0,
     Relays = 1,
     Identity = 2,
+    Templates = 3,
+    Mail = 4,
+    Rules = 5,
 }
 
 impl From<i32> for Tab {
@@ -20,6 +27,9 @@ impl From<i32> for Tab {
             0 => Tab::Profile,
             1 => Tab::Relays,
             2 => Tab::Identity,
+            3 => Tab::Templates,
+            4 => Tab::Mail,
+            5 => Tab::Rules,
             _ => Tab::Profile, // Default to Profile for invalid values
         }
     }
@@ -35,7 +45,7 @@ pub struct SettingsScreen {}
 
 impl SettingsScreen {
     pub fn ui(app: &mut Hoot, ui: &mut Ui) {
-        let tabs_response = Tabs::new(3)
+        let tabs_response = Tabs::new(6)
             .height(16.0)
             .selected(0)
             .layout(Layout::centered_and_justified(Direction::TopDown))
@@ -46,6 +56,9 @@ impl SettingsScreen {
                     Profile => "My Profile",
                     Relays => "Relays",
                     Identity => "Keys",
+                    Templates => "Templates",
+                    Mail => "Mail",
+                    Rules => "Rules",
                 };
                 ui.add(egui::Label::new(tab_label).selectable(false));
             });
@@ -56,11 +69,16 @@ impl SettingsScreen {
             Profile => Self::profile(app, ui),
             Relays => Self::relays(app, ui),
             Identity => Self::identity(app, ui),
+            Templates => Self::templates(app, ui),
+            Mail => Self::mail(app, ui),
+            Rules => Self::rules(app, ui),
         }
     }
 
     fn profile(app: &mut Hoot, ui: &mut Ui) {
-        ui.label("Your profile.");
+        ui.heading("My Profile");
+        ui.small("This is what people see when you send them mail. It's public.");
+        crate::ui::profile::ProfileEditor::ui(app, ui);
     }
 
     fn relays(app: &mut Hoot, ui: &mut Ui) {
@@ -121,6 +139,13 @@ impl SettingsScreen {
                 app.relays.remove_url(&relay_to_remove.unwrap());
             }
         });
+
+        ui.add_space(10.0);
+
+        ui.heading("Attachment Server");
+        ui.small("Attachments are encrypted, then uploaded to this Blossom server.");
+        ui.small("Mail with attachments can't be sent until one is set.");
+        ui.text_edit_singleline(&mut app.preferences.blob_server);
     }
 
     fn identity(app: &mut Hoot, ui: &mut Ui) {
@@ -136,7 +161,345 @@ impl SettingsScreen {
                         }
                     }
                 });
+
+                ui.label("Signature:");
+                let signature = app
+                    .preferences
+                    .signatures
+                    .entry(key.public_key().to_hex())
+                    .or_default();
+                ui.text_edit_multiline(signature);
+                ui.separator();
+            }
+        });
+    }
+
+    fn templates(app: &mut Hoot, ui: &mut Ui) {
+        ui.heading("Templates");
+        ui.small("Canned responses you can insert while writing a message.");
+        ui.horizontal_wrapped(|ui| {
+            ui.small("Placeholders:");
+            for (placeholder, description) in crate::templates::PLACEHOLDERS {
+                ui.small(egui::RichText::new(placeholder).monospace())
+                    .on_hover_text(description);
             }
         });
+
+        if ui.button("New Template").clicked() {
+            app.private.templates.push(crate::templates::Template {
+                name: "New template".to_string(),
+                ..Default::default()
+            });
+        }
+
+        let mut template_to_remove: Option<usize> = None;
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for (index, template) in app.private.templates.iter_mut().enumerate() {
+                ui.push_id(index, |ui| {
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        ui.label("Name:");
+                        ui.text_edit_singleline(&mut template.name);
+                        if ui.button("Delete").clicked() {
+                            template_to_remove = Some(index);
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Subject:");
+                        ui.text_edit_singleline(&mut template.subject);
+                    });
+                    ui.text_edit_multiline(&mut template.body);
+                });
+            }
+        });
+
+        if let Some(index) = template_to_remove {
+            app.private.templates.remove(index);
+        }
+    }
+
+    fn mail(app: &mut Hoot, ui: &mut Ui) {
+        ui.heading("Trash");
+        ui.horizontal(|ui| {
+            ui.label("Delete messages in the trash after");
+            ui.add(
+                egui::DragValue::new(&mut app.preferences.trash_retention_days)
+                    .clamp_range(0..=3650),
+            );
+            ui.label("days");
+        });
+        ui.small("Set to 0 to keep the trash until you empty it yourself.");
+        ui.small("Relays that support it are asked to delete their copies too.");
+        ui.small(
+            "The encrypted copy stays in the local database, hidden, since it can't remove \
+             single events.",
+        );
+
+        ui.separator();
+        ui.heading("Spam");
+        ui.checkbox(
+            &mut app.preferences.spam_filter,
+            "Move new mail from people you don't know to Spam",
+        );
+        ui.add_enabled_ui(app.preferences.spam_filter, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Trust strangers whose mail carries at least");
+                ui.add(egui::DragValue::new(&mut app.preferences.spam_min_pow).clamp_range(0..=64));
+                ui.label("bits of proof of work");
+            });
+        });
+        ui.small(
+            "Mail from your contacts and the people you follow is never marked as spam. \
+             Marking mail as spam or not spam teaches the filter.",
+        );
+
+        ui.separator();
+        ui.heading("Proof of Work");
+        ui.horizontal(|ui| {
+            ui.label("Put at least");
+            ui.add(egui::DragValue::new(&mut app.preferences.send_pow).clamp_range(0..=32));
+            ui.label("bits of proof of work on mail you send");
+        });
+        ui.checkbox(
+            &mut app.preferences.send_pow_seal,
+            "Also on the seal inside",
+        );
+        ui.small(
+            "Relays that ask for more get more. Each extra bit doubles the time sending takes, \
+             which the compose window lets you cancel.",
+        );
+
+        ui.separator();
+        ui.heading("Blocked Senders");
+        ui.small("Mail from people you block is dropped as it comes in. Block from a message.");
+        let mut unblock = None;
+        for keys in &app.account_manager.loaded_keys {
+            let muted = app.mutes.muted(&keys.public_key());
+            if muted.is_empty() {
+                continue;
+            }
+            ui.label(app.profiles.display_name(&app.ndb, &keys.public_key()));
+            for pubkey in muted {
+                ui.horizontal(|ui| {
+                    ui.add_space(ui.spacing().indent);
+                    ui.label(app.profiles.display_name(&app.ndb, &pubkey));
+                    ui.small(crate::profile::short_npub(&pubkey));
+                    if ui.button("Unblock").clicked() {
+                        unblock = Some((keys.clone(), pubkey));
+                    }
+                });
+            }
+        }
+        if let Some((keys, pubkey)) = unblock {
+            app.set_muted(&keys, &pubkey, false);
+        }
+    }
+
+    fn rules(app: &mut Hoot, ui: &mut Ui) {
+        use nostr::ToBech32;
+        ui.heading("Rules");
+        ui.small(
+            "Rules run on each new message, in order, when all of their conditions hold. \
+             They don't run on mail you already have; Preview shows what they would match.",
+        );
+        ui.small(
+            "Notifications, replies and forwards only happen for mail sent in the last day, \
+             and replies are never sent to automatic mail.",
+        );
+
+        if ui.button("New Rule").clicked() {
+            app.private.rules.push(Rule::default());
+        }
+
+        let accounts: Vec<String> = app
+            .account_manager
+            .loaded_keys
+            .iter()
+            .map(|k| k.public_key().to_bech32().unwrap())
+            .collect();
+        let mut rule_to_remove: Option<usize> = None;
+        let mut rule_to_raise: Option<usize> = None;
+        let mut rule_to_preview: Option<usize> = None;
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for (index, rule) in app.private.rules.iter_mut().enumerate() {
+                ui.push_id(index, |ui| {
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut rule.enabled, "");
+                        ui.text_edit_singleline(&mut rule.name);
+                        if index > 0 && ui.button("⬆").on_hover_text("Run earlier").clicked() {
+                            rule_to_raise = Some(index);
+                        }
+                        if ui.button("Preview").clicked() {
+                            rule_to_preview = Some(index);
+                        }
+                        if ui.button("Delete").clicked() {
+                            rule_to_remove = Some(index);
+                        }
+                    });
+
+                    ui.label("When all of these are true:");
+                    rule.compile();
+                    let problems: Vec<Option<String>> =
+                        rule.conditions.iter().map(|c| rule.problem(c)).collect();
+                    let mut condition_to_remove: Option<usize> = None;
+                    for (i, condition) in rule.conditions.iter_mut().enumerate() {
+                        ui.push_id(("condition", i), |ui| {
+                            if Self::condition(ui, condition, problems[i].as_deref(), &accounts)
+                            {
+                                condition_to_remove = Some(i);
+                            }
+                        });
+                    }
+                    if let Some(i) = condition_to_remove {
+                        rule.conditions.remove(i);
+                    }
+                    if ui.small_button("+ Condition").clicked() {
+                        rule.conditions.push(Condition::DEFAULTS[0].clone());
+                    }
+
+                    ui.label("Do these:");
+                    let mut action_to_remove: Option<usize> = None;
+                    for (i, action) in rule.actions.iter_mut().enumerate() {
+                        ui.push_id(("action", i), |ui| {
+                            if Self::action(ui, action) {
+                                action_to_remove = Some(i);
+                            }
+                        });
+                    }
+                    if let Some(i) = action_to_remove {
+                        rule.actions.remove(i);
+                    }
+                    if ui.small_button("+ Action").clicked() {
+                        rule.actions.push(RuleAction::DEFAULTS[0].clone());
+                    }
+
+                    if let Some((_, matched)) = app
+                        .state
+                        .settings
+                        .rule_preview
+                        .as_ref()
+                        .filter(|(previewed, _)| *previewed == index)
+                    {
+                        ui.label(format!(
+                            "Matches {} messages you already have",
+                            matched.len()
+                        ));
+                        for line in matched.iter().take(20) {
+                            ui.small(line);
+                        }
+                        if matched.len() > 20 {
+                            ui.small(format!("and {} more", matched.len() - 20));
+                        }
+                    }
+                });
+            }
+        });
+
+        if let Some(index) = rule_to_preview {
+            let rule = app.private.rules[index].clone();
+            let matched = app.preview_rule(&rule);
+            app.state.settings.rule_preview = Some((index, matched));
+        }
+        if let Some(index) = rule_to_raise {
+            app.private.rules.swap(index, index - 1);
+            app.state.settings.rule_preview = None;
+        }
+        if let Some(index) = rule_to_remove {
+            app.private.rules.remove(index);
+            app.state.settings.rule_preview = None;
+        }
+    }
+
+    /// Edits one rule condition, showing `problem` next to it. Returns true if it should be
+    /// removed.
+    fn condition(
+        ui: &mut Ui,
+        condition: &mut Condition,
+        problem: Option<&str>,
+        accounts: &[String],
+    ) -> bool {
+        let mut remove = false;
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_source("kind")
+                .selected_text(condition.title())
+                .show_ui(ui, |ui| {
+                    for kind in Condition::DEFAULTS {
+                        let selected = kind.title() == condition.title();
+                        if ui.selectable_label(selected, kind.title()).clicked() && !selected {
+                            *condition = kind;
+                        }
+                    }
+                });
+
+            match condition {
+                Condition::Sender(value) => {
+                    ui.add(egui::TextEdit::singleline(value).hint_text("name or npub"));
+                }
+                Condition::Subject(pattern) => {
+                    ui.add(egui::TextEdit::singleline(pattern).hint_text("regular expression"));
+                }
+                Condition::Account(account) => {
+                    egui::ComboBox::from_id_source("account")
+                        .selected_text(account.as_str())
+                        .show_ui(ui, |ui| {
+                            for npub in accounts {
+                                ui.selectable_value(account, npub.clone(), npub);
+                            }
+                        });
+                }
+                Condition::InContacts(wanted) | Condition::HasAttachment(wanted) => {
+                    ui.checkbox(wanted, "");
+                }
+            }
+
+            if let Some(problem) = problem {
+                ui.colored_label(Color32::RED, problem);
+            }
+            if ui.small_button("✕").clicked() {
+                remove = true;
+            }
+        });
+
+        remove
+    }
+
+    /// Edits one rule action. Returns true if it should be removed.
+    fn action(ui: &mut Ui, action: &mut RuleAction) -> bool {
+        let mut remove = false;
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_source("kind")
+                .selected_text(action.title())
+                .show_ui(ui, |ui| {
+                    for kind in RuleAction::DEFAULTS {
+                        let selected = kind.title() == action.title();
+                        if ui.selectable_label(selected, kind.title()).clicked() && !selected {
+                            *action = kind;
+                        }
+                    }
+                });
+
+            match action {
+                RuleAction::Label(name) | RuleAction::MoveTo(name) => {
+                    ui.text_edit_singleline(name);
+                }
+                RuleAction::AutoReply(text) => {
+                    ui.text_edit_multiline(text);
+                }
+                RuleAction::Forward(to) => {
+                    ui.add(egui::TextEdit::singleline(to).hint_text("npub"));
+                }
+                RuleAction::MarkRead | RuleAction::Star | RuleAction::Notify => {}
+            }
+
+            if let Some(problem) = action.problem() {
+                ui.colored_label(Color32::RED, problem);
+            }
+            if ui.small_button("✕").clicked() {
+                remove = true;
+            }
+        });
+
+        remove
     }
 }